@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS audits
+(
+  id          BIGSERIAL PRIMARY KEY,
+  actor       TEXT NOT NULL,
+  operation   TEXT NOT NULL,
+  user_id     BIGINT NOT NULL,
+  name        TEXT NOT NULL,
+  old_value   TEXT NOT NULL,
+  new_value   TEXT NOT NULL,
+  datetime    BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS audits_user_id ON audits (user_id);
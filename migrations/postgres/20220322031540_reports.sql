@@ -0,0 +1,17 @@
+-- Add migration script here
+ALTER TABLE users ADD COLUMN IF NOT EXISTS is_banned BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS reports
+(
+  id          BIGSERIAL PRIMARY KEY,
+  reporter    TEXT NOT NULL,
+  user_id     BIGINT NOT NULL,
+  name        TEXT NOT NULL,
+  reason      TEXT NOT NULL,
+  status      TEXT NOT NULL DEFAULT 'pending',
+  action      TEXT NOT NULL DEFAULT '',
+  datetime    BIGINT NOT NULL,
+  resolved_at BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS reports_status ON reports (status);
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar TEXT NOT NULL DEFAULT '';
+
+CREATE INDEX IF NOT EXISTS users_avatar ON users (avatar);
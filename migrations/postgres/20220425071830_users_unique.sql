@@ -0,0 +1,14 @@
+-- Add migration script here
+-- no padding of names & pids.
+ALTER TABLE users ALTER COLUMN name TYPE VARCHAR(255) USING trim(name);
+ALTER TABLE users ALTER COLUMN pid TYPE VARCHAR(64) USING trim(pid);
+
+-- normalized name (unicode lowercase) set by the domain, same in all backends.
+-- existing users are keyed by the domain after migration, it fails and lists
+-- the names if they conflict, nothing is deleted.
+ALTER TABLE users ADD COLUMN name_key VARCHAR(255) NOT NULL DEFAULT '';
+
+-- names are unique case-insensitive in not deleted users.
+CREATE UNIQUE INDEX IF NOT EXISTS users_name_unique ON users (name_key) WHERE is_deleted = false AND name_key != '';
+CREATE INDEX IF NOT EXISTS users_name ON users (name_key);
+CREATE INDEX IF NOT EXISTS users_pid ON users (pid);
@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE users ADD COLUMN IF NOT EXISTS deleted_at BIGINT NOT NULL DEFAULT 0;
+
+-- deleted time of old rows is unknown, retention starts from now.
+UPDATE users SET deleted_at = EXTRACT(EPOCH FROM now())::BIGINT WHERE is_deleted = true;
+
+CREATE INDEX IF NOT EXISTS users_deleted_at ON users (deleted_at) WHERE is_deleted = true;
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS attributes
+(
+  user_id     BIGINT NOT NULL,
+  key         VARCHAR(64) NOT NULL,
+  value       TEXT NOT NULL,
+  is_public   BOOLEAN NOT NULL,
+  datetime    BIGINT NOT NULL,
+  PRIMARY KEY (user_id, key)
+);
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS dids
+(
+  user_id     BIGINT PRIMARY KEY,
+  document    TEXT NOT NULL,
+  key_id      TEXT NOT NULL,
+  signature   TEXT NOT NULL,
+  sequence    BIGINT NOT NULL,
+  datetime    BIGINT NOT NULL
+);
@@ -0,0 +1,12 @@
+-- Add migration script here
+-- audits are append-only, only restore of a backup replaces them.
+CREATE OR REPLACE FUNCTION audits_append_only() RETURNS TRIGGER AS $$
+BEGIN
+  RAISE EXCEPTION 'audits are append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audits_no_update BEFORE UPDATE OR DELETE ON audits
+  FOR EACH ROW EXECUTE PROCEDURE audits_append_only();
+CREATE TRIGGER audits_no_truncate BEFORE TRUNCATE ON audits
+  FOR EACH STATEMENT EXECUTE PROCEDURE audits_append_only();
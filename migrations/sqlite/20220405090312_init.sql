@@ -0,0 +1,41 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS users
+(
+  id          INTEGER PRIMARY KEY AUTOINCREMENT,
+  name        TEXT NOT NULL,
+  pid         TEXT NOT NULL,
+  bio         TEXT NOT NULL,
+  is_actived  BOOLEAN NOT NULL DEFAULT TRUE,
+  datetime    INTEGER NOT NULL,
+  is_deleted  BOOLEAN NOT NULL DEFAULT FALSE,
+  is_banned   BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE TABLE IF NOT EXISTS audits
+(
+  id          INTEGER PRIMARY KEY AUTOINCREMENT,
+  actor       TEXT NOT NULL,
+  operation   TEXT NOT NULL,
+  user_id     INTEGER NOT NULL,
+  name        TEXT NOT NULL,
+  old_value   TEXT NOT NULL,
+  new_value   TEXT NOT NULL,
+  datetime    INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS audits_user_id ON audits (user_id);
+
+CREATE TABLE IF NOT EXISTS reports
+(
+  id          INTEGER PRIMARY KEY AUTOINCREMENT,
+  reporter    TEXT NOT NULL,
+  user_id     INTEGER NOT NULL,
+  name        TEXT NOT NULL,
+  reason      TEXT NOT NULL,
+  status      TEXT NOT NULL DEFAULT 'pending',
+  action      TEXT NOT NULL DEFAULT '',
+  datetime    INTEGER NOT NULL,
+  resolved_at INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS reports_status ON reports (status);
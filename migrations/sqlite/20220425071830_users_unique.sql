@@ -0,0 +1,10 @@
+-- Add migration script here
+-- normalized name (unicode lowercase) set by the domain, same in all backends.
+-- existing users are keyed by the domain after migration, it fails and lists
+-- the names if they conflict, nothing is deleted.
+ALTER TABLE users ADD COLUMN name_key TEXT NOT NULL DEFAULT '';
+
+-- names are unique case-insensitive in not deleted users.
+CREATE UNIQUE INDEX IF NOT EXISTS users_name_unique ON users (name_key) WHERE is_deleted = false AND name_key != '';
+CREATE INDEX IF NOT EXISTS users_name ON users (name_key);
+CREATE INDEX IF NOT EXISTS users_pid ON users (pid);
@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS versions
+(
+  id          INTEGER PRIMARY KEY AUTOINCREMENT,
+  user_id     INTEGER NOT NULL,
+  bio         TEXT NOT NULL,
+  avatar      TEXT NOT NULL,
+  datetime    INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS versions_user_id ON versions (user_id);
+CREATE INDEX IF NOT EXISTS versions_avatar ON versions (avatar);
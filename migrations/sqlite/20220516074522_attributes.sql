@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS attributes
+(
+  user_id     INTEGER NOT NULL,
+  key         TEXT NOT NULL,
+  value       TEXT NOT NULL,
+  is_public   BOOLEAN NOT NULL,
+  datetime    INTEGER NOT NULL,
+  PRIMARY KEY (user_id, key)
+);
@@ -0,0 +1,11 @@
+-- Add migration script here
+-- audits are append-only, only restore of a backup replaces them.
+CREATE TRIGGER IF NOT EXISTS audits_no_update BEFORE UPDATE ON audits
+BEGIN
+  SELECT RAISE(ABORT, 'audits are append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS audits_no_delete BEFORE DELETE ON audits
+BEGIN
+  SELECT RAISE(ABORT, 'audits are append-only');
+END;
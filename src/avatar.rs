@@ -0,0 +1,65 @@
+use image::{imageops::FilterType, io::Reader, ImageFormat, ImageOutputFormat};
+use std::io::Cursor;
+use tdn::types::primitives::Result;
+
+/// standard avatar size, all avatars are re-encoded to it.
+pub(crate) const AVATAR_SIZE: u32 = 256;
+
+/// thumbnail sizes, saved with the avatar.
+pub(crate) const THUMBNAIL_SIZES: [u32; 1] = [64];
+
+/// max width/height of the upload, check before decoding.
+const MAX_DIMENSION: u32 = 4096;
+
+/// default max bytes of the upload.
+pub(crate) const DEFAULT_AVATAR_MAX_SIZE: usize = 1024 * 1024;
+
+/// check the upload is a real PNG/JPEG/WebP image, and re-encode it to
+/// standard size PNG, all metadata is stripped. empty avatar is empty.
+pub(crate) async fn normalize(bytes: Vec<u8>, max_size: usize) -> Result<Vec<u8>> {
+    if bytes.is_empty() {
+        return Ok(bytes);
+    }
+    if bytes.len() > max_size {
+        return Err(anyhow!(
+            "avatar too large: {} > {} bytes",
+            bytes.len(),
+            max_size
+        ));
+    }
+
+    tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+        let format = Reader::new(Cursor::new(&bytes))
+            .with_guessed_format()?
+            .format();
+        let format = match format {
+            Some(f @ ImageFormat::Png)
+            | Some(f @ ImageFormat::Jpeg)
+            | Some(f @ ImageFormat::WebP) => f,
+            _ => return Err(anyhow!("avatar is not PNG/JPEG/WebP image")),
+        };
+
+        let mut reader = Reader::new(Cursor::new(&bytes));
+        reader.set_format(format);
+        let (width, height) = reader.into_dimensions()?;
+        if width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(anyhow!("avatar too large: {}x{}", width, height));
+        }
+
+        let image = image::load_from_memory_with_format(&bytes, format)?;
+        let image = image.resize_to_fill(AVATAR_SIZE, AVATAR_SIZE, FilterType::Lanczos3);
+        let mut out = Cursor::new(vec![]);
+        image.write_to(&mut out, ImageOutputFormat::Png)?;
+        Ok(out.into_inner())
+    })
+    .await?
+}
+
+/// resize the avatar to thumbnail PNG.
+pub(crate) fn thumbnail(bytes: &[u8], size: u32) -> Result<Vec<u8>> {
+    let image = image::load_from_memory(bytes)?;
+    let image = image.resize_to_fill(size, size, FilterType::Triangle);
+    let mut out = Cursor::new(vec![]);
+    image.write_to(&mut out, ImageOutputFormat::Png)?;
+    Ok(out.into_inner())
+}
@@ -0,0 +1,727 @@
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use tdn::types::{
+    primitives::{PeerId, Result},
+    rpc::{json, RpcParam},
+};
+use tokio::{fs, sync::mpsc};
+
+use crate::avatar::THUMBNAIL_SIZES;
+use crate::config::{restore_custom, strip_secrets, CONFIG_FILE_NAME};
+use crate::crypto::{
+    password_key, password_salt, SealWriter, UnsealReader, KEYRING_FILE, SALT_LEN,
+};
+use crate::models::{now, Attribute, Audit, Did, Report, User, Version};
+use crate::storage::{lock_avatar, pin_avatars, thumbnail_key, Backend, Db, Snapshot, AVATAR_DIR};
+
+/// folder in the db_path of the backups by RPC.
+pub(crate) const BACKUP_DIR: &'static str = "backups";
+
+/// backup file: magic | version | flags | salt | data. data is the frames
+/// (length u32 | bytes), an empty frame, and the checksum of the frames,
+/// optionally compressed, then sealed by the password.
+const MAGIC: &'static [u8] = b"DBAK";
+const VERSION: u8 = 2;
+const FLAG_COMPRESSED: u8 = 1;
+const FLAG_ENCRYPTED: u8 = 2;
+const HEADER_LEN: usize = MAGIC.len() + 2 + SALT_LEN;
+const CHECKSUM_LEN: usize = 32;
+
+/// max bytes of a frame, larger is a broken backup.
+const MAX_FRAME: usize = 64 * 1024 * 1024;
+
+/// frames waiting to be written.
+const FRAME_QUEUE: usize = 64;
+
+/// summary of the backup, last entry of the backup.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub(crate) struct Summary {
+    pub datetime: i64,
+    pub users: usize,
+    pub audits: usize,
+    pub reports: usize,
+    /// backups before profile versions have none.
+    #[serde(default)]
+    pub versions: usize,
+    #[serde(default)]
+    pub attributes: usize,
+    #[serde(default)]
+    pub dids: usize,
+    pub avatars: usize,
+}
+
+impl Summary {
+    pub fn to_rpc(&self) -> RpcParam {
+        json!({
+            "datetime": self.datetime,
+            "users": self.users,
+            "audits": self.audits,
+            "reports": self.reports,
+            "versions": self.versions,
+            "attributes": self.attributes,
+            "dids": self.dids,
+            "avatars": self.avatars,
+        })
+    }
+}
+
+/// user row, bio & avatar saved as stored (maybe encrypted at rest).
+#[derive(Serialize, Deserialize)]
+struct UserRow {
+    id: i64,
+    name: String,
+    pid: String,
+    bio: String,
+    avatar: String,
+    is_actived: bool,
+    is_deleted: bool,
+    /// backups before it have none, retention starts from restore.
+    #[serde(default)]
+    deleted_at: i64,
+    is_banned: bool,
+    datetime: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AuditRow {
+    id: i64,
+    actor: String,
+    operation: String,
+    user_id: i64,
+    name: String,
+    old_value: String,
+    new_value: String,
+    datetime: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ReportRow {
+    id: i64,
+    reporter: String,
+    user_id: i64,
+    name: String,
+    reason: String,
+    status: String,
+    action: String,
+    datetime: i64,
+    resolved_at: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct VersionRow {
+    id: i64,
+    user_id: i64,
+    bio: String,
+    avatar: String,
+    datetime: i64,
+}
+
+/// attribute row, value saved as stored.
+#[derive(Serialize, Deserialize)]
+struct AttributeRow {
+    user_id: i64,
+    key: String,
+    value: String,
+    is_public: bool,
+    datetime: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DidRow {
+    user_id: i64,
+    document: String,
+    key_id: String,
+    signature: String,
+    sequence: i64,
+    datetime: i64,
+}
+
+/// one frame of the backup payload, JSON.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Entry {
+    Summary(Summary),
+    /// config without the secrets (mnemonic).
+    Config(String),
+    /// keyring of rotated keys, wrapped by the mnemonic or key file.
+    Keyring(String),
+    User(UserRow),
+    Audit(AuditRow),
+    Report(ReportRow),
+    Version(VersionRow),
+    Attribute(AttributeRow),
+    Did(DidRow),
+    /// avatar file key, the raw bytes are the next frame.
+    Avatar(String),
+}
+
+/// all contents of a verified backup.
+pub(crate) struct Archive {
+    pub summary: Summary,
+    config: String,
+    keyring: String,
+    snapshot: Snapshot,
+    /// avatars saved in the staging folder, moved in when restore.
+    staging: PathBuf,
+}
+
+fn user_row((user, is_banned): &(User, bool)) -> Entry {
+    Entry::User(UserRow {
+        id: user.id,
+        name: user.name.clone(),
+        pid: user.pid.to_hex(),
+        bio: user.bio.clone(),
+        avatar: user.avatar_hash.clone(),
+        is_actived: user.is_actived,
+        is_deleted: user.is_deleted,
+        deleted_at: user.deleted_at,
+        is_banned: *is_banned,
+        datetime: user.datetime,
+    })
+}
+
+fn audit_row(a: &Audit) -> Entry {
+    Entry::Audit(AuditRow {
+        id: a.id,
+        actor: a.actor.clone(),
+        operation: a.operation.clone(),
+        user_id: a.user_id,
+        name: a.name.clone(),
+        old_value: a.old_value.clone(),
+        new_value: a.new_value.clone(),
+        datetime: a.datetime,
+    })
+}
+
+fn report_row(r: &Report) -> Entry {
+    Entry::Report(ReportRow {
+        id: r.id,
+        reporter: r.reporter.to_hex(),
+        user_id: r.user_id,
+        name: r.name.clone(),
+        reason: r.reason.clone(),
+        status: r.status.clone(),
+        action: r.action.clone(),
+        datetime: r.datetime,
+        resolved_at: r.resolved_at,
+    })
+}
+
+fn version_row(v: &Version) -> Entry {
+    Entry::Version(VersionRow {
+        id: v.id,
+        user_id: v.user_id,
+        bio: v.bio.clone(),
+        avatar: v.avatar_hash.clone(),
+        datetime: v.datetime,
+    })
+}
+
+fn attribute_row(a: &Attribute) -> Entry {
+    Entry::Attribute(AttributeRow {
+        user_id: a.user_id,
+        key: a.key.clone(),
+        value: a.value.clone(),
+        is_public: a.is_public,
+        datetime: a.datetime,
+    })
+}
+
+fn did_row(d: &Did) -> Entry {
+    Entry::Did(DidRow {
+        user_id: d.user_id,
+        document: d.document.clone(),
+        key_id: d.key_id.clone(),
+        signature: d.signature.clone(),
+        sequence: d.sequence,
+        datetime: d.datetime,
+    })
+}
+
+fn pid(hex: &str) -> Result<PeerId> {
+    PeerId::from_hex(hex).map_err(|_| anyhow!("invalid pid {}", hex))
+}
+
+/// stream the snapshot rows, used avatars & config to the file, the domain
+/// keeps running. password is optional (empty), the avatars & bios encrypted
+/// at rest are kept encrypted, restore needs the same key (mnemonic or key file).
+pub(crate) async fn backup(
+    db: &Db,
+    path: &Path,
+    compress: bool,
+    password: &str,
+) -> Result<Summary> {
+    // a crash never leaves half backup.
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let (sender, frames) = mpsc::channel(FRAME_QUEUE);
+    let writer = {
+        let (tmp, password) = (tmp.clone(), password.to_owned());
+        tokio::task::spawn_blocking(move || write_backup(&tmp, compress, &password, frames))
+    };
+    let produced = produce(db, &sender).await;
+    drop(sender);
+    let written = writer.await?;
+
+    match (written, produced) {
+        (Ok(()), Ok(summary)) => {
+            fs::rename(&tmp, path).await?;
+            info!(
+                "Backup {} users, {} avatars to {:?}",
+                summary.users, summary.avatars, path
+            );
+            Ok(summary)
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            let _ = fs::remove_file(&tmp).await;
+            Err(e)
+        }
+    }
+}
+
+async fn send_frame(frames: &mpsc::Sender<Vec<u8>>, frame: Vec<u8>) -> Result<()> {
+    frames
+        .send(frame)
+        .await
+        .map_err(|_| anyhow!("backup writer closed"))
+}
+
+async fn send_entry(frames: &mpsc::Sender<Vec<u8>>, entry: Entry) -> Result<()> {
+    send_frame(frames, serde_json::to_vec(&entry)?).await
+}
+
+/// read the rows & avatars to frames, return the summary.
+async fn produce(db: &Db, frames: &mpsc::Sender<Vec<u8>>) -> Result<Summary> {
+    let (base, avatars) = (&db.base, &db.avatars);
+    // avatars released after the snapshot are kept until the backup finished.
+    let _pin = pin_avatars();
+    let snapshot = db.store.snapshot().await?;
+
+    let mut config_path = base.clone();
+    config_path.push(CONFIG_FILE_NAME);
+    let config = fs::read_to_string(config_path).await.unwrap_or_default();
+    let mut keyring_path = base.clone();
+    keyring_path.push(KEYRING_FILE);
+    let keyring = fs::read_to_string(keyring_path).await.unwrap_or_default();
+
+    send_entry(frames, Entry::Config(strip_secrets(&config))).await?;
+    send_entry(frames, Entry::Keyring(keyring)).await?;
+    let rows = snapshot
+        .users
+        .iter()
+        .map(user_row)
+        .chain(snapshot.audits.iter().map(audit_row))
+        .chain(snapshot.reports.iter().map(report_row))
+        .chain(snapshot.versions.iter().map(version_row))
+        .chain(snapshot.attributes.iter().map(attribute_row))
+        .chain(snapshot.dids.iter().map(did_row));
+    for entry in rows {
+        send_entry(frames, entry).await?;
+    }
+
+    let mut count = 0;
+    let mut used = HashSet::new();
+    let hashes = snapshot
+        .users
+        .iter()
+        .filter(|(user, _)| !user.is_deleted)
+        .map(|(user, _)| (user.id, &user.avatar_hash))
+        .chain(
+            snapshot
+                .versions
+                .iter()
+                .map(|v| (v.user_id, &v.avatar_hash)),
+        );
+    for (user_id, hash) in hashes {
+        if hash.is_empty() || !used.insert(hash) {
+            continue;
+        }
+        let mut keys = vec![hash.clone()];
+        for size in THUMBNAIL_SIZES {
+            keys.push(thumbnail_key(hash, size));
+        }
+        // not replaced by re-encryption while reading.
+        let _guard = lock_avatar(hash).await;
+        for key in keys {
+            let bytes = avatars.read(base, &key).await?;
+            if bytes.is_empty() {
+                warn!("backup: user {} avatar {} is missing", user_id, key);
+                continue;
+            }
+            send_entry(frames, Entry::Avatar(key)).await?;
+            send_frame(frames, bytes).await?;
+            count += 1;
+        }
+    }
+
+    let summary = Summary {
+        datetime: now(),
+        users: snapshot.users.len(),
+        audits: snapshot.audits.len(),
+        reports: snapshot.reports.len(),
+        versions: snapshot.versions.len(),
+        attributes: snapshot.attributes.len(),
+        dids: snapshot.dids.len(),
+        avatars: count,
+    };
+    send_entry(frames, Entry::Summary(summary.clone())).await?;
+    Ok(summary)
+}
+
+/// write the header & frames to the file, synced when finished.
+fn write_backup(
+    path: &Path,
+    compress: bool,
+    password: &str,
+    frames: mpsc::Receiver<Vec<u8>>,
+) -> Result<()> {
+    let mut file = BufWriter::new(std::fs::File::create(path)?);
+    let mut flags = 0;
+    if compress {
+        flags |= FLAG_COMPRESSED;
+    }
+    let mut salt = [0u8; SALT_LEN];
+    if !password.is_empty() {
+        flags |= FLAG_ENCRYPTED;
+        salt = password_salt();
+    }
+    file.write_all(MAGIC)?;
+    file.write_all(&[VERSION, flags])?;
+    file.write_all(&salt)?;
+
+    let file = if password.is_empty() {
+        if compress {
+            let encoder = GzEncoder::new(file, Compression::default());
+            write_frames(encoder, frames)?.finish()?
+        } else {
+            write_frames(file, frames)?
+        }
+    } else {
+        let sealer = SealWriter::new(file, &password_key(password, &salt)?);
+        let sealer = if compress {
+            let encoder = GzEncoder::new(sealer, Compression::default());
+            write_frames(encoder, frames)?.finish()?
+        } else {
+            write_frames(sealer, frames)?
+        };
+        sealer.finish()?
+    };
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(())
+}
+
+/// write the frames, the end & the checksum.
+fn write_frames<W: Write>(mut writer: W, mut frames: mpsc::Receiver<Vec<u8>>) -> Result<W> {
+    let mut hasher = blake3::Hasher::new();
+    while let Some(frame) = frames.blocking_recv() {
+        let len = (frame.len() as u32).to_le_bytes();
+        hasher.update(&len);
+        hasher.update(&frame);
+        writer.write_all(&len)?;
+        writer.write_all(&frame)?;
+    }
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(hasher.finalize().as_bytes())?;
+    Ok(writer)
+}
+
+/// next frame, none if it is the end.
+fn read_frame(reader: &mut impl Read, hasher: &mut blake3::Hasher) -> Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    reader
+        .read_exact(&mut len)
+        .map_err(|e| anyhow!("backup is incomplete: {}", e))?;
+    let size = u32::from_le_bytes(len) as usize;
+    if size == 0 {
+        return Ok(None);
+    }
+    if size > MAX_FRAME {
+        return Err(anyhow!("backup is corrupted"));
+    }
+    let mut frame = vec![0u8; size];
+    reader
+        .read_exact(&mut frame)
+        .map_err(|e| anyhow!("backup is incomplete: {}", e))?;
+    hasher.update(&len);
+    hasher.update(&frame);
+    Ok(Some(frame))
+}
+
+/// read the backup, verify the checksum and all entries. the avatars are
+/// saved to the staging folder in db_path, the domain is not touched.
+pub(crate) async fn verify(path: &Path, password: &str, base: &PathBuf) -> Result<Archive> {
+    let mut staging = base.clone();
+    staging.push(format!("{}.restore", AVATAR_DIR));
+    if staging.exists() {
+        fs::remove_dir_all(&staging).await?;
+    }
+    fs::create_dir_all(&staging).await?;
+
+    let archive = {
+        let (path, password, staging) = (path.to_owned(), password.to_owned(), staging.clone());
+        tokio::task::spawn_blocking(move || read_backup(&path, &password, staging)).await?
+    };
+    if archive.is_err() {
+        let _ = fs::remove_dir_all(&staging).await;
+    }
+    archive
+}
+
+fn read_backup(path: &Path, password: &str, staging: PathBuf) -> Result<Archive> {
+    let mut file = BufReader::new(std::fs::File::open(path)?);
+    let mut header = [0u8; HEADER_LEN];
+    file.read_exact(&mut header)
+        .map_err(|_| anyhow!("not a domain backup"))?;
+    if !header.starts_with(MAGIC) {
+        return Err(anyhow!("not a domain backup"));
+    }
+    if header[MAGIC.len()] != VERSION {
+        return Err(anyhow!(
+            "unsupported backup version {}",
+            header[MAGIC.len()]
+        ));
+    }
+    let flags = header[MAGIC.len() + 1];
+    let salt = &header[MAGIC.len() + 2..];
+
+    let mut reader: Box<dyn Read> = Box::new(file);
+    if flags & FLAG_ENCRYPTED != 0 {
+        if password.is_empty() {
+            return Err(anyhow!("backup is encrypted, password required"));
+        }
+        reader = Box::new(UnsealReader::new(reader, &password_key(password, salt)?));
+    }
+    if flags & FLAG_COMPRESSED != 0 {
+        reader = Box::new(GzDecoder::new(reader));
+    }
+
+    let mut hasher = blake3::Hasher::new();
+    let mut summary = None;
+    let mut config = String::new();
+    let mut keyring = String::new();
+    let mut snapshot = Snapshot::default();
+    let mut avatars = 0;
+    while let Some(frame) = read_frame(&mut reader, &mut hasher)? {
+        match serde_json::from_slice(&frame)? {
+            Entry::Summary(s) => summary = Some(s),
+            Entry::Config(c) => config = c,
+            Entry::Keyring(k) => keyring = k,
+            Entry::User(u) => {
+                let user = User {
+                    id: u.id,
+                    name: u.name,
+                    pid: pid(&u.pid)?,
+                    bio: u.bio,
+                    avatar: vec![],
+                    avatar_hash: u.avatar,
+                    is_actived: u.is_actived,
+                    is_deleted: u.is_deleted,
+                    deleted_at: u.deleted_at,
+                    datetime: u.datetime,
+                };
+                snapshot.users.push((user, u.is_banned));
+            }
+            Entry::Audit(a) => snapshot.audits.push(Audit {
+                id: a.id,
+                actor: a.actor,
+                operation: a.operation,
+                user_id: a.user_id,
+                name: a.name,
+                old_value: a.old_value,
+                new_value: a.new_value,
+                datetime: a.datetime,
+            }),
+            Entry::Report(r) => snapshot.reports.push(Report {
+                id: r.id,
+                reporter: pid(&r.reporter)?,
+                user_id: r.user_id,
+                name: r.name,
+                reason: r.reason,
+                status: r.status,
+                action: r.action,
+                datetime: r.datetime,
+                resolved_at: r.resolved_at,
+            }),
+            Entry::Version(v) => snapshot.versions.push(Version {
+                id: v.id,
+                user_id: v.user_id,
+                bio: v.bio,
+                avatar_hash: v.avatar,
+                datetime: v.datetime,
+            }),
+            Entry::Attribute(a) => snapshot.attributes.push(Attribute {
+                user_id: a.user_id,
+                key: a.key,
+                value: a.value,
+                is_public: a.is_public,
+                datetime: a.datetime,
+            }),
+            Entry::Did(d) => snapshot.dids.push(Did {
+                user_id: d.user_id,
+                document: d.document,
+                key_id: d.key_id,
+                signature: d.signature,
+                sequence: d.sequence,
+                datetime: d.datetime,
+            }),
+            Entry::Avatar(key) => {
+                if key.contains('/') || key.contains('\\') || key.starts_with('.') {
+                    return Err(anyhow!("invalid avatar key {}", key));
+                }
+                let bytes = read_frame(&mut reader, &mut hasher)?
+                    .ok_or_else(|| anyhow!("avatar {} is missing", key))?;
+                let mut path = staging.clone();
+                path.push(&key);
+                std::fs::write(path, bytes)?;
+                avatars += 1;
+            }
+        }
+    }
+
+    let mut checksum = [0u8; CHECKSUM_LEN];
+    reader
+        .read_exact(&mut checksum)
+        .map_err(|_| anyhow!("backup is incomplete"))?;
+    if hasher.finalize().as_bytes() != &checksum {
+        return Err(anyhow!("backup checksum mismatch, file is corrupted"));
+    }
+
+    let summary = summary.ok_or(anyhow!("backup summary missing"))?;
+    if summary.users != snapshot.users.len()
+        || summary.audits != snapshot.audits.len()
+        || summary.reports != snapshot.reports.len()
+        || summary.versions != snapshot.versions.len()
+        || summary.attributes != snapshot.attributes.len()
+        || summary.dids != snapshot.dids.len()
+        || summary.avatars != avatars
+    {
+        return Err(anyhow!("backup is incomplete"));
+    }
+
+    Ok(Archive {
+        summary,
+        config,
+        keyring,
+        snapshot,
+        staging,
+    })
+}
+
+/// replace all rows, avatars & config by the verified backup. the `keep`
+/// items of current config (storage, database & secrets) are kept.
+pub(crate) async fn restore(
+    db: &Db,
+    backend: Backend,
+    archive: Archive,
+    keep: &[(&str, String)],
+) -> Result<Summary> {
+    let base = &db.base;
+    let mut avatar_dir = base.clone();
+    avatar_dir.push(AVATAR_DIR);
+    let staging = &archive.staging;
+    let mut old = base.clone();
+    old.push(format!("{}.old", AVATAR_DIR));
+
+    // the avatars are staged, the rows are replaced only if all saved.
+    db.store.restore(&archive.snapshot).await?;
+
+    if backend == Backend::Memory {
+        let mut entries = fs::read_dir(staging).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let key = entry.file_name().to_string_lossy().into_owned();
+            let bytes = fs::read(entry.path()).await?;
+            db.avatars.replace(base, &key, &bytes).await?;
+        }
+        fs::remove_dir_all(staging).await?;
+    } else {
+        if old.exists() {
+            fs::remove_dir_all(&old).await?;
+        }
+        if avatar_dir.exists() {
+            fs::rename(&avatar_dir, &old).await?;
+        }
+        fs::rename(staging, &avatar_dir).await?;
+        if old.exists() {
+            fs::remove_dir_all(&old).await?;
+        }
+    }
+
+    if !archive.config.is_empty() {
+        restore_custom(base, &archive.config, keep).await?;
+    }
+
+    // keys of the restored data.
+    if !archive.keyring.is_empty() {
+        let mut keyring_path = base.clone();
+        keyring_path.push(KEYRING_FILE);
+        let mut tmp = base.clone();
+        tmp.push(format!("{}.restore", KEYRING_FILE));
+        fs::write(&tmp, &archive.keyring).await?;
+        fs::rename(&tmp, &keyring_path).await?;
+    }
+
+    info!(
+        "Restored {} users, {} avatars from backup at {}",
+        archive.summary.users, archive.summary.avatars, archive.summary.datetime
+    );
+    Ok(archive.summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{self, DatabaseConfig};
+
+    async fn memory_db(base: &PathBuf) -> Db {
+        storage::init(base, Backend::Memory, DatabaseConfig::default())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn seal_verify_restore() {
+        let base = std::env::temp_dir().join(format!("domain-backup-{}", rand::random::<u32>()));
+        fs::create_dir_all(&base).await.unwrap();
+        let mut path = base.clone();
+        path.push("test.backup");
+
+        let db = memory_db(&base).await;
+        for (i, name) in ["alice", "bob"].iter().enumerate() {
+            let pid = PeerId([i as u8 + 1; 32]);
+            let mut user = User::new(name.to_string(), pid, "bio".to_owned(), vec![i as u8; 8]);
+            user.insert(&db, "test").await.unwrap();
+        }
+        let summary = backup(&db, &path, true, "password").await.unwrap();
+        assert_eq!((summary.users, summary.avatars), (2, 2));
+
+        assert!(verify(&path, "wrong", &base).await.is_err());
+        assert!(verify(&path, "", &base).await.is_err());
+
+        let mut bytes = fs::read(&path).await.unwrap();
+        let middle = HEADER_LEN + (bytes.len() - HEADER_LEN) / 2;
+        bytes[middle] ^= 0xff;
+        let mut corrupted = base.clone();
+        corrupted.push("corrupted.backup");
+        fs::write(&corrupted, bytes).await.unwrap();
+        assert!(verify(&corrupted, "password", &base).await.is_err());
+
+        let archive = verify(&path, "password", &base).await.unwrap();
+        let restored = memory_db(&base).await;
+        let summary = restore(&restored, Backend::Memory, archive, &[])
+            .await
+            .unwrap();
+        assert_eq!(summary.users, 2);
+        let users = User::list(&restored).await.unwrap();
+        assert_eq!(users.len(), 2);
+        assert!(users
+            .iter()
+            .all(|u| u.bio == "bio" && u.avatar.len() == 8 && !u.avatar_hash.is_empty()));
+
+        let _ = fs::remove_dir_all(&base).await;
+    }
+}
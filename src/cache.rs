@@ -0,0 +1,196 @@
+use lru::LruCache;
+use once_cell::sync::OnceCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tdn::types::rpc::{json, RpcParam};
+
+use crate::models::{now, User};
+use crate::storage::name_key;
+
+/// default max users in cache.
+pub(crate) const DEFAULT_CACHE_CAPACITY: usize = 512;
+/// default seconds of the cached user.
+pub(crate) const DEFAULT_CACHE_TTL: u64 = 300;
+
+/// LRU cache of searched users, keyed by normalized name.
+struct UserCache {
+    users: Mutex<LruCache<String, (User, i64)>>,
+    ttl: i64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    /// increased by every invalidation, users loaded before it are not cached.
+    generation: AtomicU64,
+}
+
+static CACHE: OnceCell<UserCache> = OnceCell::new();
+
+/// cache key of the name, the name key of the database unique index.
+#[inline]
+fn key(name: &str) -> String {
+    name_key(name)
+}
+
+impl UserCache {
+    fn new(capacity: usize, ttl: u64) -> Self {
+        Self {
+            users: Mutex::new(LruCache::new(capacity)),
+            ttl: ttl as i64,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<User> {
+        let mut users = self.users.lock().unwrap();
+        let key = key(name);
+        let user = match users.get(&key) {
+            Some((user, time)) if now() - *time < self.ttl => Some(user.clone()),
+            Some(_) => {
+                users.pop(&key);
+                None
+            }
+            None => None,
+        };
+
+        if user.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        user
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    fn put(&self, user: &User, generation: u64) {
+        let mut users = self.users.lock().unwrap();
+        if self.generation.load(Ordering::SeqCst) == generation {
+            users.put(key(&user.name), (user.clone(), now()));
+        }
+    }
+
+    fn invalidate(&self, name: &str) {
+        let mut users = self.users.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        users.pop(&key(name));
+    }
+
+    fn invalidate_id(&self, id: i64) {
+        let mut users = self.users.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        let keys: Vec<String> = users
+            .iter()
+            .filter(|(_, (user, _))| user.id == id)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys {
+            users.pop(&key);
+        }
+    }
+}
+
+/// capacity 0 disables the cache.
+pub(crate) fn init(capacity: usize, ttl: u64) {
+    if capacity == 0 {
+        return;
+    }
+    let _ = CACHE.set(UserCache::new(capacity, ttl));
+}
+
+/// cached user, none if missing or expired.
+pub(crate) fn get(name: &str) -> Option<User> {
+    CACHE.get()?.get(name)
+}
+
+/// generation before loading the user from store.
+#[inline]
+pub(crate) fn generation() -> u64 {
+    CACHE.get().map(|c| c.generation()).unwrap_or(0)
+}
+
+/// cache the user loaded at the generation, skipped if invalidated since.
+pub(crate) fn put(user: &User, generation: u64) {
+    if let Some(cache) = CACHE.get() {
+        cache.put(user, generation)
+    }
+}
+
+/// user changed, remove it by name.
+pub(crate) fn invalidate(name: &str) {
+    if let Some(cache) = CACHE.get() {
+        cache.invalidate(name)
+    }
+}
+
+/// user changed, remove it by id.
+pub(crate) fn invalidate_id(id: i64) {
+    if let Some(cache) = CACHE.get() {
+        cache.invalidate_id(id)
+    }
+}
+
+/// cache size & hit/miss stats.
+pub(crate) fn stats() -> RpcParam {
+    match CACHE.get() {
+        Some(cache) => {
+            let users = cache.users.lock().unwrap();
+            json!({
+                "enabled": true,
+                "capacity": users.cap(),
+                "size": users.len(),
+                "ttl": cache.ttl,
+                "hits": cache.hits.load(Ordering::Relaxed),
+                "misses": cache.misses.load(Ordering::Relaxed),
+            })
+        }
+        None => json!({ "enabled": false }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tdn::types::primitives::PeerId;
+
+    fn user(id: i64, name: &str) -> User {
+        let mut user = User::new(
+            name.to_owned(),
+            PeerId([id as u8; 32]),
+            String::new(),
+            vec![],
+        );
+        user.id = id;
+        user
+    }
+
+    #[test]
+    fn lru_eviction() {
+        let cache = UserCache::new(2, 300);
+        cache.put(&user(1, "alice"), 0);
+        cache.put(&user(2, "bob"), 0);
+        assert!(cache.get("ALICE").is_some());
+        // bob is the least recently used.
+        cache.put(&user(3, "carol"), 0);
+        assert!(cache.get("bob").is_none());
+        assert!(cache.get("alice").is_some() && cache.get("carol").is_some());
+    }
+
+    #[test]
+    fn generation_invalidation() {
+        let cache = UserCache::new(8, 300);
+        cache.put(&user(1, "alice"), cache.generation());
+        cache.invalidate_id(1);
+        assert!(cache.get("alice").is_none());
+
+        // loaded before an invalidation, it may be stale.
+        let generation = cache.generation();
+        cache.invalidate("bob");
+        cache.put(&user(1, "alice"), generation);
+        assert!(cache.get("alice").is_none());
+        cache.put(&user(1, "alice"), cache.generation());
+        assert!(cache.get("alice").is_some());
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use tdn::types::primitives::Result;
+
+use crate::config::parse_addrs;
+
+/// default data directory (db_path).
+pub(crate) const DEFAULT_DATA_DIR: &'static str = "./.tdn";
+
+pub(crate) const USAGE: &'static str = r#"ESSE domain service.
+
+Usage: domain [command] [options]
+
+Commands:
+  run                             start the domain (default)
+      --p2p-addr <addr>           P2P listen address, e.g. 0.0.0.0:7350
+      --rpc-addr <addr>           RPC listen address, e.g. 127.0.0.1:7351
+      --bootstrap <addr,...>      bootstrap peers, empty is none
+  init                            create the config and mnemonic
+  show-id                         show the domain peer id
+  users list                      list all users
+  users get <name>                show the user
+  users ban <name> [--unban]      ban or unban the user
+  users delete <name>             delete the user
+  export <file> [--format f]      export users (json or csv)
+  import <file> [--format f]      import users (json or csv)
+  migrate [run|status|dry-run]    apply or show database migrations
+  backup <file> [--compress] [--password-stdin]
+  restore <file> [--password-stdin]
+                                  restore the backup, stop the service first
+  help                            show this message
+
+Options:
+  --data-dir <dir>                domain data directory, default ./.tdn
+"#;
+
+/// users management subcommands.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum UsersCommand {
+    List,
+    Get(String),
+    Ban(String, bool),
+    Delete(String),
+}
+
+/// parsed command line.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Command {
+    Run {
+        p2p_addr: Option<SocketAddr>,
+        rpc_addr: Option<SocketAddr>,
+        bootstrap: Option<Vec<SocketAddr>>,
+    },
+    Init,
+    ShowId,
+    Users(UsersCommand),
+    Export {
+        file: String,
+        format: String,
+    },
+    Import {
+        file: String,
+        format: String,
+    },
+    Migrate(String),
+    /// the password is from stdin if `password_stdin`, or env.
+    Backup {
+        file: String,
+        compress: bool,
+        password_stdin: bool,
+    },
+    Restore {
+        file: String,
+        password_stdin: bool,
+    },
+    Help,
+}
+
+/// options with a value.
+const VALUE_OPTIONS: [&'static str; 5] = [
+    "--data-dir",
+    "--p2p-addr",
+    "--rpc-addr",
+    "--bootstrap",
+    "--format",
+];
+/// options without value.
+const FLAG_OPTIONS: [&'static str; 5] =
+    ["--compress", "--password-stdin", "--unban", "--help", "-h"];
+
+/// command line args (without program name) split to positionals & options.
+struct Args {
+    positionals: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn split(args: &[String]) -> Result<Self> {
+        let mut positionals = vec![];
+        let mut options = HashMap::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let (key, value) = match arg.split_once('=') {
+                Some((k, v)) if k.starts_with("--") => (k, Some(v.to_owned())),
+                _ => (arg.as_str(), None),
+            };
+            if VALUE_OPTIONS.contains(&key) {
+                let value = match value {
+                    Some(v) => v,
+                    None => iter
+                        .next()
+                        .cloned()
+                        .ok_or(anyhow!("{} needs a value", key))?,
+                };
+                options.insert(key.to_owned(), value);
+            } else if FLAG_OPTIONS.contains(&key) {
+                options.insert(key.to_owned(), String::new());
+            } else if key.starts_with("--") {
+                return Err(anyhow!("unknown option {}", key));
+            } else {
+                positionals.push(arg.clone());
+            }
+        }
+        Ok(Self {
+            positionals,
+            options,
+        })
+    }
+
+    fn flag(&self, key: &str) -> bool {
+        self.options.contains_key(key)
+    }
+
+    fn value(&self, key: &str) -> Option<String> {
+        self.options.get(key).cloned()
+    }
+
+    fn addr(&self, key: &str) -> Result<Option<SocketAddr>> {
+        match self.options.get(key) {
+            Some(v) => Ok(Some(
+                v.parse()
+                    .map_err(|_| anyhow!("{} invalid address {}", key, v))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// the nth positional, required.
+    fn required(&self, i: usize, name: &str) -> Result<String> {
+        self.positionals
+            .get(i)
+            .cloned()
+            .ok_or(anyhow!("missing <{}>", name))
+    }
+}
+
+/// parse the command & data dir, the data dir can also be the last positional
+/// (`domain ./.tdn`, `domain export users.csv csv ./.tdn`) as before.
+pub(crate) fn parse(args: &[String]) -> Result<(Command, String)> {
+    let args = Args::split(args)?;
+    if args.flag("--help") || args.flag("-h") {
+        return Ok((Command::Help, DEFAULT_DATA_DIR.to_owned()));
+    }
+
+    let cmd = args.positionals.get(0).map(|s| s.as_str()).unwrap_or("run");
+    // the command, and the index of the legacy data dir positional.
+    let (command, count) = match cmd {
+        "run" => (run(&args)?, 1),
+        "init" => (Command::Init, 1),
+        "show-id" => (Command::ShowId, 1),
+        "help" => (Command::Help, 1),
+        "users" => {
+            let sub = args.required(1, "list|get|ban|delete")?;
+            let users = match sub.as_str() {
+                "list" => UsersCommand::List,
+                "get" => UsersCommand::Get(args.required(2, "name")?),
+                "ban" => UsersCommand::Ban(args.required(2, "name")?, !args.flag("--unban")),
+                "delete" => UsersCommand::Delete(args.required(2, "name")?),
+                _ => return Err(anyhow!("unknown users command {}", sub)),
+            };
+            let count = if users == UsersCommand::List { 2 } else { 3 };
+            (Command::Users(users), count)
+        }
+        "export" | "import" => {
+            let file = args.required(1, "file")?;
+            // legacy positional format.
+            let format = args
+                .value("--format")
+                .or(args.positionals.get(2).cloned())
+                .unwrap_or_default();
+            let count = if args.value("--format").is_some() {
+                2
+            } else {
+                3
+            };
+            if cmd == "export" {
+                (Command::Export { file, format }, count)
+            } else {
+                (Command::Import { file, format }, count)
+            }
+        }
+        "migrate" => {
+            let action = args.positionals.get(1).cloned().unwrap_or("run".to_owned());
+            if !["run", "status", "dry-run"].contains(&action.as_str()) {
+                return Err(anyhow!("unknown migrate command {}", action));
+            }
+            (Command::Migrate(action), 2)
+        }
+        "backup" | "restore" => {
+            let file = args.required(1, "file")?;
+            // never in the args, seen by other users in the process list.
+            let password_stdin = args.flag("--password-stdin");
+            if cmd == "backup" {
+                let compress = args.flag("--compress");
+                let command = Command::Backup {
+                    file,
+                    compress,
+                    password_stdin,
+                };
+                (command, 2)
+            } else {
+                (
+                    Command::Restore {
+                        file,
+                        password_stdin,
+                    },
+                    2,
+                )
+            }
+        }
+        // `domain ./.tdn` starts the domain.
+        _ if args.positionals.len() == 1 => (run(&args)?, 0),
+        _ => return Err(anyhow!("unknown command {}", cmd)),
+    };
+
+    let data_dir = match args.value("--data-dir") {
+        Some(dir) => dir,
+        None => args
+            .positionals
+            .get(count)
+            .cloned()
+            .unwrap_or(DEFAULT_DATA_DIR.to_owned()),
+    };
+    let max = if args.value("--data-dir").is_some() {
+        count
+    } else {
+        count + 1
+    };
+    if args.positionals.len() > max {
+        return Err(anyhow!("unexpected argument {}", args.positionals[max]));
+    }
+
+    Ok((command, data_dir))
+}
+
+fn run(args: &Args) -> Result<Command> {
+    Ok(Command::Run {
+        p2p_addr: args.addr("--p2p-addr")?,
+        rpc_addr: args.addr("--rpc-addr")?,
+        bootstrap: match args.value("--bootstrap") {
+            Some(v) => Some(parse_addrs(&v)?),
+            None => None,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(args: &str) -> Result<(Command, String)> {
+        let args: Vec<String> = args.split_whitespace().map(|s| s.to_owned()).collect();
+        parse(&args)
+    }
+
+    #[test]
+    fn run_default() {
+        let (command, dir) = parse_str("").unwrap();
+        assert_eq!(
+            command,
+            Command::Run {
+                p2p_addr: None,
+                rpc_addr: None,
+                bootstrap: None,
+            }
+        );
+        assert_eq!(dir, DEFAULT_DATA_DIR);
+    }
+
+    #[test]
+    fn run_options() {
+        let (command, dir) = parse_str(
+            "run --p2p-addr 0.0.0.0:7350 --rpc-addr=127.0.0.1:7351 --bootstrap= --data-dir d",
+        )
+        .unwrap();
+        assert_eq!(
+            command,
+            Command::Run {
+                p2p_addr: Some("0.0.0.0:7350".parse().unwrap()),
+                rpc_addr: Some("127.0.0.1:7351".parse().unwrap()),
+                bootstrap: Some(vec![]),
+            }
+        );
+        assert_eq!(dir, "d");
+
+        assert!(parse_str("run --p2p-addr localhost").is_err());
+        assert!(parse_str("run --rpc-addr").is_err());
+    }
+
+    #[test]
+    fn legacy_data_dir() {
+        let (command, dir) = parse_str("./data").unwrap();
+        assert!(matches!(command, Command::Run { .. }));
+        assert_eq!(dir, "./data");
+
+        let (command, dir) = parse_str("export users.csv csv ./data").unwrap();
+        assert_eq!(
+            command,
+            Command::Export {
+                file: "users.csv".to_owned(),
+                format: "csv".to_owned(),
+            }
+        );
+        assert_eq!(dir, "./data");
+
+        let (command, dir) = parse_str("import users.json --format json --data-dir d").unwrap();
+        assert_eq!(
+            command,
+            Command::Import {
+                file: "users.json".to_owned(),
+                format: "json".to_owned(),
+            }
+        );
+        assert_eq!(dir, "d");
+    }
+
+    #[test]
+    fn users() {
+        let (command, _) = parse_str("users list").unwrap();
+        assert_eq!(command, Command::Users(UsersCommand::List));
+        let (command, _) = parse_str("users ban alice").unwrap();
+        assert_eq!(
+            command,
+            Command::Users(UsersCommand::Ban("alice".to_owned(), true))
+        );
+        let (command, _) = parse_str("users ban alice --unban").unwrap();
+        assert_eq!(
+            command,
+            Command::Users(UsersCommand::Ban("alice".to_owned(), false))
+        );
+        let (command, dir) = parse_str("users delete alice ./data").unwrap();
+        assert_eq!(
+            command,
+            Command::Users(UsersCommand::Delete("alice".to_owned()))
+        );
+        assert_eq!(dir, "./data");
+
+        assert!(parse_str("users get").is_err());
+        assert!(parse_str("users rename alice").is_err());
+    }
+
+    #[test]
+    fn backup_restore() {
+        let (command, _) = parse_str("backup b.bin --compress --password-stdin").unwrap();
+        assert_eq!(
+            command,
+            Command::Backup {
+                file: "b.bin".to_owned(),
+                compress: true,
+                password_stdin: true,
+            }
+        );
+        let (command, _) = parse_str("restore b.bin").unwrap();
+        assert_eq!(
+            command,
+            Command::Restore {
+                file: "b.bin".to_owned(),
+                password_stdin: false,
+            }
+        );
+        // the password is never an argument.
+        assert!(parse_str("backup b.bin --password secret").is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(parse_str("run --unknown").is_err());
+        assert!(parse_str("unknown command").is_err());
+        assert!(parse_str("migrate up").is_err());
+        assert!(parse_str("init ./data extra").is_err());
+        assert!(parse_str("show-id --data-dir d extra").is_err());
+        assert_eq!(parse_str("users list -h").unwrap().0, Command::Help);
+    }
+}
@@ -0,0 +1,473 @@
+use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+use std::{env, net::SocketAddr, path::PathBuf};
+use tdn::types::primitives::Result;
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+
+use crate::avatar::DEFAULT_AVATAR_MAX_SIZE;
+use crate::cache::{DEFAULT_CACHE_CAPACITY, DEFAULT_CACHE_TTL};
+use crate::crypto::{Encryption, DEFAULT_KEY_FILE};
+use crate::dispatch::DEFAULT_LAYER_WORKERS;
+use crate::network::DEFAULT_LOST_TIMEOUT;
+use crate::storage::{Backend, DatabaseConfig, DEFAULT_PURGE_RETENTION};
+
+pub(crate) const DEFAULT_PROVIDER_NAME: &'static str = "domain.esse";
+pub(crate) const DEFAULT_PROVIDER_PROXY: bool = true;
+
+pub(crate) const DEFAULT_P2P_ADDR: &'static str = "0.0.0.0:7350";
+pub(crate) const DEFAULT_RPC_ADDR: &'static str = "127.0.0.1:7351";
+
+/// max bootstrap peers.
+pub(crate) const MAX_BOOTSTRAP: usize = 64;
+
+/// tdn config file, custom config is saved in it.
+pub(crate) const CONFIG_FILE_NAME: &'static str = "config.toml";
+
+/// items of the config not saved in backups, restore keeps the current.
+const SECRET_ITEMS: [&'static str; 2] = ["mnemonic", "admin_token"];
+
+/// registration policy of the domain.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterPolicy {
+    /// everyone can register.
+    Open,
+    /// no new registration.
+    Closed,
+}
+
+impl Default for RegisterPolicy {
+    fn default() -> Self {
+        RegisterPolicy::Open
+    }
+}
+
+impl RegisterPolicy {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "open" => Some(RegisterPolicy::Open),
+            "closed" => Some(RegisterPolicy::Closed),
+            _ => None,
+        }
+    }
+
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            RegisterPolicy::Open => "open",
+            RegisterPolicy::Closed => "closed",
+        }
+    }
+}
+
+/// network settings, saved in config.toml, and can be overridden by env
+/// (`DOMAIN_P2P_ADDR`, `DOMAIN_RPC_ADDR`, `DOMAIN_BOOTSTRAP` comma separated,
+/// `DOMAIN_NETWORK_LOST_TIMEOUT`) and command line.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// P2P listen address.
+    pub network_p2p_addr: SocketAddr,
+    /// RPC listen address.
+    pub network_rpc_addr: SocketAddr,
+    /// bootstrap peers dialed when start, empty is none.
+    pub network_bootstrap: Vec<SocketAddr>,
+    /// seconds of network lost before exit, 0 is never.
+    pub network_lost_timeout: u64,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            network_p2p_addr: DEFAULT_P2P_ADDR.parse().unwrap(),
+            network_rpc_addr: DEFAULT_RPC_ADDR.parse().unwrap(),
+            network_bootstrap: vec![],
+            network_lost_timeout: DEFAULT_LOST_TIMEOUT,
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// override the settings by env (and .env file).
+    pub fn with_env(mut self) -> Result<Self> {
+        dotenv().ok();
+        if let Ok(v) = env::var("DOMAIN_P2P_ADDR") {
+            self.network_p2p_addr = v.parse().map_err(|_| anyhow!("invalid DOMAIN_P2P_ADDR"))?;
+        }
+        if let Ok(v) = env::var("DOMAIN_RPC_ADDR") {
+            self.network_rpc_addr = v.parse().map_err(|_| anyhow!("invalid DOMAIN_RPC_ADDR"))?;
+        }
+        if let Ok(v) = env::var("DOMAIN_BOOTSTRAP") {
+            self.network_bootstrap =
+                parse_addrs(&v).map_err(|_| anyhow!("invalid DOMAIN_BOOTSTRAP"))?;
+        }
+        if let Ok(v) = env::var("DOMAIN_NETWORK_LOST_TIMEOUT") {
+            self.network_lost_timeout = v
+                .parse()
+                .map_err(|_| anyhow!("invalid DOMAIN_NETWORK_LOST_TIMEOUT"))?;
+        }
+        Ok(self)
+    }
+}
+
+/// parse comma separated addresses, empty is none.
+pub(crate) fn parse_addrs(s: &str) -> Result<Vec<SocketAddr>> {
+    let mut addrs = vec![];
+    for addr in s.split(',').map(|a| a.trim()).filter(|a| !a.is_empty()) {
+        let addr = addr
+            .parse()
+            .map_err(|_| anyhow!("invalid address {}", addr))?;
+        if !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+    }
+    Ok(addrs)
+}
+
+/// parse custom config from config.toml.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CustomConfig {
+    pub name: String,
+    pub proxy: bool,
+    pub mnemonic: String,
+    /// max layer requests per peer per minute, 0 is unlimited.
+    #[serde(default)]
+    pub rate_limit: u32,
+    #[serde(default)]
+    pub registration: RegisterPolicy,
+    /// storage backend.
+    #[serde(default)]
+    pub storage: Backend,
+    /// database settings.
+    #[serde(flatten)]
+    pub database: DatabaseConfig,
+    /// listen addresses & bootstrap peers.
+    #[serde(flatten)]
+    pub network: NetworkConfig,
+    /// max bytes of avatar upload.
+    #[serde(default = "default_avatar_max_size")]
+    pub avatar_max_size: usize,
+    /// at-rest encryption of avatars & bios.
+    #[serde(default)]
+    pub encryption: Encryption,
+    /// key file in the db_path, when encryption is "keyfile".
+    #[serde(default = "default_key_file")]
+    pub key_file: String,
+    /// current key version, increased by key rotation.
+    #[serde(default = "default_key_version")]
+    pub key_version: u32,
+    /// max users in the search cache, 0 is disabled.
+    #[serde(default = "default_cache_capacity")]
+    pub cache_capacity: usize,
+    /// seconds of the cached user.
+    #[serde(default = "default_cache_ttl")]
+    pub cache_ttl: u64,
+    /// days of deleted users kept before purge, 0 is never purge.
+    #[serde(default = "default_purge_retention")]
+    pub purge_retention: u64,
+    /// max layer requests handled at the same time.
+    #[serde(default = "default_layer_workers")]
+    pub layer_workers: usize,
+    /// token of the `subscribe` rpc, generated if empty.
+    #[serde(default)]
+    pub admin_token: String,
+}
+
+fn default_avatar_max_size() -> usize {
+    DEFAULT_AVATAR_MAX_SIZE
+}
+
+fn default_key_file() -> String {
+    DEFAULT_KEY_FILE.to_owned()
+}
+
+fn default_key_version() -> u32 {
+    1
+}
+
+fn default_cache_capacity() -> usize {
+    DEFAULT_CACHE_CAPACITY
+}
+
+fn default_cache_ttl() -> u64 {
+    DEFAULT_CACHE_TTL
+}
+
+fn default_purge_retention() -> u64 {
+    DEFAULT_PURGE_RETENTION
+}
+
+fn default_layer_workers() -> usize {
+    DEFAULT_LAYER_WORKERS
+}
+
+pub(crate) fn custom_config_str(config: &CustomConfig) -> String {
+    format!(
+        r#"## Domain custom Config.
+## domain server name.
+name = {}
+
+## domain is support proxy request/response.
+proxy = {}
+
+## domain server default mnemonic words (keep PeerId same).
+mnemonic = {}
+
+## max requests per peer per minute (0 is unlimited).
+rate_limit = {}
+
+## registration policy: "open" or "closed".
+registration = {}
+
+## storage backend: "postgres" (DATABASE_URL), "sqlite" (embedded in db path)
+## or "memory" (all lost when exit, for tests & ephemeral domains).
+storage = {}
+
+## database settings, can be overridden by env (e.g. DATABASE_URL).
+## postgres url, e.g. "postgres://postgres@localhost/domain".
+database_url = {}
+## max connections of the pool.
+database_max_connections = {}
+## seconds of waiting a connection.
+database_connect_timeout = {}
+## seconds of idle connection closed (0 is never).
+database_idle_timeout = {}
+## postgres TLS: "disable", "allow", "prefer", "require", "verify-ca",
+## "verify-full", or "" to use the mode in url.
+database_ssl_mode = {}
+## max prepared statements cached per connection.
+database_statement_cache = {}
+
+## network settings, can be overridden by env (e.g. DOMAIN_P2P_ADDR) and
+## command line (e.g. --p2p-addr).
+## P2P listen address.
+network_p2p_addr = {}
+## RPC listen address.
+network_rpc_addr = {}
+## bootstrap peers, e.g. ["1.2.3.4:7364"] (rpc: add-bootstrap, remove-bootstrap).
+network_bootstrap = {}
+## seconds of network lost (reconnecting the bootstrap peers) before exit
+## with failure, for the supervisor to restart (0 is never exit).
+network_lost_timeout = {}
+
+## max bytes of avatar upload (PNG/JPEG/WebP).
+avatar_max_size = {}
+
+## at-rest encryption of avatars & bios: "none", "mnemonic" (key derived
+## from the mnemonic) or "keyfile" (key derived from the key file).
+encryption = {}
+
+## key file in db path, generated if not exists.
+key_file = {}
+
+## current key version, increased by key rotation (rpc: rotate-key).
+key_version = {}
+
+## max users in the search cache (0 is disabled).
+cache_capacity = {}
+
+## seconds of the cached user.
+cache_ttl = {}
+
+## days of deleted users kept, then purged with their avatars & versions
+## (0 is never purge, rpc: purge-users).
+purge_retention = {}
+
+## max layer requests handled at the same time, requests of the same name
+## are always in order (rpc: stats).
+layer_workers = {}
+
+## token of subscribing the live events (rpc: subscribe), generated if empty.
+admin_token = {}
+"#,
+        toml_str(&config.name),
+        config.proxy,
+        toml_str(&config.mnemonic),
+        config.rate_limit,
+        toml_str(config.registration.to_str()),
+        toml_str(config.storage.to_str()),
+        toml_str(&config.database.database_url),
+        config.database.database_max_connections,
+        config.database.database_connect_timeout,
+        config.database.database_idle_timeout,
+        toml_str(&config.database.database_ssl_mode),
+        config.database.database_statement_cache,
+        toml_str(&config.network.network_p2p_addr.to_string()),
+        toml_str(&config.network.network_rpc_addr.to_string()),
+        toml_addrs(&config.network.network_bootstrap),
+        config.network.network_lost_timeout,
+        config.avatar_max_size,
+        toml_str(config.encryption.to_str()),
+        toml_str(&config.key_file),
+        config.key_version,
+        config.cache_capacity,
+        config.cache_ttl,
+        config.purge_retention,
+        config.layer_workers,
+        toml_str(&config.admin_token)
+    )
+}
+
+/// secret items of the config, (key, toml value).
+pub(crate) fn secret_items(config: &CustomConfig) -> Vec<(&'static str, String)> {
+    vec![
+        ("mnemonic", toml_str(&config.mnemonic)),
+        ("admin_token", toml_str(&config.admin_token)),
+    ]
+}
+
+/// the config content without the secret items.
+pub(crate) fn strip_secrets(content: &str) -> String {
+    let mut stripped = String::new();
+    for line in content.lines() {
+        let key = line.split('=').next().unwrap_or("").trim();
+        if !SECRET_ITEMS.contains(&key) {
+            stripped.push_str(line);
+            stripped.push('\n');
+        }
+    }
+    stripped
+}
+
+/// the file of the name in the folder of db_path, RPC cannot touch other files.
+pub(crate) async fn data_file(db_path: &PathBuf, folder: &str, name: &str) -> Result<PathBuf> {
+    let is_file_name = std::path::Path::new(name)
+        .file_name()
+        .and_then(|n| n.to_str())
+        == Some(name);
+    if !is_file_name || name.starts_with('.') {
+        return Err(anyhow!("invalid file name: {}", name));
+    }
+    let mut path = db_path.clone();
+    path.push(folder);
+    if !path.exists() {
+        fs::create_dir_all(&path).await?;
+    }
+    path.push(name);
+    Ok(path)
+}
+
+/// storage & database items of the config, (key, toml value).
+pub(crate) fn storage_items(config: &CustomConfig) -> Vec<(&'static str, String)> {
+    let db = &config.database;
+    vec![
+        ("storage", toml_str(config.storage.to_str())),
+        ("database_url", toml_str(&db.database_url)),
+        (
+            "database_max_connections",
+            db.database_max_connections.to_string(),
+        ),
+        (
+            "database_connect_timeout",
+            db.database_connect_timeout.to_string(),
+        ),
+        (
+            "database_idle_timeout",
+            db.database_idle_timeout.to_string(),
+        ),
+        ("database_ssl_mode", toml_str(&db.database_ssl_mode)),
+        (
+            "database_statement_cache",
+            db.database_statement_cache.to_string(),
+        ),
+    ]
+}
+
+/// random token of the admin rpc.
+pub(crate) fn generate_token() -> String {
+    hex::encode(rand::random::<[u8; 16]>())
+}
+
+/// TOML basic string.
+pub(crate) fn toml_str(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// TOML array of addresses.
+pub(crate) fn toml_addrs(addrs: &[SocketAddr]) -> String {
+    let items: Vec<String> = addrs.iter().map(|a| toml_str(&a.to_string())).collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// load the custom config in config.toml, none if not created yet.
+/// fails if it cannot be parsed, the caller never replaces it.
+pub(crate) async fn load_custom(db_path: &PathBuf) -> Result<Option<CustomConfig>> {
+    let mut path = db_path.clone();
+    path.push(CONFIG_FILE_NAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path).await?;
+    let table: toml::Value =
+        toml::from_str(&content).map_err(|e| anyhow!("invalid {:?}: {}", path, e))?;
+    // the custom items are appended after the TDN config when created.
+    if table.get("name").is_none() && table.get("mnemonic").is_none() {
+        return Ok(None);
+    }
+    let custom = table
+        .try_into()
+        .map_err(|e| anyhow!("invalid {:?}: {}", path, e))?;
+    Ok(Some(custom))
+}
+
+/// every write of config.toml, a write never loses the items of another.
+static CONFIG_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// save custom items (key, toml value) back to config.toml,
+/// replace the item lines in place, keep all comments and other items.
+pub(crate) async fn save_custom(db_path: &PathBuf, items: &[(&str, String)]) -> Result<()> {
+    let _guard = CONFIG_LOCK.lock().await;
+    let mut path = db_path.clone();
+    path.push(CONFIG_FILE_NAME);
+    let content = fs::read_to_string(&path).await.unwrap_or(String::new());
+    write_config(db_path, &merge_custom(&content, items)).await
+}
+
+/// replace config.toml by the restored content with the custom items,
+/// the merged content is written once.
+pub(crate) async fn restore_custom(
+    db_path: &PathBuf,
+    content: &str,
+    items: &[(&str, String)],
+) -> Result<()> {
+    let _guard = CONFIG_LOCK.lock().await;
+    write_config(db_path, &merge_custom(content, items)).await
+}
+
+/// write to a temp file, synced & renamed, a crash never leaves it half.
+async fn write_config(db_path: &PathBuf, content: &str) -> Result<()> {
+    let mut path = db_path.clone();
+    path.push(CONFIG_FILE_NAME);
+    let mut tmp = db_path.clone();
+    tmp.push(format!("{}.tmp", CONFIG_FILE_NAME));
+    let mut file = fs::File::create(&tmp).await?;
+    file.write_all(content.as_bytes()).await?;
+    file.sync_all().await?;
+    Ok(fs::rename(&tmp, &path).await?)
+}
+
+/// replace the item lines of the content, append the missing items.
+fn merge_custom(content: &str, items: &[(&str, String)]) -> String {
+    let mut lines: Vec<String> = content.lines().map(|l| l.to_owned()).collect();
+    for (key, value) in items {
+        let new_line = format!("{} = {}", key, value);
+        let index = lines.iter().position(|line| {
+            let line = line.trim_start();
+            !line.starts_with('#')
+                && line
+                    .split('=')
+                    .next()
+                    .map(|k| k.trim() == *key)
+                    .unwrap_or(false)
+        });
+        if let Some(index) = index {
+            lines[index] = new_line;
+        } else {
+            lines.push(String::new());
+            lines.push(new_line);
+        }
+    }
+
+    let mut content = lines.join("\n");
+    content.push('\n');
+    content
+}
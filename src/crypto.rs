@@ -0,0 +1,579 @@
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use once_cell::sync::OnceCell;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::RwLock;
+use tdn::types::primitives::Result;
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+
+/// default key file in the db_path.
+pub(crate) const DEFAULT_KEY_FILE: &'static str = "domain.key";
+/// keyring file in the db_path, random keys of the rotated versions.
+pub(crate) const KEYRING_FILE: &'static str = "domain.keyring";
+
+/// magic prefix of encrypted bytes (avatars).
+const BYTES_MAGIC: &'static [u8] = b"DENC";
+/// prefix of encrypted text (bios).
+const TEXT_PREFIX: &'static str = "enc:";
+/// prefix of plaintext looks like encrypted or escaped, e.g. a bio "enc:1:00".
+const RAW_PREFIX: &'static str = "raw:";
+const NONCE_LEN: usize = 12;
+
+/// at-rest encryption of avatars & profiles.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Encryption {
+    /// plaintext.
+    None,
+    /// key derived from the domain mnemonic.
+    Mnemonic,
+    /// key derived from the key file.
+    KeyFile,
+}
+
+impl Default for Encryption {
+    fn default() -> Self {
+        Encryption::None
+    }
+}
+
+impl Encryption {
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Encryption::None => "none",
+            Encryption::Mnemonic => "mnemonic",
+            Encryption::KeyFile => "keyfile",
+        }
+    }
+}
+
+/// Keys of rotated versions are random, saved in the keyring wrapped by the
+/// secret. keys of versions before the keyring are derived from the secret.
+/// new data is always encrypted by current version.
+struct Cipher {
+    secret: Vec<u8>,
+    keys: RwLock<HashMap<u32, [u8; 32]>>,
+    version: AtomicU32,
+    keyring: PathBuf,
+}
+
+impl Cipher {
+    fn cipher(&self, version: u32) -> Aes256Gcm {
+        if let Some(key) = self.keys.read().unwrap().get(&version) {
+            return Aes256Gcm::new(Key::from_slice(key));
+        }
+        let key = blake3::derive_key(
+            &format!("ESSE domain at-rest encryption key v{}", version),
+            &self.secret,
+        );
+        Aes256Gcm::new(Key::from_slice(&key))
+    }
+
+    /// key wraps the keys in the keyring.
+    fn keyring_cipher(&self) -> Aes256Gcm {
+        let key = blake3::derive_key("ESSE domain keyring key", &self.secret);
+        Aes256Gcm::new(Key::from_slice(&key))
+    }
+
+    /// keyring lines: version:hex(nonce | wrapped key).
+    fn keyring_lines(&self) -> Result<String> {
+        let keys = self.keys.read().unwrap();
+        let mut versions: Vec<&u32> = keys.keys().collect();
+        versions.sort();
+        let mut lines = String::new();
+        for version in versions {
+            let mut nonce = [0u8; NONCE_LEN];
+            rand::thread_rng().fill_bytes(&mut nonce);
+            let wrapped = self
+                .keyring_cipher()
+                .encrypt(Nonce::from_slice(&nonce), &keys[version][..])
+                .map_err(|_| anyhow!("encrypt failure"))?;
+            let mut bytes = nonce.to_vec();
+            bytes.extend(wrapped);
+            lines.push_str(&format!("{}:{}\n", version, hex::encode(bytes)));
+        }
+        Ok(lines)
+    }
+
+    /// unwrap the keys of the keyring.
+    fn load_keyring(&self, content: &str) -> Result<()> {
+        let mut keys = self.keys.write().unwrap();
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            let (version, data) = line
+                .trim()
+                .split_once(':')
+                .ok_or(anyhow!("invalid keyring"))?;
+            let version: u32 = version.parse().map_err(|_| anyhow!("invalid keyring"))?;
+            let bytes = hex::decode(data).map_err(|_| anyhow!("invalid keyring"))?;
+            if bytes.len() < NONCE_LEN {
+                return Err(anyhow!("invalid keyring"));
+            }
+            let (nonce, wrapped) = bytes.split_at(NONCE_LEN);
+            let key = self
+                .keyring_cipher()
+                .decrypt(Nonce::from_slice(nonce), wrapped)
+                .map_err(|_| anyhow!("keyring is not of the mnemonic or key file"))?;
+            if key.len() != 32 {
+                return Err(anyhow!("invalid keyring"));
+            }
+            let mut k = [0u8; 32];
+            k.copy_from_slice(&key);
+            keys.insert(version, k);
+        }
+        Ok(())
+    }
+}
+
+static CIPHER: OnceCell<Cipher> = OnceCell::new();
+/// rotations one by one, every one gets a new version.
+static ROTATE_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// load the secret, encryption is disabled if not init.
+/// a missing key file is generated only if `create_key` (first-time setup,
+/// nothing encrypted), else the encrypted data can not be read, it is error.
+pub(crate) async fn init(
+    encryption: Encryption,
+    mnemonic: &str,
+    key_file: &str,
+    version: u32,
+    base: &PathBuf,
+    create_key: bool,
+) -> Result<()> {
+    let secret = match encryption {
+        Encryption::None => return Ok(()),
+        Encryption::Mnemonic => mnemonic.as_bytes().to_vec(),
+        Encryption::KeyFile => {
+            let mut path = base.clone();
+            path.push(key_file);
+            if !path.exists() {
+                let mut keyring = base.clone();
+                keyring.push(KEYRING_FILE);
+                if !create_key || keyring.exists() {
+                    return Err(anyhow!(
+                        "key file {:?} missing, but the data is encrypted by it, restore it",
+                        path
+                    ));
+                }
+                save_key(&path).await?;
+                info!("Generated new key file {:?}", path);
+            }
+            let content = fs::read_to_string(&path).await?;
+            hex::decode(content.trim()).map_err(|_| anyhow!("key file is not hex"))?
+        }
+    };
+
+    let mut keyring = base.clone();
+    keyring.push(KEYRING_FILE);
+    let cipher = Cipher {
+        secret,
+        keys: RwLock::new(HashMap::new()),
+        version: AtomicU32::new(version),
+        keyring,
+    };
+    if cipher.keyring.exists() {
+        cipher.load_keyring(&fs::read_to_string(&cipher.keyring).await?)?;
+    }
+    // a rotation saved in the keyring but not in the config is still current.
+    let newest = cipher.keys.read().unwrap().keys().max().cloned();
+    if let Some(newest) = newest {
+        cipher.version.fetch_max(newest, Ordering::SeqCst);
+    }
+
+    CIPHER.set(cipher).map_err(|_| anyhow!("Cipher set error!"))
+}
+
+#[inline]
+pub(crate) fn is_enabled() -> bool {
+    CIPHER.get().is_some()
+}
+
+/// current key version.
+#[inline]
+pub(crate) fn version() -> u32 {
+    CIPHER
+        .get()
+        .map(|c| c.version.load(Ordering::SeqCst))
+        .unwrap_or(0)
+}
+
+/// use a new version of a random key, saved to the keyring before used,
+/// return the version.
+pub(crate) async fn rotate() -> Result<u32> {
+    let cipher = CIPHER.get().ok_or(anyhow!("encryption is disabled"))?;
+    let _guard = ROTATE_LOCK.lock().await;
+    let version = cipher.version.load(Ordering::SeqCst) + 1;
+    let mut key = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut key);
+    cipher.keys.write().unwrap().insert(version, key);
+
+    let saved = save_keyring(cipher).await;
+    if let Err(e) = saved {
+        cipher.keys.write().unwrap().remove(&version);
+        return Err(e);
+    }
+    cipher.version.store(version, Ordering::SeqCst);
+    Ok(version)
+}
+
+/// write the keyring atomically, synced before it is used.
+async fn save_keyring(cipher: &Cipher) -> Result<()> {
+    let lines = cipher.keyring_lines()?;
+    let mut tmp = cipher.keyring.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut file = fs::File::create(&tmp).await?;
+    file.write_all(lines.as_bytes()).await?;
+    file.sync_all().await?;
+    fs::rename(&tmp, &cipher.keyring).await?;
+    Ok(())
+}
+
+/// generate a random key file, only readable by the owner.
+async fn save_key(path: &PathBuf) -> Result<()> {
+    let mut key = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut key);
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&tmp)
+        .await?;
+    file.write_all(hex::encode(key).as_bytes()).await?;
+    file.sync_all().await?;
+    fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+/// key version of the encrypted bytes, 0 is plaintext.
+pub(crate) fn bytes_version(bytes: &[u8]) -> u32 {
+    if bytes.len() > BYTES_MAGIC.len() + 4 && bytes.starts_with(BYTES_MAGIC) {
+        let mut version = [0u8; 4];
+        version.copy_from_slice(&bytes[BYTES_MAGIC.len()..BYTES_MAGIC.len() + 4]);
+        u32::from_le_bytes(version)
+    } else {
+        0
+    }
+}
+
+/// key version of the encrypted text, 0 is plaintext.
+pub(crate) fn text_version(text: &str) -> u32 {
+    text.strip_prefix(TEXT_PREFIX)
+        .and_then(|s| s.split(':').next())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+/// encrypt by current key, bytes: magic | version | nonce | ciphertext.
+pub(crate) fn encrypt_bytes(bytes: &[u8]) -> Result<Vec<u8>> {
+    let cipher = match CIPHER.get() {
+        Some(c) => c,
+        None => return Ok(bytes.to_vec()),
+    };
+    if bytes.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let version = cipher.version.load(Ordering::SeqCst);
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = cipher
+        .cipher(version)
+        .encrypt(Nonce::from_slice(&nonce), bytes)
+        .map_err(|_| anyhow!("encrypt failure"))?;
+
+    let mut out = BYTES_MAGIC.to_vec();
+    out.extend(version.to_le_bytes());
+    out.extend(nonce);
+    out.extend(ciphertext);
+    Ok(out)
+}
+
+/// decrypt the bytes, plaintext is returned as it is.
+pub(crate) fn decrypt_bytes(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let version = bytes_version(&bytes);
+    if version == 0 {
+        return Ok(bytes);
+    }
+    let cipher = CIPHER.get().ok_or(anyhow!("encryption key missing"))?;
+
+    let start = BYTES_MAGIC.len() + 4;
+    if bytes.len() < start + NONCE_LEN {
+        return Err(anyhow!("invalid encrypted data"));
+    }
+    let (nonce, ciphertext) = bytes[start..].split_at(NONCE_LEN);
+    cipher
+        .cipher(version)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow!("decrypt failure"))
+}
+
+/// encrypt by current key, text: enc:version:hex(nonce | ciphertext).
+/// plaintext starts with the prefixes is escaped by `RAW_PREFIX`.
+pub(crate) fn encrypt_text(text: &str) -> Result<String> {
+    if text.is_empty() {
+        return Ok(String::new());
+    }
+    if !is_enabled() {
+        if text.starts_with(TEXT_PREFIX) || text.starts_with(RAW_PREFIX) {
+            return Ok(format!("{}{}", RAW_PREFIX, text));
+        }
+        return Ok(text.to_owned());
+    }
+    let bytes = encrypt_bytes(text.as_bytes())?;
+    let version = bytes_version(&bytes);
+    let start = BYTES_MAGIC.len() + 4;
+    Ok(format!(
+        "{}{}:{}",
+        TEXT_PREFIX,
+        version,
+        hex::encode(&bytes[start..])
+    ))
+}
+
+/// decrypt the text, plaintext is returned as it is.
+pub(crate) fn decrypt_text(text: String) -> Result<String> {
+    if let Some(raw) = text.strip_prefix(RAW_PREFIX) {
+        return Ok(raw.to_owned());
+    }
+    let version = text_version(&text);
+    if version == 0 {
+        return Ok(text);
+    }
+    let data = text
+        .splitn(3, ':')
+        .nth(2)
+        .ok_or(anyhow!("invalid encrypted data"))?;
+
+    let mut bytes = BYTES_MAGIC.to_vec();
+    bytes.extend(version.to_le_bytes());
+    bytes.extend(hex::decode(data).map_err(|_| anyhow!("invalid encrypted data"))?);
+    Ok(String::from_utf8(decrypt_bytes(bytes)?)?)
+}
+
+/// bytes of the password salt, random for every backup.
+pub(crate) const SALT_LEN: usize = 16;
+
+/// plaintext bytes of a sealed chunk.
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// tag bytes of the AES-GCM ciphertext.
+const TAG_LEN: usize = 16;
+
+/// random salt of the password key.
+#[inline]
+pub(crate) fn password_salt() -> [u8; SALT_LEN] {
+    rand::random()
+}
+
+/// key of the password & salt by argon2, for backups moved out of the domain.
+pub(crate) fn password_key(password: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("password key failure: {}", e))?;
+    Ok(key)
+}
+
+/// nonce of the chunk: counter | is last, chunks cannot be reordered or cut.
+#[inline]
+fn chunk_nonce(counter: u64, last: bool) -> [u8; NONCE_LEN] {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..8].copy_from_slice(&counter.to_be_bytes());
+    nonce[8] = last as u8;
+    nonce
+}
+
+#[inline]
+fn io_error(error: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// encrypt the stream by chunks of `CHUNK_LEN`, written as: length (u32) | ciphertext.
+pub(crate) struct SealWriter<W: Write> {
+    inner: W,
+    cipher: Aes256Gcm,
+    buffer: Vec<u8>,
+    counter: u64,
+}
+
+impl<W: Write> SealWriter<W> {
+    pub fn new(inner: W, key: &[u8; 32]) -> Self {
+        Self {
+            inner,
+            cipher: Aes256Gcm::new(Key::from_slice(key)),
+            buffer: Vec::with_capacity(CHUNK_LEN),
+            counter: 0,
+        }
+    }
+
+    fn write_chunk(&mut self, last: bool) -> io::Result<()> {
+        let nonce = chunk_nonce(self.counter, last);
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), &self.buffer[..])
+            .map_err(|_| io_error("encrypt failure"))?;
+        self.inner
+            .write_all(&(ciphertext.len() as u32).to_le_bytes())?;
+        self.inner.write_all(&ciphertext)?;
+        self.buffer.clear();
+        self.counter += 1;
+        Ok(())
+    }
+
+    /// seal the last chunk, return the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_chunk(true)?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for SealWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(CHUNK_LEN - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == CHUNK_LEN {
+            self.write_chunk(false)?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// decrypt the stream of `SealWriter`, fails if the password is wrong,
+/// or the chunks changed or cut.
+pub(crate) struct UnsealReader<R: Read> {
+    inner: R,
+    cipher: Aes256Gcm,
+    chunk: Vec<u8>,
+    pos: usize,
+    counter: u64,
+    /// length of the next chunk, read ahead to know the last chunk.
+    next: Option<usize>,
+    done: bool,
+}
+
+impl<R: Read> UnsealReader<R> {
+    pub fn new(inner: R, key: &[u8; 32]) -> Self {
+        Self {
+            inner,
+            cipher: Aes256Gcm::new(Key::from_slice(key)),
+            chunk: vec![],
+            pos: 0,
+            counter: 0,
+            next: None,
+            done: false,
+        }
+    }
+
+    /// length of the next chunk, none if the stream ends.
+    fn read_len(&mut self) -> io::Result<Option<usize>> {
+        let mut bytes = [0u8; 4];
+        let mut read = 0;
+        while read < bytes.len() {
+            match self.inner.read(&mut bytes[read..])? {
+                0 if read == 0 => return Ok(None),
+                0 => return Err(io_error("encrypted data is truncated")),
+                n => read += n,
+            }
+        }
+        let len = u32::from_le_bytes(bytes) as usize;
+        if len < TAG_LEN || len > CHUNK_LEN + TAG_LEN {
+            return Err(io_error("invalid encrypted data"));
+        }
+        Ok(Some(len))
+    }
+
+    fn read_chunk(&mut self) -> io::Result<()> {
+        let len = match self.next.take() {
+            Some(len) => len,
+            None => self
+                .read_len()?
+                .ok_or_else(|| io_error("encrypted data is truncated"))?,
+        };
+        let mut ciphertext = vec![0u8; len];
+        self.inner.read_exact(&mut ciphertext)?;
+        self.next = self.read_len()?;
+
+        let last = self.next.is_none();
+        let nonce = chunk_nonce(self.counter, last);
+        self.chunk = self
+            .cipher
+            .decrypt(Nonce::from_slice(&nonce), &ciphertext[..])
+            .map_err(|_| io_error("decrypt failure, wrong password or data changed"))?;
+        self.pos = 0;
+        self.counter += 1;
+        self.done = last;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for UnsealReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.read_chunk()?;
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn encrypt_rotate_decrypt() {
+        let base = std::env::temp_dir().join(format!("domain-crypto-{}", rand::random::<u32>()));
+        fs::create_dir_all(&base).await.unwrap();
+
+        // the key file is not generated when the data is encrypted.
+        let missing = init(Encryption::KeyFile, "", "domain.key", 1, &base, false).await;
+        assert!(missing.is_err() && !is_enabled());
+
+        init(Encryption::Mnemonic, "test mnemonic", "", 1, &base, false)
+            .await
+            .unwrap();
+        let text = encrypt_text("bio").unwrap();
+        let bytes = encrypt_bytes(b"avatar").unwrap();
+        assert_eq!(text_version(&text), 1);
+        assert_eq!(bytes_version(&bytes), 1);
+
+        let version = rotate().await.unwrap();
+        assert_eq!(version, 2);
+        let rotated = encrypt_text("bio").unwrap();
+        assert_eq!(text_version(&rotated), 2);
+        assert_eq!(decrypt_text(text).unwrap(), "bio");
+        assert_eq!(decrypt_text(rotated).unwrap(), "bio");
+        assert_eq!(decrypt_bytes(bytes).unwrap(), b"avatar");
+        assert!(decrypt_text("enc:2:00".to_owned()).is_err());
+
+        let _ = fs::remove_dir_all(&base).await;
+    }
+
+    #[tokio::test]
+    async fn key_file_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("domain-key-{}", rand::random::<u32>()));
+        save_key(&path).await.unwrap();
+        let mode = fs::metadata(&path).await.unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(fs::read_to_string(&path).await.unwrap().len(), 64);
+        let _ = fs::remove_file(&path).await;
+    }
+}
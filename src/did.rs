@@ -0,0 +1,303 @@
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tdn::types::primitives::{PeerId, Result};
+
+/// DID method of ESSE users, `did:esse:{peer id hex}`.
+pub(crate) const DID_PREFIX: &'static str = "did:esse:";
+
+/// max bytes of the document.
+pub(crate) const MAX_DOCUMENT_SIZE: usize = 16 * 1024;
+
+/// max keys & services of the document.
+const MAX_KEYS: usize = 16;
+const MAX_SERVICES: usize = 8;
+
+/// key types, signing keys verify the document updates.
+pub(crate) const SIGNING_KEY_TYPE: &'static str = "Ed25519VerificationKey2020";
+pub(crate) const AGREEMENT_KEY_TYPE: &'static str = "X25519KeyAgreementKey2020";
+
+/// public key of the user devices.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct VerificationMethod {
+    /// `{did}#{name}`.
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// hex of 32 bytes public key.
+    pub public_key_hex: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Service {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub service_endpoint: String,
+}
+
+/// DID document of the user, signed by the owner device.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DidDocument {
+    pub id: String,
+    pub verification_method: Vec<VerificationMethod>,
+    /// ids of the signing keys.
+    #[serde(default)]
+    pub authentication: Vec<String>,
+    /// ids of the encryption keys.
+    #[serde(default)]
+    pub key_agreement: Vec<String>,
+    #[serde(default)]
+    pub service: Vec<Service>,
+    /// increased by every update, older documents are rejected.
+    pub sequence: i64,
+}
+
+impl DidDocument {
+    /// parse & check the document of the peer.
+    pub fn parse(bytes: &[u8], pid: &PeerId) -> Result<Self> {
+        if bytes.len() > MAX_DOCUMENT_SIZE {
+            return Err(anyhow!(
+                "did document too large: {} > {} bytes",
+                bytes.len(),
+                MAX_DOCUMENT_SIZE
+            ));
+        }
+        let doc: DidDocument = serde_json::from_slice(bytes)?;
+
+        if doc.id != did(pid) {
+            return Err(anyhow!("did {} is not the peer", doc.id));
+        }
+        if doc.verification_method.is_empty() || doc.verification_method.len() > MAX_KEYS {
+            return Err(anyhow!("did document needs 1-{} keys", MAX_KEYS));
+        }
+        if doc.service.len() > MAX_SERVICES {
+            return Err(anyhow!("did document max {} services", MAX_SERVICES));
+        }
+
+        // fragments of the document, `{did}#{name}`.
+        let fragment = format!("{}#", doc.id);
+        let mut ids = HashSet::new();
+        for key in doc.verification_method.iter() {
+            if !key.id.starts_with(&fragment) || !ids.insert(key.id.as_str()) {
+                return Err(anyhow!("invalid key id {}", key.id));
+            }
+            if key.kind != SIGNING_KEY_TYPE && key.kind != AGREEMENT_KEY_TYPE {
+                return Err(anyhow!("unsupported key type {}", key.kind));
+            }
+            let bytes = hex::decode(&key.public_key_hex)
+                .map_err(|_| anyhow!("key {} is not hex", key.id))?;
+            if bytes.len() != 32 {
+                return Err(anyhow!("key {} is not 32 bytes", key.id));
+            }
+        }
+        for id in doc.authentication.iter() {
+            if doc.key(id, SIGNING_KEY_TYPE).is_none() {
+                return Err(anyhow!("authentication {} is not a signing key", id));
+            }
+        }
+        for id in doc.key_agreement.iter() {
+            if doc.key(id, AGREEMENT_KEY_TYPE).is_none() {
+                return Err(anyhow!("key agreement {} is not an encryption key", id));
+            }
+        }
+        if doc.signing_keys().next().is_none() {
+            return Err(anyhow!("did document needs a signing key"));
+        }
+        for service in doc.service.iter() {
+            if !service.id.starts_with(&fragment) || service.service_endpoint.is_empty() {
+                return Err(anyhow!("invalid service {}", service.id));
+            }
+        }
+
+        Ok(doc)
+    }
+
+    fn key(&self, id: &str, kind: &str) -> Option<&VerificationMethod> {
+        self.verification_method
+            .iter()
+            .find(|k| k.id == id && k.kind == kind)
+    }
+
+    /// keys can sign updates, authentication keys, or all signing keys if empty.
+    fn signing_keys(&self) -> impl Iterator<Item = &VerificationMethod> {
+        self.verification_method.iter().filter(move |k| {
+            k.kind == SIGNING_KEY_TYPE
+                && (self.authentication.is_empty() || self.authentication.contains(&k.id))
+        })
+    }
+
+    /// check the signature of the document bytes by the signing key.
+    pub fn verify(&self, key_id: &str, bytes: &[u8], signature: &[u8]) -> Result<()> {
+        let key = self
+            .signing_keys()
+            .find(|k| k.id == key_id)
+            .ok_or(anyhow!("{} is not a signing key", key_id))?;
+        let public = hex::decode(&key.public_key_hex)?;
+        let public = PublicKey::from_bytes(&public).map_err(|_| anyhow!("invalid key"))?;
+        let signature = Signature::try_from(signature).map_err(|_| anyhow!("invalid signature"))?;
+        public
+            .verify(bytes, &signature)
+            .map_err(|_| anyhow!("did document signature mismatch"))
+    }
+}
+
+/// DID of the peer.
+#[inline]
+pub(crate) fn did(pid: &PeerId) -> String {
+    format!("{}{}", DID_PREFIX, pid.to_hex())
+}
+
+/// check the signed update, the first document is self-signed, then every
+/// update must be signed by a key of the current document, with a larger sequence.
+pub(crate) fn check_update(
+    current: Option<&DidDocument>,
+    new: &DidDocument,
+    bytes: &[u8],
+    key_id: &str,
+    signature: &[u8],
+) -> Result<()> {
+    match current {
+        Some(current) => {
+            if new.sequence <= current.sequence {
+                return Err(anyhow!(
+                    "did document sequence {} is not after {}",
+                    new.sequence,
+                    current.sequence
+                ));
+            }
+            current.verify(key_id, bytes, signature)
+        }
+        None => new.verify(key_id, bytes, signature),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{ExpandedSecretKey, SecretKey};
+
+    /// the device key of the seed.
+    fn keypair(seed: u8) -> (ExpandedSecretKey, PublicKey) {
+        let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        (ExpandedSecretKey::from(&secret), public)
+    }
+
+    /// document of the signing keys, ids are `{did}#key-{seed}`.
+    fn document(pid: &PeerId, seeds: &[u8], sequence: i64) -> Vec<u8> {
+        let id = did(pid);
+        let keys: Vec<_> = seeds
+            .iter()
+            .map(|seed| {
+                serde_json::json!({
+                    "id": format!("{}#key-{}", id, seed),
+                    "type": SIGNING_KEY_TYPE,
+                    "publicKeyHex": hex::encode(keypair(*seed).1.as_bytes()),
+                })
+            })
+            .collect();
+        serde_json::to_vec(&serde_json::json!({
+            "id": id,
+            "verificationMethod": keys,
+            "sequence": sequence,
+        }))
+        .unwrap()
+    }
+
+    fn sign(bytes: &[u8], seed: u8) -> Vec<u8> {
+        let (secret, public) = keypair(seed);
+        secret.sign(bytes, &public).to_bytes().to_vec()
+    }
+
+    #[test]
+    fn parse_document() {
+        let pid = PeerId([1; 32]);
+        let doc = DidDocument::parse(&document(&pid, &[1, 2], 1), &pid).unwrap();
+        assert_eq!(doc.id, did(&pid));
+        assert_eq!(doc.verification_method.len(), 2);
+
+        // not the document of the peer.
+        assert!(DidDocument::parse(&document(&pid, &[1], 1), &PeerId([2; 32])).is_err());
+        // no keys.
+        assert!(DidDocument::parse(&document(&pid, &[], 1), &pid).is_err());
+        assert!(DidDocument::parse(b"not json", &pid).is_err());
+        assert!(DidDocument::parse(" ".repeat(MAX_DOCUMENT_SIZE + 1).as_bytes(), &pid).is_err());
+    }
+
+    #[test]
+    fn parse_invalid_keys() {
+        let pid = PeerId([1; 32]);
+        let id = did(&pid);
+        let with_key = |key: serde_json::Value| {
+            let doc = serde_json::json!({
+                "id": id,
+                "verificationMethod": [key],
+                "sequence": 1,
+            });
+            DidDocument::parse(&serde_json::to_vec(&doc).unwrap(), &pid)
+        };
+        let public = hex::encode([7u8; 32]);
+
+        // key id outside the document.
+        assert!(with_key(serde_json::json!({
+            "id": "did:esse:other#key",
+            "type": SIGNING_KEY_TYPE,
+            "publicKeyHex": public,
+        }))
+        .is_err());
+        // not 32 bytes.
+        assert!(with_key(serde_json::json!({
+            "id": format!("{}#key", id),
+            "type": SIGNING_KEY_TYPE,
+            "publicKeyHex": "0102",
+        }))
+        .is_err());
+        // unsupported type.
+        assert!(with_key(serde_json::json!({
+            "id": format!("{}#key", id),
+            "type": "RsaVerificationKey2018",
+            "publicKeyHex": public,
+        }))
+        .is_err());
+        // no signing key.
+        assert!(with_key(serde_json::json!({
+            "id": format!("{}#key", id),
+            "type": AGREEMENT_KEY_TYPE,
+            "publicKeyHex": public,
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn update_signed() {
+        let pid = PeerId([1; 32]);
+        let key = |seed: u8| format!("{}#key-{}", did(&pid), seed);
+
+        // the first is self-signed.
+        let bytes = document(&pid, &[1], 1);
+        let first = DidDocument::parse(&bytes, &pid).unwrap();
+        assert!(check_update(None, &first, &bytes, &key(1), &sign(&bytes, 1)).is_ok());
+        assert!(check_update(None, &first, &bytes, &key(1), &sign(&bytes, 2)).is_err());
+        assert!(check_update(None, &first, &bytes, &key(2), &sign(&bytes, 2)).is_err());
+
+        // the update adding key 2, signed by the current key 1.
+        let bytes = document(&pid, &[1, 2], 2);
+        let second = DidDocument::parse(&bytes, &pid).unwrap();
+        assert!(check_update(Some(&first), &second, &bytes, &key(1), &sign(&bytes, 1)).is_ok());
+        // key 2 is not in the current document yet.
+        assert!(check_update(Some(&first), &second, &bytes, &key(2), &sign(&bytes, 2)).is_err());
+        // signed bytes changed.
+        let mut changed = bytes.clone();
+        changed.push(b' ');
+        assert!(check_update(Some(&first), &second, &changed, &key(1), &sign(&bytes, 1)).is_err());
+
+        // the sequence must increase.
+        let bytes = document(&pid, &[1], 2);
+        let replay = DidDocument::parse(&bytes, &pid).unwrap();
+        assert!(check_update(Some(&second), &replay, &bytes, &key(1), &sign(&bytes, 1)).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tdn::types::{
+    primitives::Result,
+    rpc::{json, RpcParam},
+};
+use tokio::sync::{oneshot, OwnedSemaphorePermit, Semaphore};
+
+use crate::shutdown;
+
+/// default max layer requests handled at the same time.
+pub(crate) const DEFAULT_LAYER_WORKERS: usize = 32;
+
+/// requests dispatched but not finished, times of workers.
+const QUEUE_FACTOR: usize = 4;
+
+/// clean the finished keys when so many keys kept.
+const CLEAN_KEYS: usize = 1024;
+
+/// layer requests stats.
+struct Metrics {
+    workers: AtomicUsize,
+    /// requests handling now.
+    running: AtomicUsize,
+    /// requests dispatched to workers.
+    dispatched: AtomicU64,
+    /// requests finished, and the failed ones.
+    completed: AtomicU64,
+    failed: AtomicU64,
+    /// requests waiting the previous request of the same name.
+    ordered: AtomicU64,
+    /// times of all workers busy, receiving paused.
+    saturated: AtomicU64,
+    /// microseconds of handling, total & max.
+    total_micros: AtomicU64,
+    max_micros: AtomicU64,
+}
+
+static METRICS: Lazy<Metrics> = Lazy::new(|| Metrics {
+    workers: AtomicUsize::new(0),
+    running: AtomicUsize::new(0),
+    dispatched: AtomicU64::new(0),
+    completed: AtomicU64::new(0),
+    failed: AtomicU64::new(0),
+    ordered: AtomicU64::new(0),
+    saturated: AtomicU64::new(0),
+    total_micros: AtomicU64::new(0),
+    max_micros: AtomicU64::new(0),
+});
+
+/// a layer request waiting the room of the queue.
+type Work = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+/// dispatch the layer requests to tokio tasks, requests of the same key are
+/// handled in arrival order. at most `workers` requests are running, a request
+/// waiting its previous one holds no worker. when too many requests queued,
+/// requests are kept pending (at most `workers`), then the receiver pauses,
+/// so slows down the peers.
+pub(crate) struct Dispatcher {
+    workers: Arc<Semaphore>,
+    queue: Arc<Semaphore>,
+    /// received requests not dispatched, in arrival order.
+    pending: VecDeque<(Option<String>, Work)>,
+    max_pending: usize,
+    /// the pending head counted as saturated.
+    saturated: bool,
+    /// the last request of the key, finished when its sender is dropped.
+    tails: HashMap<String, oneshot::Receiver<()>>,
+}
+
+impl Dispatcher {
+    /// workers 0 is 1.
+    pub(crate) fn new(workers: usize) -> Self {
+        let workers = workers.max(1);
+        METRICS.workers.store(workers, Ordering::Relaxed);
+        Self {
+            workers: Arc::new(Semaphore::new(workers)),
+            queue: Arc::new(Semaphore::new(workers * QUEUE_FACTOR)),
+            pending: VecDeque::new(),
+            max_pending: workers,
+            saturated: false,
+            tails: HashMap::new(),
+        }
+    }
+
+    /// keep the request, dispatched by `run` in arrival order.
+    pub(crate) fn push<F>(&mut self, key: Option<String>, work: F)
+    where
+        F: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.pending.push_back((key, Box::pin(work)));
+    }
+
+    /// requests pending.
+    #[inline]
+    pub(crate) fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// too many requests pending, stop receiving.
+    #[inline]
+    pub(crate) fn is_full(&self) -> bool {
+        self.pending.len() >= self.max_pending
+    }
+
+    /// dispatch the pending requests, wait the room of the queue.
+    /// cancel safe, the request waiting the room is kept pending.
+    pub(crate) async fn run(&mut self) {
+        while !self.pending.is_empty() {
+            let queued = match self.queue.clone().try_acquire_owned() {
+                Ok(queued) => queued,
+                Err(_) => {
+                    if !self.saturated {
+                        self.saturated = true;
+                        METRICS.saturated.fetch_add(1, Ordering::Relaxed);
+                    }
+                    self.queue
+                        .clone()
+                        .acquire_owned()
+                        .await
+                        .expect("dispatcher queue closed")
+                }
+            };
+            self.saturated = false;
+            if let Some((key, work)) = self.pending.pop_front() {
+                self.dispatch(queued, key, work);
+            }
+        }
+    }
+
+    fn dispatch(&mut self, queued: OwnedSemaphorePermit, key: Option<String>, work: Work) {
+        // in-flight, drained when the domain stopping.
+        let guard = match shutdown::begin() {
+            Some(guard) => guard,
+            None => return,
+        };
+
+        let (done, prev) = match key {
+            Some(key) => {
+                if self.tails.len() >= CLEAN_KEYS {
+                    self.clean();
+                }
+                let (done, tail) = oneshot::channel::<()>();
+                (Some(done), self.tails.insert(key, tail))
+            }
+            None => (None, None),
+        };
+        METRICS.dispatched.fetch_add(1, Ordering::Relaxed);
+
+        let workers = self.workers.clone();
+        tokio::spawn(async move {
+            let _queued = queued;
+            let _guard = guard;
+            let _done = done;
+            if let Some(prev) = prev {
+                METRICS.ordered.fetch_add(1, Ordering::Relaxed);
+                // error is the previous finished.
+                let _ = prev.await;
+            }
+            // the worker taken after the previous finished, not blocking others.
+            let _permit = workers
+                .acquire_owned()
+                .await
+                .expect("dispatcher workers closed");
+
+            METRICS.running.fetch_add(1, Ordering::Relaxed);
+            let start = Instant::now();
+            let res = work.await;
+            let micros = start.elapsed().as_micros() as u64;
+            METRICS.running.fetch_sub(1, Ordering::Relaxed);
+
+            METRICS.completed.fetch_add(1, Ordering::Relaxed);
+            if res.is_err() {
+                METRICS.failed.fetch_add(1, Ordering::Relaxed);
+            }
+            METRICS.total_micros.fetch_add(micros, Ordering::Relaxed);
+            METRICS.max_micros.fetch_max(micros, Ordering::Relaxed);
+        });
+    }
+
+    /// remove the keys whose last request finished.
+    fn clean(&mut self) {
+        self.tails
+            .retain(|_, tail| matches!(tail.try_recv(), Err(oneshot::error::TryRecvError::Empty)));
+    }
+}
+
+/// workers & requests stats.
+pub(crate) fn stats() -> RpcParam {
+    let dispatched = METRICS.dispatched.load(Ordering::Relaxed);
+    let completed = METRICS.completed.load(Ordering::Relaxed);
+    let running = METRICS.running.load(Ordering::Relaxed) as u64;
+    let total = METRICS.total_micros.load(Ordering::Relaxed);
+    let avg = if completed > 0 { total / completed } else { 0 };
+    json!({
+        "workers": METRICS.workers.load(Ordering::Relaxed),
+        "running": running,
+        // waiting the previous request of the same name, or a worker.
+        "queued": dispatched.saturating_sub(completed + running),
+        "dispatched": dispatched,
+        "completed": completed,
+        "failed": METRICS.failed.load(Ordering::Relaxed),
+        "ordered": METRICS.ordered.load(Ordering::Relaxed),
+        "saturated": METRICS.saturated.load(Ordering::Relaxed),
+        "avg_micros": avg,
+        "max_micros": METRICS.max_micros.load(Ordering::Relaxed),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    /// wait the handled requests, in finished order.
+    async fn handled(finished: &mut mpsc::UnboundedReceiver<u64>, count: usize) -> Vec<u64> {
+        let mut handled = vec![];
+        for _ in 0..count {
+            let i = tokio::time::timeout(Duration::from_secs(5), finished.recv())
+                .await
+                .expect("request not finished")
+                .unwrap();
+            handled.push(i);
+        }
+        handled
+    }
+
+    #[tokio::test]
+    async fn same_key_in_order() {
+        let mut dispatcher = Dispatcher::new(4);
+        let (done, mut finished) = mpsc::unbounded_channel();
+        for i in 0..8u64 {
+            let done = done.clone();
+            dispatcher.push(Some("alice".to_owned()), async move {
+                // the earlier slower, not finished first if unordered.
+                tokio::time::sleep(Duration::from_millis(40 - i * 5)).await;
+                let _ = done.send(i);
+                Ok(())
+            });
+        }
+        dispatcher.run().await;
+
+        assert_eq!(handled(&mut finished, 8).await, (0..8).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn waiting_takes_no_worker() {
+        let mut dispatcher = Dispatcher::new(2);
+        let (open, gate) = oneshot::channel::<()>();
+        let (done, finished) = oneshot::channel::<()>();
+
+        // alice holds a worker until the gate opened.
+        dispatcher.push(Some("alice".to_owned()), async move {
+            let _ = gate.await;
+            Ok(())
+        });
+        // waiting the previous alice, the last worker is free.
+        dispatcher.push(Some("alice".to_owned()), async { Ok(()) });
+        dispatcher.push(Some("bob".to_owned()), async move {
+            let _ = done.send(());
+            Ok(())
+        });
+        dispatcher.run().await;
+
+        let res = tokio::time::timeout(Duration::from_secs(5), finished).await;
+        let _ = open.send(());
+        assert!(res.is_ok(), "bob blocked by the waiting alice");
+    }
+
+    #[tokio::test]
+    async fn saturated_keeps_pending() {
+        let mut dispatcher = Dispatcher::new(1);
+        let gate = Arc::new(Semaphore::new(0));
+        let (done, mut finished) = mpsc::unbounded_channel();
+        let count = QUEUE_FACTOR as u64 + 2;
+        for i in 0..count {
+            let (gate, done) = (gate.clone(), done.clone());
+            dispatcher.push(None, async move {
+                let _ = gate.acquire().await;
+                let _ = done.send(i);
+                Ok(())
+            });
+        }
+
+        // the queue is full, waiting is cancelled, the rest kept.
+        let run = tokio::time::timeout(Duration::from_millis(50), dispatcher.run()).await;
+        assert!(run.is_err());
+        assert_eq!(dispatcher.pending(), 2);
+
+        gate.add_permits(count as usize);
+        dispatcher.run().await;
+        assert_eq!(dispatcher.pending(), 0);
+        let mut handled = handled(&mut finished, count as usize).await;
+        handled.sort();
+        assert_eq!(handled, (0..count).collect::<Vec<_>>());
+    }
+}
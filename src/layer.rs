@@ -1,13 +1,27 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
 use tdn::types::{
     group::GroupId,
     message::{RecvType, SendType},
     primitives::{HandleResult, PeerId, Result},
+    rpc::RpcParam,
 };
 
 use domain_types::{LayerPeerEvent, LayerServerEvent};
 
-use crate::models::User;
+use crate::avatar::normalize;
+use crate::config::{CustomConfig, NetworkConfig, RegisterPolicy};
+use crate::models::{now, Attribute, Did, Report, User, Version};
+use crate::profile::validate;
+use crate::protocol::{add_ext_layer, ExtPeerEvent, ExtServerEvent};
+use crate::rpc::{
+    event_active, event_delete, event_error, event_register, event_report, event_update,
+};
+use crate::storage::{name_key, Db, NameConflict};
+
+/// seconds of the live events subscription, renewed by subscribing again.
+pub(crate) const SUBSCRIBE_TTL: i64 = 600;
 
 /// Domain server to peer.
 #[inline]
@@ -23,29 +37,136 @@ pub(crate) fn add_server_layer(
     Ok(())
 }
 
+/// key of the request, requests changing the same name are handled in order,
+/// none is unordered, e.g. the read-only requests.
+pub(crate) fn order_key(msg: &RecvType) -> Option<String> {
+    let bytes = match msg {
+        RecvType::Event(_, bytes) => bytes,
+        _ => return None,
+    };
+    let name = if let Some(event) = ExtPeerEvent::decode(bytes) {
+        match event.ok()? {
+            ExtPeerEvent::Avatar(..)
+            | ExtPeerEvent::Profile(..)
+            | ExtPeerEvent::Versions(..)
+            | ExtPeerEvent::ResolveDid(..) => return None,
+            event => event.name().to_owned(),
+        }
+    } else {
+        match bincode::deserialize(bytes).ok()? {
+            LayerPeerEvent::Register(name, _, _)
+            | LayerPeerEvent::Update(name, _, _)
+            | LayerPeerEvent::Suspend(name)
+            | LayerPeerEvent::Active(name)
+            | LayerPeerEvent::Delete(name) => name,
+            _ => return None,
+        }
+    };
+    // the name key of the unique index, same on all backends.
+    Some(name_key(name))
+}
+
 pub(crate) struct Layer {
-    pub base: PathBuf,
+    pub db: Db,
     pub name: String,
     pub pid: PeerId,
     pub proxy: bool,
+    /// max requests per peer per minute, 0 is unlimited.
+    pub rate_limit: u32,
+    pub registration: RegisterPolicy,
+    /// max bytes of avatar upload.
+    pub avatar_max_size: usize,
+    /// days of deleted users kept before purge.
+    pub purge_retention: u64,
+    /// bootstrap peers of the network, the saved overridden by env & command line.
+    pub bootstrap: Vec<SocketAddr>,
+    /// bootstrap peers saved in config.toml.
+    pub saved_bootstrap: Vec<SocketAddr>,
+    /// token of subscribing the live events.
+    pub admin_token: String,
+    /// websocket connections subscribed to live events, with the expired time.
+    subscribers: Mutex<HashMap<u64, i64>>,
+    /// live events waiting to push to subscribers.
+    events: Mutex<Vec<RpcParam>>,
+    /// (current minute, requests count of peers in it).
+    rates: Mutex<(i64, HashMap<PeerId, u32>)>,
 }
 
 impl Layer {
+    /// the network is the config overridden by env & command line.
     pub(crate) async fn new(
-        base: PathBuf,
-        name: String,
+        db: Db,
+        config: &CustomConfig,
+        network: &NetworkConfig,
         pid: PeerId,
-        proxy: bool,
     ) -> Result<Layer> {
         Ok(Layer {
-            base,
-            name,
+            db,
             pid,
-            proxy,
+            name: config.name.clone(),
+            proxy: config.proxy,
+            rate_limit: config.rate_limit,
+            registration: config.registration,
+            avatar_max_size: config.avatar_max_size,
+            purge_retention: config.purge_retention,
+            bootstrap: network.network_bootstrap.clone(),
+            saved_bootstrap: config.network.network_bootstrap.clone(),
+            admin_token: config.admin_token.clone(),
+            subscribers: Mutex::new(HashMap::new()),
+            events: Mutex::new(vec![]),
+            rates: Mutex::new((0, HashMap::new())),
         })
     }
 
-    pub(crate) async fn handle(&mut self, fgid: GroupId, msg: RecvType) -> Result<HandleResult> {
+    /// subscribe or renew the websocket connection, expired after `SUBSCRIBE_TTL`.
+    pub(crate) fn subscribe(&self, uid: u64) -> bool {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.insert(uid, now() + SUBSCRIBE_TTL);
+        true
+    }
+
+    pub(crate) fn unsubscribe(&self, uid: u64) -> bool {
+        self.subscribers.lock().unwrap().remove(&uid).is_some()
+    }
+
+    /// the subscribed connections, the expired (closed and not renewed) removed.
+    pub(crate) fn subscribers(&self) -> Vec<u64> {
+        let now = now();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|_, expired| *expired > now);
+        subscribers.keys().copied().collect()
+    }
+
+    /// add a live event, pushed to subscribers after the request handled.
+    pub(crate) fn event(&self, event: RpcParam) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    /// take the live events waiting to push.
+    pub(crate) fn take_events(&self) -> Vec<RpcParam> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    /// count the peer request, return false if over the rate limit.
+    fn check_rate(&self, addr: &PeerId) -> bool {
+        if self.rate_limit == 0 {
+            return true;
+        }
+
+        let minute = now() / 60;
+        let mut rates = self.rates.lock().unwrap();
+        let (rates_minute, counts) = &mut *rates;
+        if minute != *rates_minute {
+            *rates_minute = minute;
+            counts.clear();
+        }
+
+        let count = counts.entry(*addr).or_insert(0);
+        *count += 1;
+        *count <= self.rate_limit
+    }
+
+    pub(crate) async fn handle(&self, fgid: GroupId, msg: RecvType) -> Result<HandleResult> {
         let mut results = HandleResult::new();
 
         match msg {
@@ -57,91 +178,528 @@ impl Layer {
                 info!("domain message nerver to here.")
             }
             RecvType::Event(addr, bytes) => {
-                // server & client handle it.
-                let event: LayerPeerEvent = bincode::deserialize(&bytes)?;
+                if !self.check_rate(&addr) {
+                    warn!("peer {} is over the rate limit.", addr.to_hex());
+                    self.event(event_error("rate limit"));
+                    return Ok(results);
+                }
 
                 // TODO check proof. date if from fgid.
 
-                match event {
-                    LayerPeerEvent::Check => {
-                        let status = LayerServerEvent::Status(self.name.clone(), self.proxy);
+                // server & client handle it.
+                if let Some(event) = ExtPeerEvent::decode(&bytes) {
+                    self.handle_ext(&mut results, fgid, addr, event?).await?;
+                } else {
+                    let event: LayerPeerEvent = bincode::deserialize(&bytes)?;
+                    self.handle_event(&mut results, fgid, addr, event).await?;
+                }
+            }
+            RecvType::Delivery(_t, _tid, _is_ok) => {
+                // MAYBE
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// requests of `domain_types`.
+    async fn handle_event(
+        &self,
+        results: &mut HandleResult,
+        fgid: GroupId,
+        addr: PeerId,
+        event: LayerPeerEvent,
+    ) -> Result<()> {
+        match event {
+            LayerPeerEvent::Check => {
+                let status = LayerServerEvent::Status(self.name.clone(), self.proxy);
 
-                        add_server_layer(&mut results, addr, status, fgid)?;
-                        println!("------ DEBUG DOMAIN SERVICE IS OK");
+                add_server_layer(results, addr, status, fgid)?;
+                println!("------ DEBUG DOMAIN SERVICE IS OK");
+            }
+            LayerPeerEvent::Search(name) => {
+                if let Ok(user) = User::search(&self.db, &name).await {
+                    add_server_layer(results, addr, user.to_info(), fgid)?;
+                } else {
+                    add_server_layer(results, addr, LayerServerEvent::None(name), fgid)?;
+                }
+            }
+            LayerPeerEvent::Register(name, bio, avatar) => {
+                match self.register(addr, &name, bio, avatar, None).await? {
+                    Ok(is_ok) => {
+                        let event = LayerServerEvent::Result(name, is_ok);
+                        add_server_layer(results, addr, event, fgid)?;
                     }
-                    LayerPeerEvent::Search(name) => {
-                        if let Ok(user) = User::search(&self.base, &name).await {
-                            add_server_layer(&mut results, addr, user.to_info(), fgid)?;
-                        } else {
-                            add_server_layer(
-                                &mut results,
-                                addr,
-                                LayerServerEvent::None(name),
-                                fgid,
-                            )?;
-                        }
+                    Err(reason) => {
+                        // peers only knowing `domain_types` wait the result.
+                        let event = LayerServerEvent::Result(name.clone(), false);
+                        add_server_layer(results, addr, event, fgid)?;
+                        let event = ExtServerEvent::Rejected(name, reason);
+                        add_ext_layer(results, addr, event, fgid)?;
                     }
-                    LayerPeerEvent::Register(name, bio, avatar) => {
-                        let mut user = User::new(name.clone(), addr, bio, avatar);
-                        let is_ok = user.insert(&self.base).await.is_ok();
-                        add_server_layer(
-                            &mut results,
-                            addr,
-                            LayerServerEvent::Result(name, is_ok),
-                            fgid,
-                        )?;
+                }
+            }
+            LayerPeerEvent::Update(name, bio, avatar) => {
+                if let Err(reason) = self.update(addr, &name, bio, avatar, None).await? {
+                    let event = ExtServerEvent::Rejected(name, reason);
+                    add_ext_layer(results, addr, event, fgid)?;
+                }
+            }
+            LayerPeerEvent::Suspend(name) => {
+                let mut user = User::get_by_name(&self.db, &name).await?;
+                if user.pid == addr {
+                    user.active(&self.db, false, &addr.to_hex()).await?;
+                    self.event(event_active(&name, &addr, false));
+                    add_server_layer(results, addr, LayerServerEvent::Actived(name, false), fgid)?;
+                }
+            }
+            LayerPeerEvent::Active(name) => {
+                let mut user = User::get_by_name(&self.db, &name).await?;
+                if user.pid == addr {
+                    user.active(&self.db, true, &addr.to_hex()).await?;
+                    self.event(event_active(&name, &addr, true));
+                    add_server_layer(results, addr, LayerServerEvent::Actived(name, true), fgid)?;
+                }
+            }
+            LayerPeerEvent::Delete(name) => {
+                if let Ok(user) = User::get_by_name(&self.db, &name).await {
+                    if user.pid == addr {
+                        user.delete(&self.db, &addr.to_hex()).await?;
+                        self.event(event_delete(&name, &addr));
                     }
-                    LayerPeerEvent::Update(name, bio, avatar) => {
-                        let user = User::get_by_name(&self.base, &name).await?;
-                        if user.pid == addr {
-                            User::update(&user.id, &bio, &avatar, &self.base).await?;
-                        }
+                }
+                add_server_layer(results, addr, LayerServerEvent::Deleted(name), fgid)?;
+            }
+            LayerPeerEvent::Request(_name, _rname, _remark) => {}
+        }
+
+        Ok(())
+    }
+
+    /// extension requests, see `protocol`.
+    async fn handle_ext(
+        &self,
+        results: &mut HandleResult,
+        fgid: GroupId,
+        addr: PeerId,
+        event: ExtPeerEvent,
+    ) -> Result<()> {
+        match event {
+            ExtPeerEvent::Avatar(name, hash) => {
+                // only send avatar bytes when the peer cached hash is changed.
+                if let Ok(mut user) = User::search(&self.db, &name).await {
+                    if user.avatar_hash == hash {
+                        user.avatar = vec![];
                     }
-                    LayerPeerEvent::Suspend(name) => {
-                        let user = User::get_by_name(&self.base, &name).await?;
-                        if user.pid == addr {
-                            User::active(&user.id, false).await?;
-                            add_server_layer(
-                                &mut results,
-                                addr,
-                                LayerServerEvent::Actived(name, false),
-                                fgid,
-                            )?;
-                        }
+                    let event = ExtServerEvent::Avatar(name, user.avatar_hash, user.avatar);
+                    add_ext_layer(results, addr, event, fgid)?;
+                } else {
+                    add_server_layer(results, addr, LayerServerEvent::None(name), fgid)?;
+                }
+            }
+            ExtPeerEvent::RegisterProfile(name, bio, avatar, attributes) => {
+                let registered = self
+                    .register(addr, &name, bio, avatar, Some(attributes))
+                    .await?;
+                match registered {
+                    Ok(is_ok) => {
+                        let event = LayerServerEvent::Result(name, is_ok);
+                        add_server_layer(results, addr, event, fgid)?;
                     }
-                    LayerPeerEvent::Active(name) => {
-                        let user = User::get_by_name(&self.base, &name).await?;
-                        if user.pid == addr {
-                            User::active(&user.id, true).await?;
-                            add_server_layer(
-                                &mut results,
-                                addr,
-                                LayerServerEvent::Actived(name, true),
-                                fgid,
-                            )?;
-                        }
+                    Err(reason) => {
+                        let event = ExtServerEvent::Rejected(name, reason);
+                        add_ext_layer(results, addr, event, fgid)?;
                     }
-                    LayerPeerEvent::Delete(name) => {
-                        if let Ok(user) = User::get_by_name(&self.base, &name).await {
-                            if user.pid == addr {
-                                User::delete(&user.id, &self.base).await?;
-                            }
+                }
+            }
+            ExtPeerEvent::UpdateProfile(name, bio, avatar, attributes) => {
+                let updated = self
+                    .update(addr, &name, bio, avatar, Some(attributes))
+                    .await?;
+                if let Err(reason) = updated {
+                    let event = ExtServerEvent::Rejected(name, reason);
+                    add_ext_layer(results, addr, event, fgid)?;
+                }
+            }
+            ExtPeerEvent::Profile(name) => {
+                if let Ok(user) = User::search(&self.db, &name).await {
+                    let attributes = Attribute::visible(&self.db, &user, &addr).await?;
+                    let event = ExtServerEvent::Profile(
+                        user.pid,
+                        user.name,
+                        user.bio,
+                        user.avatar,
+                        attributes,
+                    );
+                    add_ext_layer(results, addr, event, fgid)?;
+                } else {
+                    add_server_layer(results, addr, LayerServerEvent::None(name), fgid)?;
+                }
+            }
+            ExtPeerEvent::Report(name, reason) => {
+                if let Ok(user) = User::get_by_name(&self.db, &name).await {
+                    let mut report = Report::new(addr, &user, reason);
+                    report.insert(&self.db).await?;
+                    self.event(event_report(&name, &addr, report.id));
+                }
+            }
+            ExtPeerEvent::Versions(name) => {
+                let user = User::get_by_name(&self.db, &name).await?;
+                if user.pid == addr {
+                    let versions = Version::list(&self.db, &user.id)
+                        .await?
+                        .into_iter()
+                        .map(|v| (v.id, v.bio, v.avatar_hash, v.datetime))
+                        .collect();
+                    add_ext_layer(
+                        results,
+                        addr,
+                        ExtServerEvent::Versions(name, versions),
+                        fgid,
+                    )?;
+                }
+            }
+            ExtPeerEvent::Revert(name, id) => {
+                let mut user = User::get_by_name(&self.db, &name).await?;
+                if user.pid == addr {
+                    let is_ok = match Version::get(&self.db, &id).await {
+                        Ok(version) if version.user_id == user.id => {
+                            user.revert(&version, &self.db, &addr.to_hex()).await?;
+                            self.event(event_update(&name, &addr));
+                            true
                         }
-                        add_server_layer(
-                            &mut results,
-                            addr,
-                            LayerServerEvent::Deleted(name),
-                            fgid,
-                        )?;
+                        _ => false,
+                    };
+                    add_ext_layer(
+                        results,
+                        addr,
+                        ExtServerEvent::Reverted(name, id, is_ok),
+                        fgid,
+                    )?;
+                }
+            }
+            ExtPeerEvent::PublishDid(name, document, key_id, signature) => {
+                let user = User::get_by_name(&self.db, &name).await?;
+                if user.pid == addr {
+                    let actor = addr.to_hex();
+                    let is_ok =
+                        match Did::publish(&self.db, &user, document, key_id, signature, &actor)
+                            .await
+                        {
+                            Ok(_) => true,
+                            Err(e) => {
+                                warn!("{} did document rejected: {}", name, e);
+                                self.event(event_error(&e.to_string()));
+                                false
+                            }
+                        };
+                    add_ext_layer(
+                        results,
+                        addr,
+                        ExtServerEvent::DidPublished(name, is_ok),
+                        fgid,
+                    )?;
+                }
+            }
+            ExtPeerEvent::ResolveDid(name) => {
+                let did = match User::search(&self.db, &name).await {
+                    Ok(user) => Did::get(&self.db, &user.id).await?.map(|did| (user, did)),
+                    Err(_) => None,
+                };
+                match did {
+                    Some((user, did)) => {
+                        let event = ExtServerEvent::Did(
+                            name,
+                            user.pid,
+                            did.document.into_bytes(),
+                            did.key_id,
+                            hex::decode(did.signature).unwrap_or_default(),
+                        );
+                        add_ext_layer(results, addr, event, fgid)?;
+                    }
+                    None => {
+                        add_server_layer(results, addr, LayerServerEvent::None(name), fgid)?;
                     }
-                    LayerPeerEvent::Request(_name, _rname, _remark) => {}
                 }
             }
-            RecvType::Delivery(_t, _tid, _is_ok) => {
-                // MAYBE
+        }
+
+        Ok(())
+    }
+
+    /// register the name, with the profile attributes if has, return is ok
+    /// (false is the name conflict), or the reason of rejected.
+    async fn register(
+        &self,
+        addr: PeerId,
+        name: &str,
+        bio: String,
+        avatar: Vec<u8>,
+        attributes: Option<Vec<(String, String, bool)>>,
+    ) -> Result<std::result::Result<bool, String>> {
+        if self.registration == RegisterPolicy::Closed {
+            return Ok(Err("registration closed".to_owned()));
+        }
+
+        let checked = normalize(avatar, self.avatar_max_size)
+            .await
+            .and_then(|avatar| {
+                if let Some(attributes) = &attributes {
+                    validate(attributes)?;
+                }
+                Ok(avatar)
+            });
+        let avatar = match checked {
+            Ok(avatar) => avatar,
+            Err(e) => {
+                warn!("register {} rejected: {}", name, e);
+                self.event(event_error(&e.to_string()));
+                return Ok(Err(e.to_string()));
+            }
+        };
+
+        let mut user = User::new(name.to_owned(), addr, bio, avatar);
+        let is_ok = match user.insert(&self.db, &addr.to_hex()).await {
+            Ok(()) => {
+                if let Some(attributes) = attributes {
+                    self.save_attributes(addr, &user, attributes).await;
+                }
+                true
             }
+            Err(e) => {
+                // name conflict is normal, only report the failure.
+                if e.downcast_ref::<NameConflict>().is_none() {
+                    warn!("register {} failure: {}", name, e);
+                    self.event(event_error(&e.to_string()));
+                }
+                false
+            }
+        };
+        self.event(event_register(name, &addr, is_ok));
+        Ok(Ok(is_ok))
+    }
+
+    /// owner update bio & avatar, and the profile attributes if has,
+    /// return the reason if rejected.
+    async fn update(
+        &self,
+        addr: PeerId,
+        name: &str,
+        bio: String,
+        avatar: Vec<u8>,
+        attributes: Option<Vec<(String, String, bool)>>,
+    ) -> Result<std::result::Result<(), String>> {
+        let mut user = User::get_by_name(&self.db, name).await?;
+        if user.pid != addr {
+            return Ok(Ok(()));
         }
+        let checked = normalize(avatar, self.avatar_max_size)
+            .await
+            .and_then(|avatar| {
+                if let Some(attributes) = &attributes {
+                    validate(attributes)?;
+                }
+                Ok(avatar)
+            });
+        let avatar = match checked {
+            Ok(avatar) => avatar,
+            Err(e) => {
+                warn!("update {} rejected: {}", name, e);
+                self.event(event_error(&e.to_string()));
+                return Ok(Err(e.to_string()));
+            }
+        };
 
-        Ok(results)
+        user.update(bio, avatar, &self.db, &addr.to_hex(), "update")
+            .await?;
+        if let Some(attributes) = attributes {
+            self.save_attributes(addr, &user, attributes).await;
+        }
+        self.event(event_update(name, &addr));
+        Ok(Ok(()))
+    }
+
+    /// replace the attributes of the user, failure is reported, not returned.
+    async fn save_attributes(
+        &self,
+        addr: PeerId,
+        user: &User,
+        attributes: Vec<(String, String, bool)>,
+    ) {
+        if let Err(e) = Attribute::save(&self.db, user, attributes, &addr.to_hex()).await {
+            warn!("{} attributes failure: {}", user.name, e);
+            self.event(event_error(&e.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::EXT_MAGIC;
+    use crate::storage::{self, Backend, DatabaseConfig};
+    use domain_types::DOMAIN_ID;
+
+    async fn layer() -> Layer {
+        let base = std::env::temp_dir().join("domain-layer-test");
+        let db = storage::init(&base, Backend::Memory, DatabaseConfig::default())
+            .await
+            .unwrap();
+        let config: CustomConfig =
+            toml::from_str("name = \"test\"\nproxy = false\nmnemonic = \"\"").unwrap();
+        Layer::new(db, &config, &config.network, PeerId([0; 32]))
+            .await
+            .unwrap()
+    }
+
+    /// handle the request of the peer, return the replies (not extension).
+    async fn request(layer: &Layer, pid: PeerId, event: LayerPeerEvent) -> Vec<LayerServerEvent> {
+        let bytes = bincode::serialize(&event).unwrap();
+        let results = layer
+            .handle(DOMAIN_ID, RecvType::Event(pid, bytes))
+            .await
+            .unwrap();
+        results
+            .layers
+            .into_iter()
+            .filter_map(|(_, send)| match send {
+                SendType::Event(_, _, data) if !data.starts_with(EXT_MAGIC) => {
+                    Some(bincode::deserialize(&data).unwrap())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    async fn register(layer: &Layer, pid: PeerId, name: &str) -> bool {
+        let event = LayerPeerEvent::Register(name.to_owned(), "bio".to_owned(), vec![]);
+        match request(layer, pid, event).await.as_slice() {
+            [LayerServerEvent::Result(_, is_ok)] => *is_ok,
+            replies => panic!("unexpected replies {:?}", replies),
+        }
+    }
+
+    /// (pid, bio) of the searched name.
+    async fn search(layer: &Layer, name: &str) -> Option<(PeerId, String)> {
+        let event = LayerPeerEvent::Search(name.to_owned());
+        match request(layer, PeerId([9; 32]), event).await.pop() {
+            Some(LayerServerEvent::Info(pid, _, bio, _)) => Some((pid, bio)),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn register_conflict() {
+        let layer = layer().await;
+        let (alice, bob) = (PeerId([1; 32]), PeerId([2; 32]));
+
+        assert!(register(&layer, alice, "alice").await);
+        assert!(!register(&layer, bob, "alice").await);
+        assert_eq!(search(&layer, "alice").await.unwrap().0, alice);
+        assert!(search(&layer, "bob").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn register_case_insensitive() {
+        let layer = layer().await;
+        let (alice, bob) = (PeerId([1; 32]), PeerId([2; 32]));
+
+        assert!(register(&layer, alice, "Alice").await);
+        assert!(!register(&layer, bob, "ALICE").await);
+        assert_eq!(search(&layer, "alice").await.unwrap().0, alice);
+    }
+
+    #[tokio::test]
+    async fn register_closed() {
+        let mut layer = layer().await;
+        layer.registration = RegisterPolicy::Closed;
+
+        let event = LayerPeerEvent::Register("alice".to_owned(), "".to_owned(), vec![]);
+        let bytes = bincode::serialize(&event).unwrap();
+        let results = layer
+            .handle(DOMAIN_ID, RecvType::Event(PeerId([1; 32]), bytes))
+            .await
+            .unwrap();
+        // the result for peers only knowing `domain_types`, then the reason.
+        assert_eq!(results.layers.len(), 2);
+        assert!(search(&layer, "alice").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn update_owner_only() {
+        let layer = layer().await;
+        let (alice, bob) = (PeerId([1; 32]), PeerId([2; 32]));
+        assert!(register(&layer, alice, "alice").await);
+
+        let event = LayerPeerEvent::Update("alice".to_owned(), "by bob".to_owned(), vec![]);
+        request(&layer, bob, event).await;
+        assert_eq!(search(&layer, "alice").await.unwrap().1, "bio");
+
+        let event = LayerPeerEvent::Update("ALICE".to_owned(), "by alice".to_owned(), vec![]);
+        request(&layer, alice, event).await;
+        assert_eq!(search(&layer, "alice").await.unwrap().1, "by alice");
+    }
+
+    #[tokio::test]
+    async fn delete_soft() {
+        let layer = layer().await;
+        let (alice, bob) = (PeerId([1; 32]), PeerId([2; 32]));
+        assert!(register(&layer, alice, "alice").await);
+
+        request(&layer, bob, LayerPeerEvent::Delete("alice".to_owned())).await;
+        assert!(search(&layer, "alice").await.is_some());
+
+        let replies = request(&layer, alice, LayerPeerEvent::Delete("alice".to_owned())).await;
+        assert!(matches!(replies.as_slice(), [LayerServerEvent::Deleted(_)]));
+        assert!(search(&layer, "alice").await.is_none());
+        // kept until purged, the name is reserved.
+        let users = layer.db.store.user_list_page(0, 10).await.unwrap();
+        assert!(users.len() == 1 && users[0].is_deleted);
+        assert!(!register(&layer, bob, "alice").await);
+        assert!(search(&layer, "alice").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn bio_like_encrypted() {
+        let layer = layer().await;
+        let (alice, bob) = (PeerId([1; 32]), PeerId([2; 32]));
+        assert!(register(&layer, alice, "alice").await);
+        assert!(register(&layer, bob, "bob").await);
+
+        let event = LayerPeerEvent::Update("alice".to_owned(), "enc:1:00".to_owned(), vec![]);
+        request(&layer, alice, event).await;
+        assert_eq!(search(&layer, "alice").await.unwrap().1, "enc:1:00");
+
+        // a stored bio can not be decrypted only skips the user.
+        let bob_id = User::search(&layer.db, "bob").await.unwrap().id;
+        layer
+            .db
+            .store
+            .user_update(&bob_id, "enc:1:00")
+            .await
+            .unwrap();
+        let users = User::list(&layer.db).await.unwrap();
+        assert!(users
+            .iter()
+            .any(|u| u.name == "alice" && u.bio == "enc:1:00"));
+        assert!(users.iter().all(|u| u.name != "bob"));
+    }
+
+    #[test]
+    fn order_key_read_only() {
+        let event =
+            |e: LayerPeerEvent| RecvType::Event(PeerId([1; 32]), bincode::serialize(&e).unwrap());
+
+        let key = order_key(&event(LayerPeerEvent::Update(
+            "Alice".to_owned(),
+            "".to_owned(),
+            vec![],
+        )));
+        assert_eq!(key, Some(name_key("alice")));
+        assert_eq!(
+            order_key(&event(LayerPeerEvent::Search("alice".to_owned()))),
+            None
+        );
+        assert_eq!(order_key(&event(LayerPeerEvent::Check)), None);
     }
 }
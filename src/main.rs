@@ -5,97 +5,407 @@ extern crate log;
 extern crate anyhow;
 
 use domain_types::DOMAIN_ID;
-use serde::{Deserialize, Serialize};
 use simplelog::{CombinedLogger, Config as LogConfig, LevelFilter};
-use std::{env::args, path::PathBuf, sync::Arc};
-use tdn::{prelude::*, types::primitives::Result};
+use std::{
+    collections::HashSet,
+    env::args,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tdn::{
+    prelude::*,
+    types::{
+        primitives::{PeerId, Result},
+        rpc::RpcParam,
+    },
+};
 use tdn_did::{generate_mnemonic, generate_peer, Count, Language};
 use tokio::sync::{mpsc::Sender, RwLock};
 
+use cli::{Command, UsersCommand};
+use config::{
+    custom_config_str, CustomConfig, RegisterPolicy, DEFAULT_PROVIDER_NAME, DEFAULT_PROVIDER_PROXY,
+};
+
+mod avatar;
+mod backup;
+mod cache;
+mod cli;
+mod config;
+mod crypto;
+mod did;
+mod dispatch;
 mod layer;
 mod models;
+mod network;
+mod profile;
+mod protocol;
+mod registry;
 mod rpc;
+mod shutdown;
 mod storage;
 
-const DEFAULT_PROVIDER_NAME: &'static str = "domain.esse";
-const DEFAULT_PROVIDER_PROXY: bool = true;
-
-const DEFAULT_P2P_ADDR: &'static str = "0.0.0.0:7350";
-const DEFAULT_HTTP_ADDR: &'static str = "127.0.0.1:7351";
 const DEFAULT_LOG_FILE: &'static str = "domain.log.txt";
 
-/// parse custom config from config.toml.
-#[derive(Serialize, Deserialize, Debug)]
-pub struct CustomConfig {
-    pub name: String,
-    pub proxy: bool,
-    pub mnemonic: String,
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = args().skip(1).collect();
+    let (command, db_path) = match cli::parse(&args) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let res = match command {
+        Command::Run {
+            p2p_addr,
+            rpc_addr,
+            bootstrap,
+        } => {
+            if let Err(e) = start(db_path, p2p_addr, rpc_addr, bootstrap).await {
+                error!("Domain start failure: {}", e);
+                println!("Domain start failure: {}", e);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Init => init(db_path).await,
+        Command::ShowId => show_id(db_path).await,
+        Command::Users(cmd) => users(cmd, db_path).await,
+        Command::Export { file, format } => transfer("export", &file, &format, db_path).await,
+        Command::Import { file, format } => transfer("import", &file, &format, db_path).await,
+        Command::Migrate(cmd) => migrate(&cmd, db_path).await,
+        Command::Backup {
+            file,
+            compress,
+            password_stdin,
+        } => match backup_password(password_stdin) {
+            Ok(password) => snapshot("backup", &file, compress, &password, db_path).await,
+            Err(e) => Err(e),
+        },
+        Command::Restore {
+            file,
+            password_stdin,
+        } => match backup_password(password_stdin) {
+            Ok(password) => snapshot("restore", &file, false, &password, db_path).await,
+            Err(e) => Err(e),
+        },
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+    };
+    if let Err(e) = res {
+        println!("Failure: {}", e);
+        std::process::exit(1);
+    }
 }
 
-fn custom_config_str(config: &CustomConfig) -> String {
-    format!(
-        r#"## Domain custom Config.
-## domain server name.
-name = "{}"
+/// create the config & mnemonic of the domain, keep them if exist.
+async fn init(db_path: String) -> Result<()> {
+    let db_path = PathBuf::from(db_path);
+    let (_, custom) = load_config(&db_path).await?;
+    let pid = domain_peer_id(&custom)?;
+    println!("Domain initialized in {:?}", db_path);
+    println!("Peer id : {}", pid.to_hex());
+    println!("Storage : {}", custom.storage.to_str());
+    Ok(())
+}
 
-## domain is support proxy request/response.
-proxy = {}
+/// show the peer id of the domain, derived from the mnemonic.
+async fn show_id(db_path: String) -> Result<()> {
+    let db_path = PathBuf::from(db_path);
+    let custom: CustomConfig = config::load_custom(&db_path).await?.ok_or(anyhow!(
+        "domain not initialized in {:?}, run init first",
+        db_path
+    ))?;
+    let pid = domain_peer_id(&custom)?;
+    println!("Name    : {}", custom.name);
+    println!("Peer id : {}", pid.to_hex());
+    println!("DID     : {}", did::did(&pid));
+    Ok(())
+}
 
-## domain server default mnemonic words (keep PeerId same).
-mnemonic = "{}"
-"#,
-        config.name, config.proxy, config.mnemonic
-    )
+#[inline]
+fn domain_peer_id(custom: &CustomConfig) -> Result<PeerId> {
+    let pkey = generate_peer(Language::English, &custom.mnemonic, 0, 0, None)?;
+    Ok(pkey.peer_id())
 }
 
-#[tokio::main]
-async fn main() {
-    let db_path = args().nth(1).unwrap_or("./.tdn".to_owned());
+/// manage users without writing JSON-RPC, changes are audited as admin.
+async fn users(cmd: UsersCommand, db_path: String) -> Result<()> {
+    let db_path = PathBuf::from(db_path);
+    if matches!(cmd, UsersCommand::Ban(..) | UsersCommand::Delete(..)) {
+        ensure_stopped(&db_path).await?;
+    }
+    let db = init_storage(&db_path).await?;
+
+    match cmd {
+        UsersCommand::List => {
+            let users = models::User::list(&db).await?;
+            for user in users.iter() {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    user.id,
+                    user.name,
+                    user.pid.to_hex(),
+                    if user.is_actived {
+                        "active"
+                    } else {
+                        "suspended"
+                    }
+                );
+            }
+            println!("{} users.", users.len());
+        }
+        UsersCommand::Get(name) => {
+            let user = models::User::get_by_name(&db, &name).await?;
+            println!("Id      : {}", user.id);
+            println!("Name    : {}", user.name);
+            println!("Peer id : {}", user.pid.to_hex());
+            println!("Bio     : {}", user.bio);
+            println!("Avatar  : {}", user.avatar_hash);
+            println!("Actived : {}", user.is_actived);
+            println!("Created : {}", user.datetime);
+            for attribute in models::Attribute::list(&db, &user.id).await? {
+                let visibility = if attribute.is_public {
+                    "public"
+                } else {
+                    "private"
+                };
+                println!("{} : {} ({})", attribute.key, attribute.value, visibility);
+            }
+        }
+        UsersCommand::Ban(name, ban) => {
+            let mut user = models::User::get_by_name(&db, &name).await?;
+            user.ban(&db, ban, "admin").await?;
+            let operation = if ban { "ban" } else { "unban" };
+            println!("User {} {}ned.", name, operation);
+        }
+        UsersCommand::Delete(name) => {
+            let user = models::User::get_by_name(&db, &name).await?;
+            user.delete(&db, "admin").await?;
+            println!("User {} deleted.", name);
+        }
+    }
+    Ok(())
+}
+
+/// refuse changing the database by command line when the domain is running,
+/// its cache would be stale. running if the RPC address accepts connections.
+async fn ensure_stopped(db_path: &PathBuf) -> Result<()> {
+    let custom = match config::load_custom(db_path).await? {
+        Some(custom) => custom,
+        None => return Ok(()),
+    };
+    let mut addr = custom.network.with_env()?.network_rpc_addr;
+    if addr.ip().is_unspecified() {
+        addr.set_ip(if addr.is_ipv4() {
+            Ipv4Addr::LOCALHOST.into()
+        } else {
+            Ipv6Addr::LOCALHOST.into()
+        });
+    }
+    let connect = tokio::net::TcpStream::connect(addr);
+    if let Ok(Ok(_)) = tokio::time::timeout(Duration::from_secs(1), connect).await {
+        return Err(anyhow!(
+            "domain is running (RPC {}), stop it first or use the RPC methods",
+            addr
+        ));
+    }
+    Ok(())
+}
+
+/// init the storage & encryption by the config of the domain.
+async fn init_storage(db_path: &PathBuf) -> Result<storage::Db> {
+    let custom = config::load_custom(db_path).await?;
+    let (backend, database) = if let Some(custom) = custom {
+        init_crypto(db_path, &custom).await?;
+        (custom.storage, custom.database)
+    } else {
+        (
+            storage::Backend::default(),
+            storage::DatabaseConfig::default(),
+        )
+    };
+    storage::init(db_path, backend, database).await
+}
+
+/// export or import the users registry without running the service.
+async fn transfer(cmd: &str, file: &str, format: &str, db_path: String) -> Result<()> {
+    let db_path = PathBuf::from(db_path);
+    let db = init_storage(&db_path).await?;
 
-    if std::fs::metadata(&db_path).is_err() {
-        std::fs::create_dir(&db_path).unwrap();
+    let path = PathBuf::from(file);
+    let format = registry::Format::parse(format, &path)?;
+    if cmd == "export" {
+        let report = registry::export(&db, &path, format).await?;
+        println!("Exported {} users.", report.exported);
+        for (name, error) in report.failures {
+            println!("Failure: {} ({})", name, error);
+        }
+    } else {
+        let report = registry::import(&db, &path, format).await?;
+        println!("Imported {} users.", report.imported);
+        for name in report.conflicts {
+            println!("Conflict: {}", name);
+        }
+        for (record, error) in report.failures {
+            println!("Failure: {} ({})", record, error);
+        }
     }
+    Ok(())
+}
 
-    let _ = start(db_path).await;
+/// password of the backup, a line of stdin, or env `DOMAIN_BACKUP_PASSWORD`.
+fn backup_password(from_stdin: bool) -> Result<String> {
+    if from_stdin {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        Ok(line.trim_end_matches(&['\r', '\n'][..]).to_owned())
+    } else {
+        Ok(std::env::var("DOMAIN_BACKUP_PASSWORD").unwrap_or_default())
+    }
 }
 
-pub async fn start(db_path: String) -> Result<()> {
+/// backup or restore the domain, restore needs the service stopped.
+async fn snapshot(
+    cmd: &str,
+    file: &str,
+    compress: bool,
+    password: &str,
+    db_path: String,
+) -> Result<()> {
     let db_path = PathBuf::from(db_path);
-    if !db_path.exists() {
-        tokio::fs::create_dir_all(&db_path).await?;
+    let path = PathBuf::from(file);
+    let custom: CustomConfig = config::load_custom(&db_path).await?.ok_or(anyhow!(
+        "domain not initialized in {:?}, run init first",
+        db_path
+    ))?;
+    let (backend, database) = (custom.storage, custom.database.clone());
+    // secrets are not in the backup, current ones are kept.
+    let mut keep = config::storage_items(&custom);
+    keep.extend(config::secret_items(&custom));
+
+    if cmd == "restore" {
+        ensure_stopped(&db_path).await?;
+        // verify all before touching the domain.
+        let archive = backup::verify(&path, password, &db_path).await?;
+        let db = storage::init(&db_path, backend, database).await?;
+        let summary = backup::restore(&db, backend, archive, &keep).await?;
+        println!(
+            "Restored {} users, {} avatars (backup at {}).",
+            summary.users, summary.avatars, summary.datetime
+        );
+    } else {
+        let db = storage::init(&db_path, backend, database).await?;
+        let summary = backup::backup(&db, &path, compress, password).await?;
+        println!(
+            "Backup {} users, {} audits, {} reports, {} avatars.",
+            summary.users, summary.audits, summary.reports, summary.avatars
+        );
     }
+    Ok(())
+}
 
-    storage::init(&db_path).await?;
+/// apply the database migrations, or show the status/pending migrations.
+async fn migrate(cmd: &str, db_path: String) -> Result<()> {
+    let db_path = PathBuf::from(db_path);
+    let custom = config::load_custom(&db_path).await?;
+    let (backend, database) = custom.map(|c| (c.storage, c.database)).unwrap_or_default();
+    let store = storage::connect(&db_path, backend, database).await?;
+    let status = storage::SchemaStatus::check(store.as_ref()).await?;
+
+    match cmd {
+        "status" => {
+            for (m, is_applied) in status.migrations.iter() {
+                let state = if *is_applied { "applied" } else { "pending" };
+                println!("{} {} ({})", m.version, m.description, state);
+            }
+            for version in status.unknown.iter() {
+                println!("{} unknown (newer schema)", version);
+            }
+            for version in status.failed.iter() {
+                println!("{} failed", version);
+            }
+        }
+        "dry-run" => {
+            status.ensure_compatible()?;
+            for (m, is_applied) in status.migrations.iter() {
+                if !is_applied {
+                    println!("-- {} {}\n{}", m.version, m.description, m.sql);
+                }
+            }
+            println!("{} pending migrations.", status.pending.len());
+        }
+        "run" => {
+            status.ensure_compatible()?;
+            store.migrate().await?;
+            println!("Applied {} migrations.", status.pending.len());
+            storage::migrate_name_keys(store.as_ref()).await?;
+        }
+        _ => {
+            return Err(anyhow!(
+                "Usage: domain migrate [run|status|dry-run] [db_path]"
+            ))
+        }
+    }
+    Ok(())
+}
 
+pub async fn start(
+    db_path: String,
+    p2p_addr: Option<SocketAddr>,
+    rpc_addr: Option<SocketAddr>,
+    bootstrap: Option<Vec<SocketAddr>>,
+) -> Result<()> {
+    let db_path = PathBuf::from(db_path);
+    if !db_path.exists() {
+        tokio::fs::create_dir_all(&db_path).await?;
+    }
     init_log(db_path.clone());
     info!("Core storage path {:?}", db_path);
 
-    let mut config = Config::default();
-    config.db_path = Some(db_path.clone());
-    config.rpc_addr = DEFAULT_HTTP_ADDR.parse().unwrap();
-    config.p2p_peer = Peer::socket(DEFAULT_P2P_ADDR.parse().unwrap());
-    config.p2p_allowlist.append(&mut vec![Peer::socket(
-        "1.15.156.199:7364".parse().unwrap(),
-    )]);
-    config.group_ids = vec![DOMAIN_ID];
-    let config = Config::load_save(db_path.clone(), config).await?;
-    let custom: Option<CustomConfig> = Config::load_custom(db_path.clone()).await;
-    let CustomConfig {
-        name,
-        proxy,
-        mnemonic,
-    } = if let Some(custom) = custom {
-        custom
-    } else {
-        let mnemonic = generate_mnemonic(Language::English, Count::Words12);
-        let custom = CustomConfig {
-            name: DEFAULT_PROVIDER_NAME.to_owned(),
-            proxy: DEFAULT_PROVIDER_PROXY,
-            mnemonic: mnemonic,
-        };
-        Config::append_custom(db_path.clone(), &custom_config_str(&custom)).await?;
-        custom
-    };
+    let (mut config, mut custom) = load_config(&db_path).await?;
+    if custom.admin_token.is_empty() {
+        custom.admin_token = config::generate_token();
+        let item = config::toml_str(&custom.admin_token);
+        config::save_custom(&db_path, &[("admin_token", item)]).await?;
+        info!("Generated admin_token in config.toml");
+    }
+    // env & command line overrides are only for this run, not saved.
+    let mut network = custom.network.clone().with_env()?;
+    if let Some(addr) = p2p_addr {
+        network.network_p2p_addr = addr;
+    }
+    if let Some(addr) = rpc_addr {
+        network.network_rpc_addr = addr;
+    }
+    if let Some(addrs) = bootstrap {
+        network.network_bootstrap = addrs;
+    }
+    config.p2p_peer = Peer::socket(network.network_p2p_addr);
+    config.rpc_addr = network.network_rpc_addr;
+    config.p2p_allowlist = network
+        .network_bootstrap
+        .iter()
+        .map(|addr| Peer::socket(*addr))
+        .collect();
+
+    init_crypto(&db_path, &custom).await?;
+    let db = storage::init(&db_path, custom.storage, custom.database.clone()).await?;
+    info!("Storage backend : {}", custom.storage.to_str());
+    info!("Encryption      : {}", custom.encryption.to_str());
+    if crypto::is_enabled() {
+        storage::spawn_reencrypt(db.clone());
+    }
+    storage::spawn_reconcile(db.clone());
+    storage::spawn_purge(db.clone(), custom.purge_retention);
+    cache::init(custom.cache_capacity, custom.cache_ttl);
 
     info!("Config RPC HTTP : {:?}", config.rpc_addr);
     info!(
@@ -103,49 +413,257 @@ pub async fn start(db_path: String) -> Result<()> {
         config.p2p_peer.transport.to_str(),
         config.p2p_peer.socket
     );
+    info!("Config bootstrap: {:?}", network.network_bootstrap);
 
     let _rand_secret = config.secret.clone();
-    let pkey = generate_peer(Language::English, &mnemonic, 0, 0, None)?;
+    let pkey = generate_peer(Language::English, &custom.mnemonic, 0, 0, None)?;
     let (peer_id, sender, mut recver) = start_with_config_and_key(config, pkey).await?;
     info!("Network Peer id : {}", peer_id.to_hex());
 
     let layer = Arc::new(RwLock::new(
-        layer::Layer::new(db_path, name, peer_id, proxy).await?,
+        layer::Layer::new(db, &custom, &network, peer_id).await?,
     ));
 
-    let rpc_handler = rpc::new_rpc_handler(layer.clone());
+    let rpc_handler = Arc::new(rpc::new_rpc_handler(layer.clone()));
+    let mut dispatcher = dispatch::Dispatcher::new(custom.layer_workers);
+
+    // federated domains connected, announced when stopping.
+    let mut federation: HashSet<PeerId> = HashSet::new();
+    let signal = shutdown::signal();
+    tokio::pin!(signal);
+    let lost_timeout = network.network_lost_timeout;
+    let mut given_up = false;
+
+    loop {
+        // layer requests running in workers are drained when stopping.
+        let message = tokio::select! {
+            name = &mut signal => {
+                info!("Received {}, stopping", name);
+                break;
+            }
+            _ = network::given_up() => {
+                given_up = true;
+                break;
+            }
+            // layer requests wait the queue here, never block the others.
+            _ = dispatcher.run(), if dispatcher.pending() > 0 => continue,
+            message = recver.recv(), if !dispatcher.is_full() => match message {
+                Some(message) => message,
+                None => break,
+            },
+        };
 
-    while let Some(message) = recver.recv().await {
         match message {
             ReceiveMessage::Own(_o_msg) => {
+                network::alive();
                 // Self distributed domain service.
             }
-            ReceiveMessage::Group(_g_msg) => {
+            ReceiveMessage::Group(g_msg) => {
+                peer_alive(&g_msg);
                 // Other domain services.
+                let event = match g_msg {
+                    RecvType::Connect(addr, _) => {
+                        federation.insert(addr);
+                        rpc::event_federation(&addr, "connect")
+                    }
+                    RecvType::Leave(addr) => {
+                        federation.remove(&addr);
+                        rpc::event_federation(&addr, "leave")
+                    }
+                    RecvType::Event(addr, _) => rpc::event_federation(&addr, "event"),
+                    _ => continue,
+                };
+                broadcast(vec![event], layer.read().await.subscribers(), &sender).await;
             }
             ReceiveMessage::Layer(fgid, tgid, l_msg) => {
+                peer_alive(&l_msg);
                 if tgid == DOMAIN_ID {
-                    if let Ok(results) = layer.write().await.handle(fgid, l_msg).await {
-                        handle(results, 0, &sender).await;
-                    }
+                    let key = layer::order_key(&l_msg);
+                    let layer = layer.clone();
+                    let sender = sender.clone();
+                    let work = async move {
+                        let layer = layer.read().await;
+                        let res = match layer.handle(fgid, l_msg).await {
+                            Ok(results) => {
+                                handle(results, 0, false, &sender).await;
+                                Ok(())
+                            }
+                            Err(e) => {
+                                warn!("Layer handle error: {}", e);
+                                layer.event(rpc::event_error(&e.to_string()));
+                                Err(e)
+                            }
+                        };
+                        broadcast(layer.take_events(), layer.subscribers(), &sender).await;
+                        res
+                    };
+                    dispatcher.push(key, work);
                 }
             }
-            ReceiveMessage::Rpc(uid, params, _is_ws) => {
-                if let Ok(results) = rpc_handler.handle(params).await {
-                    handle(results, uid, &sender).await;
-                }
+            ReceiveMessage::Rpc(uid, params, is_ws) => {
+                // long requests (e.g. import, backup) never block receiving.
+                let work = match shutdown::begin() {
+                    Some(work) => work,
+                    None => continue,
+                };
+                let layer = layer.clone();
+                let rpc_handler = rpc_handler.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    let _work = work;
+                    if let Some(results) =
+                        rpc::handle_subscription(&layer, uid, is_ws, &params).await
+                    {
+                        handle(results, uid, is_ws, &sender).await;
+                    } else if let Some(results) = rpc::check_admin(&layer, &params).await {
+                        handle(results, uid, is_ws, &sender).await;
+                    } else if let Ok(results) = rpc_handler.handle(params).await {
+                        handle(results, uid, is_ws, &sender).await;
+
+                        let layer = layer.read().await;
+                        broadcast(layer.take_events(), layer.subscribers(), &sender).await;
+                    }
+                });
             }
             ReceiveMessage::NetworkLost => {
-                //
+                network::lost(layer.clone(), sender.clone(), lost_timeout);
             }
         }
     }
 
+    stop(&layer, federation, &sender).await;
+    if given_up {
+        // exit with failure, for the supervisor to restart.
+        return Err(anyhow!("network lost more than {}s", lost_timeout));
+    }
     Ok(())
 }
 
+/// stop the domain, new works are refused, the in-flight works drained,
+/// then announce departure to federated domains, close the store & logs.
+async fn stop(
+    layer: &Arc<RwLock<layer::Layer>>,
+    federation: HashSet<PeerId>,
+    sender: &Sender<SendMessage>,
+) {
+    shutdown::stop();
+    if !shutdown::drain(Duration::from_secs(shutdown::DRAIN_TIMEOUT)).await {
+        warn!(
+            "In-flight works not finished in {}s, stop anyway",
+            shutdown::DRAIN_TIMEOUT
+        );
+    }
+
+    // wait the running RPC holding the layer, e.g. online backup.
+    let layer = layer.write().await;
+    let event = rpc::event_shutdown();
+    for uid in layer.subscribers() {
+        let _ = sender
+            .send(SendMessage::Rpc(uid, event.clone(), true))
+            .await;
+    }
+    for pid in federation {
+        let _ = sender
+            .send(SendMessage::Group(SendType::Disconnect(pid)))
+            .await;
+    }
+
+    if !shutdown::flush(sender, Duration::from_secs(shutdown::FLUSH_TIMEOUT)).await {
+        warn!(
+            "Messages not sent in {}s, stop anyway",
+            shutdown::FLUSH_TIMEOUT
+        );
+    }
+
+    storage::close(&layer.db).await;
+    info!("Domain stopped");
+    log::logger().flush();
+}
+
+/// load the config, the default config & mnemonic are created if not exists.
+async fn load_config(db_path: &PathBuf) -> Result<(Config, CustomConfig)> {
+    if !db_path.exists() {
+        tokio::fs::create_dir_all(db_path).await?;
+    }
+
+    let mut config = Config::default();
+    config.db_path = Some(db_path.clone());
+    config.group_ids = vec![DOMAIN_ID];
+    let config = Config::load_save(db_path.clone(), config).await?;
+    // a broken config fails, never replaced by a new mnemonic.
+    let custom = if let Some(custom) = config::load_custom(db_path).await? {
+        custom
+    } else {
+        let mnemonic = generate_mnemonic(Language::English, Count::Words12);
+        let custom = CustomConfig {
+            name: DEFAULT_PROVIDER_NAME.to_owned(),
+            proxy: DEFAULT_PROVIDER_PROXY,
+            mnemonic: mnemonic,
+            rate_limit: 0,
+            registration: RegisterPolicy::Open,
+            storage: storage::Backend::default(),
+            database: storage::DatabaseConfig::default(),
+            network: config::NetworkConfig::default(),
+            avatar_max_size: avatar::DEFAULT_AVATAR_MAX_SIZE,
+            encryption: crypto::Encryption::default(),
+            key_file: crypto::DEFAULT_KEY_FILE.to_owned(),
+            key_version: 1,
+            cache_capacity: cache::DEFAULT_CACHE_CAPACITY,
+            cache_ttl: cache::DEFAULT_CACHE_TTL,
+            purge_retention: storage::DEFAULT_PURGE_RETENTION,
+            layer_workers: dispatch::DEFAULT_LAYER_WORKERS,
+            admin_token: config::generate_token(),
+        };
+        Config::append_custom(db_path.clone(), &custom_config_str(&custom)).await?;
+        custom
+    };
+
+    Ok((config, custom))
+}
+
+/// load the at-rest encryption key, the key file is only generated when
+/// nothing is encrypted.
+async fn init_crypto(db_path: &PathBuf, custom: &CustomConfig) -> Result<()> {
+    let mut key_file = db_path.clone();
+    key_file.push(&custom.key_file);
+    let create_key = custom.encryption == crypto::Encryption::KeyFile
+        && !key_file.exists()
+        && !storage::has_encrypted(db_path, custom.storage, custom.database.clone()).await?;
+    crypto::init(
+        custom.encryption,
+        &custom.mnemonic,
+        &custom.key_file,
+        custom.key_version,
+        db_path,
+        create_key,
+    )
+    .await
+}
+
+/// message of a peer received, connected peers recover the network.
+#[inline]
+fn peer_alive(msg: &RecvType) {
+    match msg {
+        RecvType::Connect(..) | RecvType::ResultConnect(..) => network::connected(),
+        _ => network::alive(),
+    }
+}
+
+/// push live events to all subscribed websocket connections.
+#[inline]
+async fn broadcast(events: Vec<RpcParam>, subscribers: Vec<u64>, sender: &Sender<SendMessage>) {
+    for event in events {
+        for uid in subscribers.iter() {
+            sender
+                .send(SendMessage::Rpc(*uid, event.clone(), true))
+                .await
+                .expect("TDN channel closed");
+        }
+    }
+}
+
 #[inline]
-async fn handle(handle_result: HandleResult, uid: u64, sender: &Sender<SendMessage>) {
+async fn handle(handle_result: HandleResult, uid: u64, is_ws: bool, sender: &Sender<SendMessage>) {
     let HandleResult {
         mut owns,
         mut rpcs,
@@ -158,7 +676,7 @@ async fn handle(handle_result: HandleResult, uid: u64, sender: &Sender<SendMessa
         if rpcs.len() != 0 {
             let msg = rpcs.remove(0);
             sender
-                .send(SendMessage::Rpc(uid, msg, false))
+                .send(SendMessage::Rpc(uid, msg, is_ws))
                 .await
                 .expect("TDN channel closed");
         } else {
@@ -1,46 +1,65 @@
 use domain_types::LayerServerEvent;
-use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tdn::types::{
     primitives::{PeerId, Result},
     rpc::{json, RpcParam},
 };
 
-use crate::storage::{delete_avatar, get_pool, read_avatar, write_avatar};
+use crate::cache;
+use crate::crypto::{decrypt_text, encrypt_text};
+use crate::did::{check_update, did, DidDocument};
+use crate::profile;
+use crate::storage::{
+    avatar_hash, lock_avatar, read_avatar, release_avatar, write_avatar, Db, BIO_LOCK,
+};
+
+/// current timestamp (seconds).
+#[inline]
+pub(crate) fn now() -> i64 {
+    let start = SystemTime::now();
+    start
+        .duration_since(UNIX_EPOCH)
+        .map(|s| s.as_secs())
+        .unwrap_or(0) as i64 // safe for all life.
+}
 
 /// User Model.
+#[derive(Clone)]
 pub struct User {
     /// db auto-increment id.
     pub id: i64,
     /// name.
-    name: String,
+    pub name: String,
     /// user ID
     pub pid: PeerId,
     /// bio.
-    bio: String,
+    pub bio: String,
     /// avatar.
-    avatar: Vec<u8>,
+    pub avatar: Vec<u8>,
+    /// avatar blake3 hash, empty if no avatar.
+    pub avatar_hash: String,
     /// is actived.
-    is_actived: bool,
+    pub is_actived: bool,
+    /// is deleted.
+    pub is_deleted: bool,
+    /// deleted time, 0 if not deleted.
+    pub deleted_at: i64,
     /// created time.
-    datetime: i64,
+    pub datetime: i64,
 }
 
 impl User {
     pub fn new(name: String, pid: PeerId, bio: String, avatar: Vec<u8>) -> Self {
-        let start = SystemTime::now();
-        let datetime = start
-            .duration_since(UNIX_EPOCH)
-            .map(|s| s.as_secs())
-            .unwrap_or(0) as i64; // safe for all life.
-
         Self {
-            datetime,
+            datetime: now(),
+            avatar_hash: avatar_hash(&avatar),
             name,
             pid,
             bio,
             avatar,
             is_actived: true,
+            is_deleted: false,
+            deleted_at: 0,
             id: 0,
         }
     }
@@ -55,150 +74,691 @@ impl User {
         ])
     }
 
+    /// profile values saved in audit log, the bio is redacted to its length.
+    pub fn snapshot(&self) -> String {
+        json!({
+            "name": self.name,
+            "pid": self.pid.to_hex(),
+            "bio_len": self.bio.chars().count(),
+            "avatar": self.avatar_hash,
+            "is_actived": self.is_actived,
+        })
+        .to_string()
+    }
+
     pub fn to_info(self) -> LayerServerEvent {
         LayerServerEvent::Info(self.pid, self.name, self.bio, self.avatar)
     }
 
-    pub async fn list(base: &PathBuf) -> Result<Vec<Self>> {
-        let recs = sqlx::query!(
-            "SELECT id, name, pid, bio, is_actived, datetime FROM users WHERE is_deleted = false ORDER BY id",
-        )
-            .fetch_all(get_pool()?).await.map_err(|_| anyhow!("database failure."))?;
+    /// load the avatar and decrypt the bio of user from store.
+    pub(crate) async fn load(mut self, db: &Db) -> Result<Self> {
+        self.bio = decrypt_text(self.bio)?;
+        self.avatar = read_avatar(db, &self.avatar_hash).await?;
+        Ok(self)
+    }
+
+    /// the user saved to store, bio is encrypted.
+    fn stored(&self) -> Result<Self> {
+        let mut user = self.clone();
+        user.bio = encrypt_text(&self.bio)?;
+        Ok(user)
+    }
 
+    /// users can not be loaded (e.g. decrypt failure) are skipped & logged.
+    pub async fn list(db: &Db) -> Result<Vec<Self>> {
         let mut users = vec![];
+        for user in db.store.user_list().await? {
+            let (id, name) = (user.id, user.name.clone());
+            match user.load(db).await {
+                Ok(user) => users.push(user),
+                Err(e) => warn!("user {} ({}) skipped: {}", name, id, e),
+            }
+        }
+        Ok(users)
+    }
 
-        for res in recs {
-            let avatar = read_avatar(base, &res.id).await?;
-
-            users.push(Self {
-                avatar,
-                id: res.id,
-                name: res.name.trim().to_owned(),
-                pid: PeerId::from_hex(res.pid.trim()).unwrap_or(PeerId::default()),
-                bio: res.bio,
-                is_actived: res.is_actived,
-                datetime: res.datetime,
-            });
+    /// actived user by name, from the cache if hit.
+    pub async fn search(db: &Db, name: &str) -> Result<User> {
+        if let Some(user) = cache::get(name) {
+            return Ok(user);
         }
+        let generation = cache::generation();
+        let user = db.store.user_search(name).await?.load(db).await?;
+        cache::put(&user, generation);
+        Ok(user)
+    }
 
-        Ok(users)
+    pub async fn get_by_name(db: &Db, name: &str) -> Result<User> {
+        db.store.user_get_by_name(name).await?.load(db).await
     }
 
-    pub async fn search(base: &PathBuf, name: &str) -> Result<User> {
-        let res = sqlx::query!(
-            "SELECT id, name, pid, bio, is_actived, datetime FROM users WHERE is_actived = true AND name = $1",
-            name
-        ).fetch_one(get_pool()?).await.map_err(|_| anyhow!("database failure."))?;
+    pub async fn get(db: &Db, id: &i64) -> Result<User> {
+        db.store.user_get(id).await?.load(db).await
+    }
 
-        let avatar = read_avatar(base, &res.id).await?;
+    /// register the user, audited as the actor.
+    pub async fn insert(&mut self, db: &Db, actor: &str) -> Result<()> {
+        let (hash, avatar_lock) = write_avatar(db, &self.avatar).await?;
+        self.avatar_hash = hash;
+        let audit = Audit::new(
+            actor.to_owned(),
+            "register",
+            0,
+            self.name.clone(),
+            String::new(),
+            self.snapshot(),
+        );
+        match db.store.user_insert(&self.stored()?, &audit).await {
+            Ok(id) => self.id = id,
+            Err(e) => {
+                drop(avatar_lock);
+                let _ = release_avatar(db, &self.avatar_hash).await;
+                return Err(e);
+            }
+        }
 
-        Ok(Self {
-            avatar,
-            id: res.id,
-            name: res.name.trim().to_owned(),
-            pid: PeerId::from_hex(res.pid.trim()).unwrap_or(PeerId::default()),
-            bio: res.bio,
-            is_actived: res.is_actived,
-            datetime: res.datetime,
-        })
+        Ok(())
     }
 
-    pub async fn get_by_name(base: &PathBuf, name: &str) -> Result<User> {
-        let res = sqlx::query!(
-            "SELECT id, name, pid, bio, is_actived, datetime FROM users WHERE is_deleted = false AND name = $1",
-            name
-        ).fetch_one(get_pool()?).await.map_err(|_| anyhow!("database failure."))?;
+    /// insert an imported user, keep the state & created time,
+    /// deleted users not need unique name, but an active user not uses the
+    /// name of a deleted user. saved with the audit history.
+    pub async fn import(&mut self, db: &Db, mut history: Vec<Audit>) -> Result<()> {
+        let mut avatar_lock = None;
+        if self.is_deleted {
+            self.avatar_hash = String::new();
+        } else {
+            let (hash, lock) = write_avatar(db, &self.avatar).await?;
+            self.avatar_hash = hash;
+            avatar_lock = lock;
+        }
+        history.push(Audit::new(
+            "admin".to_owned(),
+            "import",
+            0,
+            self.name.clone(),
+            String::new(),
+            self.snapshot(),
+        ));
+        match db.store.user_import(&self.stored()?, &history).await {
+            Ok(id) => self.id = id,
+            Err(e) => {
+                drop(avatar_lock);
+                let _ = release_avatar(db, &self.avatar_hash).await;
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
 
-        let avatar = read_avatar(base, &res.id).await?;
+    /// update bio & avatar, empty avatar will keep the old one,
+    /// audited as the actor & operation.
+    pub async fn update(
+        &mut self,
+        bio: String,
+        avatar: Vec<u8>,
+        db: &Db,
+        actor: &str,
+        operation: &str,
+    ) -> Result<()> {
+        let old_snapshot = self.snapshot();
+        let old = self.avatar_hash.clone();
+        let mut avatar_lock = None;
+        if !avatar.is_empty() {
+            let (hash, lock) = write_avatar(db, &avatar).await?;
+            self.avatar_hash = hash;
+            avatar_lock = lock;
+        }
+        self.bio = bio;
+
+        let audit = self.audit(actor, operation, old_snapshot);
+        let pruned = self.save_profile(db, &audit).await;
+        drop(avatar_lock);
+        let pruned = match pruned {
+            Ok(pruned) => pruned,
+            Err(e) => {
+                if !avatar.is_empty() {
+                    let _ = release_avatar(db, &self.avatar_hash).await;
+                }
+                return Err(e);
+            }
+        };
+        cache::invalidate(&self.name);
+
+        if !avatar.is_empty() {
+            self.avatar = avatar;
+            release_old_avatar(db, &old).await;
+        }
+        release_pruned(db, pruned).await;
 
-        Ok(Self {
-            avatar,
-            id: res.id,
-            name: res.name.trim().to_owned(),
-            pid: PeerId::from_hex(res.pid.trim()).unwrap_or(PeerId::default()),
-            bio: res.bio,
-            is_actived: res.is_actived,
-            datetime: res.datetime,
-        })
+        Ok(())
     }
 
-    pub async fn _get(base: &PathBuf, id: &i64) -> Result<User> {
-        let res = sqlx::query!(
-            "SELECT id, name, pid, bio, is_actived, datetime FROM users WHERE is_deleted = false and id = $1",
-            id
-        ).fetch_one(get_pool()?).await.map_err(|_| anyhow!("database failure."))?;
+    /// restore bio & avatar of the version, saved as a new version.
+    pub async fn revert(&mut self, version: &Version, db: &Db, actor: &str) -> Result<()> {
+        let avatar_lock = lock_avatar(&version.avatar_hash).await;
+        let avatar = read_avatar(db, &version.avatar_hash).await?;
+        let old_snapshot = self.snapshot();
+        let old = std::mem::replace(&mut self.avatar_hash, version.avatar_hash.clone());
+        self.bio = version.bio.clone();
+
+        let audit = self.audit(actor, "revert", old_snapshot);
+        let pruned = self.save_profile(db, &audit).await?;
+        drop(avatar_lock);
+        cache::invalidate(&self.name);
+
+        self.avatar = avatar;
+        if old != self.avatar_hash {
+            release_old_avatar(db, &old).await;
+        }
+        release_pruned(db, pruned).await;
 
-        let avatar = read_avatar(base, id).await?;
+        Ok(())
+    }
 
-        Ok(Self {
-            avatar,
-            id: res.id,
-            name: res.name.trim().to_owned(),
-            pid: PeerId::from_hex(res.pid.trim()).unwrap_or(PeerId::default()),
-            bio: res.bio,
-            is_actived: res.is_actived,
-            datetime: res.datetime,
-        })
+    /// banned user cannot be actived by self, audited as the actor.
+    pub async fn active(&mut self, db: &Db, active: bool, actor: &str) -> Result<()> {
+        let old = self.snapshot();
+        self.is_actived = active;
+        let operation = if active { "active" } else { "suspend" };
+        let audit = self.audit(actor, operation, old);
+        db.store.user_active(&self.id, active, &audit).await?;
+        cache::invalidate_id(self.id);
+        Ok(())
     }
 
-    pub async fn insert(&mut self, base: &PathBuf) -> Result<()> {
-        // check if unique group id.
-        let unique_check = sqlx::query!("SELECT id from users WHERE name = $1", self.name)
-            .fetch_optional(get_pool()?)
-            .await
-            .map_err(|_| anyhow!("database failure."))?;
-        if unique_check.is_some() {
-            return Err(anyhow!("unique username."));
-        }
+    /// admin ban or unban the user.
+    pub async fn ban(&mut self, db: &Db, ban: bool, actor: &str) -> Result<()> {
+        let old = self.snapshot();
+        self.is_actived = !ban;
+        let operation = if ban { "ban" } else { "unban" };
+        let audit = self.audit(actor, operation, old);
+        db.store.user_ban(&self.id, ban, &audit).await?;
+        cache::invalidate_id(self.id);
+        Ok(())
+    }
 
-        let rec = sqlx::query!(
-            "INSERT INTO users (name, pid, bio, is_actived, datetime) VALUES ($1, $2, $3, $4, $5) RETURNING id",
-            self.name,
-            self.pid.to_hex(),
-            self.bio,
-            self.is_actived,
-            self.datetime
-        ).fetch_one(get_pool()?).await.map_err(|_| anyhow!("database failure."))?;
+    /// moderation clear the avatar, audited as the actor.
+    pub async fn clear_avatar(&mut self, db: &Db, actor: &str) -> Result<()> {
+        let old_snapshot = self.snapshot();
+        let old = std::mem::take(&mut self.avatar_hash);
 
-        self.id = rec.id;
-        let _ = write_avatar(base, &self.id, &self.avatar).await;
+        let audit = self.audit(actor, "clear-avatar", old_snapshot);
+        let pruned = self.save_profile(db, &audit).await?;
+        cache::invalidate(&self.name);
 
+        self.avatar = vec![];
+        release_old_avatar(db, &old).await;
+        release_pruned(db, pruned).await;
         Ok(())
     }
 
-    pub async fn update(id: &i64, bio: &str, avatar: &Vec<u8>, base: &PathBuf) -> Result<()> {
-        let _ = sqlx::query!("UPDATE users SET bio = $1 WHERE id = $2", bio, id)
-            .execute(get_pool()?)
-            .await
-            .map_err(|_| anyhow!("database failure."))?;
+    /// soft delete, audited as the actor.
+    pub async fn delete(&self, db: &Db, actor: &str) -> Result<()> {
+        let audit = Audit::new(
+            actor.to_owned(),
+            "delete",
+            self.id,
+            self.name.clone(),
+            self.snapshot(),
+            String::new(),
+        );
+        db.store.user_delete(&self.id, &audit).await?;
+        cache::invalidate(&self.name);
 
-        let _ = write_avatar(base, id, avatar).await;
+        release_old_avatar(db, &self.avatar_hash).await;
 
         Ok(())
     }
 
-    pub async fn active(id: &i64, active: bool) -> Result<()> {
-        let _ = sqlx::query!("UPDATE users SET is_actived = $1 WHERE id = $2", active, id)
-            .execute(get_pool()?)
+    /// save the bio & avatar hash as a new version with the audit, bio is
+    /// encrypted in the lock so key rotation never misses it.
+    /// return the avatar hashes of pruned versions.
+    async fn save_profile(&self, db: &Db, audit: &Audit) -> Result<Vec<String>> {
+        let _guard = BIO_LOCK.lock().await;
+        let bio = encrypt_text(&self.bio)?;
+        db.store
+            .user_profile(&self.id, &bio, &self.avatar_hash, MAX_VERSIONS, audit)
             .await
-            .map_err(|_| anyhow!("database failure."))?;
+    }
 
-        Ok(())
+    /// audit of the change, from the old snapshot to the current one.
+    fn audit(&self, actor: &str, operation: &str, old: String) -> Audit {
+        Audit::new(
+            actor.to_owned(),
+            operation,
+            self.id,
+            self.name.clone(),
+            old,
+            self.snapshot(),
+        )
+    }
+}
+
+/// max versions kept of every user, older are pruned.
+pub(crate) const MAX_VERSIONS: i64 = 20;
+
+/// Version Model. profile (bio & avatar) of the user at a time.
+#[derive(Clone)]
+pub struct Version {
+    /// db auto-increment id.
+    pub id: i64,
+    /// user id.
+    pub user_id: i64,
+    /// bio.
+    pub bio: String,
+    /// avatar blake3 hash, empty if no avatar.
+    pub avatar_hash: String,
+    /// changed time.
+    pub datetime: i64,
+}
+
+impl Version {
+    pub fn to_rpc(self) -> RpcParam {
+        json!([
+            self.id,
+            self.user_id,
+            self.bio,
+            self.avatar_hash,
+            self.datetime
+        ])
+    }
+
+    /// versions of the user, newest first, bio is decrypted,
+    /// versions failed to decrypt are skipped & logged.
+    pub async fn list(db: &Db, user_id: &i64) -> Result<Vec<Self>> {
+        let mut versions = vec![];
+        for mut version in db.store.version_list(user_id).await? {
+            match decrypt_text(std::mem::take(&mut version.bio)) {
+                Ok(bio) => {
+                    version.bio = bio;
+                    versions.push(version);
+                }
+                Err(e) => warn!("version {} skipped: {}", version.id, e),
+            }
+        }
+        Ok(versions)
+    }
+
+    pub async fn get(db: &Db, id: &i64) -> Result<Self> {
+        let mut version = db.store.version_get(id).await?;
+        version.bio = decrypt_text(version.bio)?;
+        Ok(version)
+    }
+}
+
+/// Attribute Model. profile key/value of the user, beyond name, bio & avatar.
+#[derive(Clone)]
+pub struct Attribute {
+    /// user id.
+    pub user_id: i64,
+    /// well-known key in the schema, or custom `x-` key.
+    pub key: String,
+    /// value.
+    pub value: String,
+    /// visible to all peers, or only the owner.
+    pub is_public: bool,
+    /// changed time.
+    pub datetime: i64,
+}
+
+impl Attribute {
+    pub fn to_rpc(self) -> RpcParam {
+        json!([self.key, self.value, self.is_public, self.datetime])
+    }
+
+    /// attributes of the user, value is decrypted,
+    /// attributes failed to decrypt are skipped & logged.
+    pub async fn list(db: &Db, user_id: &i64) -> Result<Vec<Self>> {
+        let mut attributes = vec![];
+        for mut attribute in db.store.attribute_list(user_id).await? {
+            match decrypt_text(std::mem::take(&mut attribute.value)) {
+                Ok(value) => {
+                    attribute.value = value;
+                    attributes.push(attribute);
+                }
+                Err(e) => warn!("attribute {} of {} skipped: {}", attribute.key, user_id, e),
+            }
+        }
+        Ok(attributes)
     }
 
-    pub async fn delete(id: &i64, base: &PathBuf) -> Result<()> {
-        let _ = sqlx::query!(
-            "UPDATE users SET is_actived = false, is_deleted = true WHERE id = $1",
-            id
+    /// (key, value) of the user visible to the peer, owner sees all.
+    pub async fn visible(db: &Db, user: &User, peer: &PeerId) -> Result<Vec<(String, String)>> {
+        let is_owner = user.pid == *peer;
+        Ok(Self::list(db, &user.id)
+            .await?
+            .into_iter()
+            .filter(|a| is_owner || a.is_public)
+            .map(|a| (a.key, a.value))
+            .collect())
+    }
+
+    /// replace all attributes (key, value, is_public) of the user,
+    /// empty value is removed. audited as the actor.
+    pub async fn save(
+        db: &Db,
+        user: &User,
+        attributes: Vec<(String, String, bool)>,
+        actor: &str,
+    ) -> Result<()> {
+        profile::validate(&attributes)?;
+        let old = db
+            .store
+            .attribute_list(&user.id)
+            .await?
+            .into_iter()
+            .map(|a| (a.key, a.is_public))
+            .collect();
+        let attributes: Vec<(String, String, bool)> = attributes
+            .into_iter()
+            .filter(|(_, value, _)| !value.is_empty())
+            .collect();
+        let new = attributes
+            .iter()
+            .map(|(key, _, is_public)| (key.clone(), *is_public))
+            .collect();
+        let audit = Audit::new(
+            actor.to_owned(),
+            "attributes",
+            user.id,
+            user.name.clone(),
+            Self::snapshot(old),
+            Self::snapshot(new),
+        );
+
+        let datetime = now();
+        let _guard = BIO_LOCK.lock().await;
+        let mut rows = vec![];
+        for (key, value, is_public) in attributes {
+            rows.push(Attribute {
+                user_id: user.id,
+                value: encrypt_text(&value)?,
+                key,
+                is_public,
+                datetime,
+            });
+        }
+        db.store.attribute_set(&user.id, &rows, Some(&audit)).await
+    }
+
+    /// attributes (key, is_public) saved in audit log, values never saved.
+    fn snapshot(attributes: Vec<(String, bool)>) -> String {
+        json!(attributes).to_string()
+    }
+}
+
+/// Did Model. signed DID document of the user.
+#[derive(Clone)]
+pub struct Did {
+    /// user id.
+    pub user_id: i64,
+    /// document JSON, exactly the signed bytes.
+    pub document: String,
+    /// id of the key signed the document.
+    pub key_id: String,
+    /// signature hex.
+    pub signature: String,
+    /// sequence of the document.
+    pub sequence: i64,
+    /// published time.
+    pub datetime: i64,
+}
+
+impl Did {
+    pub fn to_rpc(self, user: &User) -> RpcParam {
+        json!({
+            "did": did(&user.pid),
+            "name": user.name,
+            "document": serde_json::from_str::<RpcParam>(&self.document).unwrap_or_default(),
+            "key_id": self.key_id,
+            "signature": self.signature,
+            "sequence": self.sequence,
+            "datetime": self.datetime,
+        })
+    }
+
+    /// DID document of the user, none if not published.
+    pub async fn get(db: &Db, user_id: &i64) -> Result<Option<Self>> {
+        db.store.did_get(user_id).await
+    }
+
+    /// check the signed document of the user and save it, audited as the actor.
+    pub async fn publish(
+        db: &Db,
+        user: &User,
+        document: Vec<u8>,
+        key_id: String,
+        signature: Vec<u8>,
+        actor: &str,
+    ) -> Result<Self> {
+        let new = DidDocument::parse(&document, &user.pid)?;
+        let saved = Self::get(db, &user.id).await?;
+        let current = match &saved {
+            Some(did) => Some(DidDocument::parse(did.document.as_bytes(), &user.pid)?),
+            None => None,
+        };
+        check_update(current.as_ref(), &new, &document, &key_id, &signature)?;
+
+        let did = Did {
+            user_id: user.id,
+            document: String::from_utf8(document)?,
+            signature: hex::encode(signature),
+            sequence: new.sequence,
+            datetime: now(),
+            key_id,
+        };
+        let audit = Audit::new(
+            actor.to_owned(),
+            "did",
+            user.id,
+            user.name.clone(),
+            saved.map(|d| d.sequence.to_string()).unwrap_or_default(),
+            did.sequence.to_string(),
+        );
+        if !db.store.did_set(&did, &audit).await? {
+            return Err(anyhow!(
+                "did document sequence {} is outdated",
+                did.sequence
+            ));
+        }
+        Ok(did)
+    }
+}
+
+/// release the avatars of the versions pruned in the committed change.
+async fn release_pruned(db: &Db, hashes: Vec<String>) {
+    for hash in hashes {
+        release_old_avatar(db, &hash).await;
+    }
+}
+
+/// release the avatar after the row committed,
+/// a failed release leaves an orphan file, cleaned by reconciliation.
+async fn release_old_avatar(db: &Db, hash: &str) {
+    if let Err(e) = release_avatar(db, hash).await {
+        warn!("release avatar {} failure: {}", hash, e);
+    }
+}
+
+/// Audit log filter.
+#[derive(Default)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub operation: Option<String>,
+    pub user_id: Option<i64>,
+    pub start: i64,
+    pub end: i64,
+    pub limit: i64,
+}
+
+/// Audit Model. append-only log of all state-changing operations,
+/// saved by the store in the transaction of the change.
+#[derive(Clone)]
+pub struct Audit {
+    /// db auto-increment id.
+    pub id: i64,
+    /// actor, PeerId hex or admin identity.
+    pub actor: String,
+    /// operation name.
+    pub operation: String,
+    /// target user id.
+    pub user_id: i64,
+    /// target user name.
+    pub name: String,
+    /// value before operation.
+    pub old_value: String,
+    /// value after operation.
+    pub new_value: String,
+    /// operation time.
+    pub datetime: i64,
+}
+
+impl Audit {
+    pub fn new(
+        actor: String,
+        operation: &str,
+        user_id: i64,
+        name: String,
+        old_value: String,
+        new_value: String,
+    ) -> Self {
+        Self {
+            actor,
+            user_id,
+            name,
+            old_value,
+            new_value,
+            operation: operation.to_owned(),
+            datetime: now(),
+            id: 0,
+        }
+    }
+
+    pub fn to_rpc(self) -> RpcParam {
+        json!([
+            self.id,
+            self.actor,
+            self.operation,
+            self.user_id,
+            self.name,
+            self.old_value,
+            self.new_value,
+            self.datetime
+        ])
+    }
+
+    /// CSV header for export.
+    pub fn csv_header() -> &'static str {
+        "id,actor,operation,user_id,name,old_value,new_value,datetime"
+    }
+
+    /// CSV line for export.
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{}",
+            self.id,
+            csv_escape(&self.actor),
+            csv_escape(&self.operation),
+            self.user_id,
+            csv_escape(&self.name),
+            csv_escape(&self.old_value),
+            csv_escape(&self.new_value),
+            self.datetime
         )
-        .execute(get_pool()?)
-        .await
-        .map_err(|_| anyhow!("database failure."))?;
+    }
 
-        let _ = delete_avatar(base, id).await;
+    pub async fn list(db: &Db, filter: &AuditFilter) -> Result<Vec<Self>> {
+        db.store.audit_list(filter).await
+    }
+}
 
-        Ok(())
+/// quote a CSV field when needed.
+pub fn csv_escape(s: &str) -> String {
+    if s.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
     }
 }
 
-//pub struct Request {}
+/// Report Model. abuse report from peers.
+#[derive(Clone)]
+pub struct Report {
+    /// db auto-increment id.
+    pub id: i64,
+    /// reporter PeerId.
+    pub reporter: PeerId,
+    /// reported user id.
+    pub user_id: i64,
+    /// reported user name.
+    pub name: String,
+    /// reason of the report.
+    pub reason: String,
+    /// pending, reviewing, resolved or dismissed.
+    pub status: String,
+    /// moderation action: hide-bio, clear-avatar, ban or none.
+    pub action: String,
+    /// report time.
+    pub datetime: i64,
+    /// resolved time.
+    pub resolved_at: i64,
+}
+
+impl Report {
+    pub fn new(reporter: PeerId, user: &User, reason: String) -> Self {
+        Self {
+            reporter,
+            reason,
+            user_id: user.id,
+            name: user.name.clone(),
+            status: "pending".to_owned(),
+            action: String::new(),
+            datetime: now(),
+            resolved_at: 0,
+            id: 0,
+        }
+    }
+
+    pub fn to_rpc(self) -> RpcParam {
+        json!([
+            self.id,
+            self.reporter.to_hex(),
+            self.user_id,
+            self.name,
+            self.reason,
+            self.status,
+            self.action,
+            self.datetime,
+            self.resolved_at
+        ])
+    }
+
+    /// list reports by status, empty status is all.
+    pub async fn list(db: &Db, status: &str) -> Result<Vec<Self>> {
+        db.store.report_list(status).await
+    }
+
+    pub async fn get(db: &Db, id: &i64) -> Result<Self> {
+        db.store.report_get(id).await
+    }
+
+    pub async fn insert(&mut self, db: &Db) -> Result<()> {
+        self.id = db.store.report_insert(self).await?;
+        Ok(())
+    }
+
+    /// change status, resolved time is set when status is final.
+    pub async fn update(db: &Db, id: &i64, status: &str, action: &str) -> Result<()> {
+        let resolved_at = if status == "resolved" || status == "dismissed" {
+            now()
+        } else {
+            0
+        };
+
+        db.store
+            .report_update(id, status, action, resolved_at)
+            .await
+    }
+}
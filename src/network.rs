@@ -0,0 +1,118 @@
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tdn::types::{
+    message::{NetworkType, SendMessage},
+    primitives::Peer,
+    rpc::{json, RpcParam},
+};
+use tokio::sync::{mpsc::Sender, Notify, RwLock};
+
+use crate::layer::Layer;
+use crate::models::now;
+use crate::shutdown;
+
+/// default seconds of network lost before exit, 0 is never.
+pub(crate) const DEFAULT_LOST_TIMEOUT: u64 = 600;
+
+/// seconds between reconnections, doubled after every failure.
+const RECONNECT_MIN: u64 = 1;
+const RECONNECT_MAX: u64 = 60;
+
+/// time of the network lost, 0 is healthy.
+static LOST_AT: AtomicI64 = AtomicI64::new(0);
+/// times of network lost & reconnection attempts.
+static LOSTS: AtomicU64 = AtomicU64::new(0);
+static RECONNECTS: AtomicU64 = AtomicU64::new(0);
+/// network not recovered in the timeout.
+static GIVE_UP: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// network status, "ok" or "degraded".
+#[inline]
+pub(crate) fn status() -> &'static str {
+    if LOST_AT.load(Ordering::SeqCst) == 0 {
+        "ok"
+    } else {
+        "degraded"
+    }
+}
+
+/// network lost, reconnect to bootstrap peers in background, until recovered.
+pub(crate) fn lost(layer: Arc<RwLock<Layer>>, sender: Sender<SendMessage>, timeout: u64) {
+    let lost_at = now();
+    if LOST_AT
+        .compare_exchange(0, lost_at, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        // already reconnecting.
+        return;
+    }
+    LOSTS.fetch_add(1, Ordering::Relaxed);
+    warn!("Network lost, domain is degraded");
+
+    tokio::spawn(async move {
+        let mut delay = RECONNECT_MIN;
+        loop {
+            tokio::time::sleep(Duration::from_secs(delay)).await;
+            if LOST_AT.load(Ordering::SeqCst) != lost_at || shutdown::is_stopping() {
+                return;
+            }
+            if timeout > 0 && now() - lost_at >= timeout as i64 {
+                error!("Network not recovered in {}s, give up", timeout);
+                GIVE_UP.notify_one();
+                return;
+            }
+
+            let peers = layer.read().await.bootstrap.clone();
+            if peers.is_empty() {
+                warn!("Network lost, no bootstrap peers to reconnect");
+            }
+            RECONNECTS.fetch_add(1, Ordering::Relaxed);
+            for addr in peers {
+                info!("Reconnecting bootstrap peer {}", addr);
+                let msg = SendMessage::Network(NetworkType::Connect(Peer::socket(addr)));
+                if sender.send(msg).await.is_err() {
+                    return;
+                }
+            }
+            delay = (delay * 2).min(RECONNECT_MAX);
+        }
+    });
+}
+
+/// message received from the network, recovered if lost.
+#[inline]
+pub(crate) fn alive() {
+    recover("message received");
+}
+
+/// a peer connected, or the reconnection to it succeeded, recovered if lost.
+#[inline]
+pub(crate) fn connected() {
+    recover("peer connected");
+}
+
+/// clear the lost time, the reconnection stops.
+fn recover(reason: &str) {
+    let lost_at = LOST_AT.swap(0, Ordering::SeqCst);
+    if lost_at != 0 {
+        info!("Network recovered ({}) after {}s", reason, now() - lost_at);
+    }
+}
+
+/// wait the network not recovered in the timeout.
+pub(crate) async fn given_up() {
+    GIVE_UP.notified().await
+}
+
+/// network status & reconnections.
+pub(crate) fn stats() -> RpcParam {
+    let lost_at = LOST_AT.load(Ordering::SeqCst);
+    json!({
+        "status": status(),
+        "lost_at": lost_at,
+        "losts": LOSTS.load(Ordering::Relaxed),
+        "reconnects": RECONNECTS.load(Ordering::Relaxed),
+    })
+}
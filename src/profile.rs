@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+use tdn::types::{
+    primitives::Result,
+    rpc::{json, RpcParam},
+};
+
+/// well-known profile attributes, (key, max bytes of value).
+pub(crate) const ATTRIBUTE_SCHEMA: [(&'static str, usize); 6] = [
+    ("display_name", 64),
+    ("website", 256),
+    ("location", 64),
+    ("email", 256),
+    ("public_key", 4096),
+    ("pgp_key", 8192),
+];
+
+/// app defined attributes start with it, e.g. `x-github`.
+pub(crate) const CUSTOM_PREFIX: &'static str = "x-";
+
+/// max bytes of custom attribute key & value.
+const CUSTOM_KEY_MAX: usize = 32;
+const CUSTOM_VALUE_MAX: usize = 256;
+
+/// max attributes of every user.
+pub(crate) const MAX_ATTRIBUTES: usize = 16;
+
+/// max bytes of the value, none if the key is unknown.
+pub(crate) fn max_size(key: &str) -> Option<usize> {
+    if let Some((_, size)) = ATTRIBUTE_SCHEMA.iter().find(|(k, _)| *k == key) {
+        return Some(*size);
+    }
+    let name = key.strip_prefix(CUSTOM_PREFIX)?;
+    if key.len() <= CUSTOM_KEY_MAX
+        && !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+    {
+        Some(CUSTOM_VALUE_MAX)
+    } else {
+        None
+    }
+}
+
+/// check the attributes (key, value, is_public) by the schema.
+pub(crate) fn validate(attributes: &[(String, String, bool)]) -> Result<()> {
+    if attributes.len() > MAX_ATTRIBUTES {
+        return Err(anyhow!(
+            "too many attributes: {} > {}",
+            attributes.len(),
+            MAX_ATTRIBUTES
+        ));
+    }
+
+    let mut keys = HashSet::new();
+    for (key, value, _) in attributes {
+        let max = max_size(key).ok_or(anyhow!("unknown attribute {}", key))?;
+        if value.len() > max {
+            return Err(anyhow!(
+                "attribute {} too large: {} > {} bytes",
+                key,
+                value.len(),
+                max
+            ));
+        }
+        if !keys.insert(key) {
+            return Err(anyhow!("duplicate attribute {}", key));
+        }
+    }
+    Ok(())
+}
+
+/// schema for apps, well-known keys & limits.
+pub(crate) fn schema() -> RpcParam {
+    json!({
+        "attributes": ATTRIBUTE_SCHEMA,
+        "custom_prefix": CUSTOM_PREFIX,
+        "custom_key_max": CUSTOM_KEY_MAX,
+        "custom_value_max": CUSTOM_VALUE_MAX,
+        "max_attributes": MAX_ATTRIBUTES,
+    })
+}
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use tdn::types::{
+    group::GroupId,
+    message::SendType,
+    primitives::{HandleResult, PeerId, Result},
+};
+
+/// prefix of the extension messages, peers only knowing `domain_types`
+/// fail to decode them and ignore.
+pub(crate) const EXT_MAGIC: &'static [u8] = b"ESSE-DOMAIN-EXT/1";
+
+/// Domain extension requests from peer, beyond `LayerPeerEvent`.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) enum ExtPeerEvent {
+    /// avatar of the name, with the hash cached by peer.
+    Avatar(String, String),
+    /// register with name, bio, avatar & profile attributes (key, value, is_public).
+    RegisterProfile(String, String, Vec<u8>, Vec<(String, String, bool)>),
+    /// update bio, avatar & profile attributes.
+    UpdateProfile(String, String, Vec<u8>, Vec<(String, String, bool)>),
+    /// profile of the name.
+    Profile(String),
+    /// report the name, with the reason.
+    Report(String, String),
+    /// profile versions of the name, owner only.
+    Versions(String),
+    /// revert to the version, owner only.
+    Revert(String, i64),
+    /// publish the DID document (name, document, key id, signature).
+    PublishDid(String, Vec<u8>, String, Vec<u8>),
+    /// DID document of the name.
+    ResolveDid(String),
+}
+
+/// Domain extension responses to peer, beyond `LayerServerEvent`.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) enum ExtServerEvent {
+    /// name, avatar hash, avatar bytes (empty if the peer cached it).
+    Avatar(String, String, Vec<u8>),
+    /// pid, name, bio, avatar, attributes visible to the peer.
+    Profile(PeerId, String, String, Vec<u8>, Vec<(String, String)>),
+    /// reported name, and the moderation action.
+    Reported(String, String),
+    /// name, versions (id, bio, avatar hash, datetime).
+    Versions(String, Vec<(i64, String, String, i64)>),
+    /// name, version id, is ok.
+    Reverted(String, i64, bool),
+    /// name, pid, document, key id, signature.
+    Did(String, PeerId, Vec<u8>, String, Vec<u8>),
+    /// name, is saved.
+    DidPublished(String, bool),
+    /// name, reason of the register or update rejected (e.g. invalid avatar),
+    /// a rejected `Register` is also answered `Result(name, false)` before it.
+    Rejected(String, String),
+}
+
+impl ExtPeerEvent {
+    /// decode the extension request, none if not an extension message.
+    pub fn decode(bytes: &[u8]) -> Option<Result<Self>> {
+        let data = bytes.strip_prefix(EXT_MAGIC)?;
+        Some(bincode::deserialize(data).map_err(|e| e.into()))
+    }
+
+    /// the name requested.
+    pub fn name(&self) -> &str {
+        match self {
+            ExtPeerEvent::Avatar(name, _)
+            | ExtPeerEvent::RegisterProfile(name, _, _, _)
+            | ExtPeerEvent::UpdateProfile(name, _, _, _)
+            | ExtPeerEvent::Profile(name)
+            | ExtPeerEvent::Report(name, _)
+            | ExtPeerEvent::Versions(name)
+            | ExtPeerEvent::Revert(name, _)
+            | ExtPeerEvent::PublishDid(name, _, _, _)
+            | ExtPeerEvent::ResolveDid(name) => name,
+        }
+    }
+}
+
+/// Domain server extension message to peer.
+#[inline]
+pub(crate) fn add_ext_layer(
+    results: &mut HandleResult,
+    addr: PeerId,
+    event: ExtServerEvent,
+    tgid: GroupId,
+) -> Result<()> {
+    let mut data = EXT_MAGIC.to_vec();
+    data.extend(bincode::serialize(&event)?);
+    let s = SendType::Event(0, addr, data);
+    results.layers.push((tgid, s));
+    Ok(())
+}
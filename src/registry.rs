@@ -0,0 +1,567 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tdn::types::{
+    primitives::{PeerId, Result},
+    rpc::{json, RpcParam},
+};
+use tokio::fs::{self, File};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+
+use crate::avatar::normalize;
+use crate::models::{csv_escape, Attribute, Audit, AuditFilter, Did, User};
+use crate::storage::{Db, NameConflict};
+
+/// users count of every database page when export.
+const EXPORT_PAGE_SIZE: i64 = 100;
+
+/// folder in the db_path of the files exported & imported by RPC.
+pub(crate) const TRANSFER_DIR: &'static str = "transfers";
+
+/// suffix of the exporting file, renamed when finished.
+const TEMP_SUFFIX: &'static str = ".tmp";
+
+const CSV_HEADER: &'static str =
+    "name,pid,bio,avatar,is_actived,is_deleted,datetime,history,deleted_at,is_banned,attributes,did";
+
+/// Registry file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// JSON lines, one user per line.
+    Json,
+    /// CSV with header, history is a JSON array column.
+    Csv,
+}
+
+impl Format {
+    /// from the format name, or the file extension if name is empty.
+    pub fn parse(name: &str, path: &Path) -> Result<Self> {
+        let name = if name.is_empty() {
+            path.extension().and_then(|e| e.to_str()).unwrap_or("json")
+        } else {
+            name
+        };
+        match name {
+            "json" | "jsonl" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(anyhow!("unsupported format: {}", name)),
+        }
+    }
+}
+
+/// Audit history of the user.
+#[derive(Serialize, Deserialize)]
+struct History {
+    actor: String,
+    operation: String,
+    old_value: String,
+    new_value: String,
+    datetime: i64,
+}
+
+/// Profile attribute of the user.
+#[derive(Serialize, Deserialize)]
+struct Property {
+    key: String,
+    value: String,
+    is_public: bool,
+}
+
+/// Signed DID document of the user.
+#[derive(Serialize, Deserialize)]
+struct Document {
+    /// document JSON, exactly the signed bytes.
+    document: String,
+    key_id: String,
+    /// signature hex.
+    signature: String,
+}
+
+/// Registry record of a user.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    name: String,
+    pid: String,
+    bio: String,
+    /// avatar bytes, hex encoded.
+    avatar: String,
+    is_actived: bool,
+    is_deleted: bool,
+    datetime: i64,
+    history: Vec<History>,
+    /// deleted time, files before it have none.
+    #[serde(default)]
+    deleted_at: i64,
+    /// banned by admin, files before it have none.
+    #[serde(default)]
+    is_banned: bool,
+    /// profile attributes, files before them have none.
+    #[serde(default)]
+    attributes: Vec<Property>,
+    /// published DID document, files before it have none.
+    #[serde(default)]
+    did: Option<Document>,
+}
+
+impl Record {
+    async fn from_user(db: &Db, user: User) -> Result<Self> {
+        let filter = AuditFilter {
+            user_id: Some(user.id),
+            ..Default::default()
+        };
+        let mut history = vec![];
+        // audit list is newest first, history keep the time order.
+        for audit in Audit::list(db, &filter).await?.into_iter().rev() {
+            history.push(History {
+                actor: audit.actor,
+                operation: audit.operation,
+                old_value: audit.old_value,
+                new_value: audit.new_value,
+                datetime: audit.datetime,
+            });
+        }
+
+        let attributes = Attribute::list(db, &user.id)
+            .await?
+            .into_iter()
+            .map(|a| Property {
+                key: a.key,
+                value: a.value,
+                is_public: a.is_public,
+            })
+            .collect();
+
+        let did = Did::get(db, &user.id).await?.map(|d| Document {
+            document: d.document,
+            key_id: d.key_id,
+            signature: d.signature,
+        });
+
+        Ok(Self {
+            history,
+            attributes,
+            did,
+            is_banned: db.store.user_banned(&user.id).await?,
+            name: user.name,
+            pid: user.pid.to_hex(),
+            bio: user.bio,
+            avatar: hex::encode(&user.avatar),
+            is_actived: user.is_actived,
+            is_deleted: user.is_deleted,
+            deleted_at: user.deleted_at,
+            datetime: user.datetime,
+        })
+    }
+
+    fn to_csv(&self) -> Result<String> {
+        Ok(format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            csv_escape(&self.name),
+            self.pid,
+            csv_escape(&self.bio),
+            self.avatar,
+            self.is_actived,
+            self.is_deleted,
+            self.datetime,
+            csv_escape(&serde_json::to_string(&self.history)?),
+            self.deleted_at,
+            self.is_banned,
+            csv_escape(&serde_json::to_string(&self.attributes)?),
+            csv_escape(&serde_json::to_string(&self.did)?)
+        ))
+    }
+
+    fn from_csv(line: &str) -> Result<Self> {
+        let fields = csv_split(line);
+        // files before deleted_at have 8 fields, before is_banned have 9,
+        // before attributes have 10, before did have 11.
+        if fields.len() < 8 || fields.len() > 12 {
+            return Err(anyhow!("invalid csv record"));
+        }
+
+        Ok(Self {
+            name: fields[0].clone(),
+            pid: fields[1].clone(),
+            bio: fields[2].clone(),
+            avatar: fields[3].clone(),
+            is_actived: fields[4].parse()?,
+            is_deleted: fields[5].parse()?,
+            datetime: fields[6].parse()?,
+            history: serde_json::from_str(&fields[7])?,
+            deleted_at: match fields.get(8) {
+                Some(field) => field.parse()?,
+                None => 0,
+            },
+            is_banned: match fields.get(9) {
+                Some(field) => field.parse()?,
+                None => false,
+            },
+            attributes: match fields.get(10) {
+                Some(field) => serde_json::from_str(field)?,
+                None => vec![],
+            },
+            did: match fields.get(11) {
+                Some(field) => serde_json::from_str(field)?,
+                None => None,
+            },
+        })
+    }
+
+    /// the user of the record, the rest (history, banned...) is kept.
+    fn take_user(&mut self) -> Result<User> {
+        let pid = PeerId::from_hex(&self.pid).map_err(|_| anyhow!("invalid pid"))?;
+        let avatar = hex::decode(&self.avatar)?;
+        let mut user = User::new(
+            std::mem::take(&mut self.name),
+            pid,
+            std::mem::take(&mut self.bio),
+            avatar,
+        );
+        user.is_actived = self.is_actived;
+        user.is_deleted = self.is_deleted;
+        user.deleted_at = self.deleted_at;
+        user.datetime = self.datetime;
+        Ok(user)
+    }
+}
+
+/// split a CSV record to fields.
+fn csv_split(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Import result.
+#[derive(Default)]
+pub(crate) struct ImportReport {
+    pub imported: usize,
+    /// names already registered in this domain.
+    pub conflicts: Vec<String>,
+    /// records which cannot be imported, and the reason.
+    pub failures: Vec<(String, String)>,
+}
+
+impl ImportReport {
+    pub fn to_rpc(&self) -> RpcParam {
+        json!({
+            "imported": self.imported,
+            "conflicts": self.conflicts,
+            "failures": self.failures,
+        })
+    }
+}
+
+/// Export result.
+#[derive(Default)]
+pub(crate) struct ExportReport {
+    pub exported: usize,
+    /// users which cannot be exported, and the reason.
+    pub failures: Vec<(String, String)>,
+}
+
+impl ExportReport {
+    pub fn to_rpc(&self) -> RpcParam {
+        json!({
+            "exported": self.exported,
+            "failures": self.failures,
+        })
+    }
+}
+
+/// export all users (include deleted) to the file, written to a temp file
+/// and renamed when finished, never leaves a half file.
+pub(crate) async fn export(db: &Db, path: &Path, format: Format) -> Result<ExportReport> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(TEMP_SUFFIX);
+    let tmp = PathBuf::from(tmp);
+
+    match export_to(db, &tmp, format).await {
+        Ok(report) => {
+            fs::rename(&tmp, path).await?;
+            info!(
+                "Exported {} users to {:?}, {} failures",
+                report.exported,
+                path,
+                report.failures.len()
+            );
+            Ok(report)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&tmp).await;
+            Err(e)
+        }
+    }
+}
+
+/// write the users page by page, a user failed to load is reported & skipped.
+async fn export_to(db: &Db, path: &Path, format: Format) -> Result<ExportReport> {
+    let mut report = ExportReport::default();
+    let file = File::create(path).await?;
+    let mut writer = BufWriter::new(file);
+    if format == Format::Csv {
+        writer.write_all(CSV_HEADER.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+    }
+
+    let mut after = 0;
+    loop {
+        let users = db.store.user_list_page(after, EXPORT_PAGE_SIZE).await?;
+        if users.is_empty() {
+            break;
+        }
+
+        for user in users {
+            after = user.id;
+            let name = user.name.clone();
+            let line = match export_line(db, user, format).await {
+                Ok(line) => line,
+                Err(e) => {
+                    warn!("export user {} failure: {}", name, e);
+                    report.failures.push((name, e.to_string()));
+                    continue;
+                }
+            };
+            writer.write_all(line.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+            report.exported += 1;
+        }
+    }
+    writer.flush().await?;
+    writer.into_inner().sync_all().await?;
+    Ok(report)
+}
+
+async fn export_line(db: &Db, user: User, format: Format) -> Result<String> {
+    let record = Record::from_user(db, user.load(db).await?).await?;
+    match format {
+        Format::Json => Ok(serde_json::to_string(&record)?),
+        Format::Csv => record.to_csv(),
+    }
+}
+
+/// restore the state of the record not saved with the imported user.
+async fn restore_state(db: &Db, user: &mut User, record: Record) -> Result<()> {
+    if !record.attributes.is_empty() {
+        let attributes = record
+            .attributes
+            .into_iter()
+            .map(|p| (p.key, p.value, p.is_public))
+            .collect();
+        Attribute::save(db, user, attributes, "admin")
+            .await
+            .map_err(|e| anyhow!("attributes not saved: {}", e))?;
+    }
+    if let Some(did) = record.did {
+        let signature =
+            hex::decode(&did.signature).map_err(|_| anyhow!("invalid did signature"))?;
+        Did::publish(
+            db,
+            user,
+            did.document.into_bytes(),
+            did.key_id,
+            signature,
+            "admin",
+        )
+        .await
+        .map_err(|e| anyhow!("did document not saved: {}", e))?;
+    }
+    if record.is_banned {
+        user.ban(db, true, "admin")
+            .await
+            .map_err(|e| anyhow!("not banned: {}", e))?;
+    }
+    Ok(())
+}
+
+/// import users from the file, merge into current database. the users
+/// before a broken line are imported, the line & the rest are reported.
+pub(crate) async fn import(db: &Db, path: &Path, format: Format) -> Result<ImportReport> {
+    let mut lines = BufReader::new(File::open(path).await?).lines();
+    let mut report = ImportReport::default();
+
+    if format == Format::Csv {
+        lines.next_line().await?; // header.
+    }
+
+    let mut buffer = String::new();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                let index = report.imported + report.conflicts.len() + report.failures.len();
+                report.failures.push((
+                    format!("after record {}", index),
+                    format!("read failure, the rest not imported: {}", e),
+                ));
+                break;
+            }
+        };
+        if !buffer.is_empty() {
+            buffer.push('\n');
+        }
+        buffer.push_str(&line);
+
+        // CSV quoted field can cross lines.
+        if format == Format::Csv && buffer.matches('"').count() % 2 == 1 {
+            continue;
+        }
+        let line = std::mem::take(&mut buffer);
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record = match format {
+            Format::Json => serde_json::from_str(&line).map_err(|e| anyhow!("{}", e)),
+            Format::Csv => Record::from_csv(&line),
+        };
+        let (mut record, mut user) = match record.and_then(|mut r| r.take_user().map(|u| (r, u))) {
+            Ok(v) => v,
+            Err(e) => {
+                report.failures.push((line, e.to_string()));
+                continue;
+            }
+        };
+
+        if !user.is_deleted && User::get_by_name(db, &user.name).await.is_ok() {
+            report.conflicts.push(user.name);
+            continue;
+        }
+        match normalize(std::mem::take(&mut user.avatar), usize::MAX).await {
+            Ok(avatar) => user.avatar = avatar,
+            Err(e) => {
+                report.failures.push((user.name, e.to_string()));
+                continue;
+            }
+        }
+        let history = std::mem::take(&mut record.history)
+            .into_iter()
+            .map(|h| {
+                let mut audit = Audit::new(
+                    h.actor,
+                    &h.operation,
+                    0,
+                    user.name.clone(),
+                    h.old_value,
+                    h.new_value,
+                );
+                audit.datetime = h.datetime;
+                audit
+            })
+            .collect();
+        if let Err(e) = user.import(db, history).await {
+            if e.downcast_ref::<NameConflict>().is_some() {
+                report.conflicts.push(user.name);
+            } else {
+                report.failures.push((user.name, e.to_string()));
+            }
+            continue;
+        }
+        if let Err(e) = restore_state(db, &mut user, record).await {
+            report
+                .failures
+                .push((user.name, format!("imported, but {}", e)));
+            continue;
+        }
+        report.imported += 1;
+    }
+    if !buffer.trim().is_empty() {
+        report
+            .failures
+            .push((buffer, "unterminated csv record".to_owned()));
+    }
+
+    info!(
+        "Imported {} users from {:?}, {} conflicts, {} failures",
+        report.imported,
+        path,
+        report.conflicts.len(),
+        report.failures.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{self, Backend, DatabaseConfig};
+
+    /// quotes, commas & line breaks, all need quoting.
+    const BIO: &'static str = "hi, I'm \"alice\"\nsecond line";
+
+    async fn memory_db() -> Db {
+        storage::init(
+            &std::env::temp_dir(),
+            Backend::Memory,
+            DatabaseConfig::default(),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn csv_quoting() {
+        let record = Record {
+            name: "alice".to_owned(),
+            pid: PeerId([1; 32]).to_hex(),
+            bio: BIO.to_owned(),
+            avatar: String::new(),
+            is_actived: true,
+            is_deleted: false,
+            datetime: 1,
+            history: vec![History {
+                actor: "admin".to_owned(),
+                operation: "register".to_owned(),
+                old_value: String::new(),
+                new_value: "{\"bio\":\"a, b\"}".to_owned(),
+                datetime: 1,
+            }],
+            deleted_at: 0,
+            is_banned: true,
+            attributes: vec![Property {
+                key: "x-note".to_owned(),
+                value: "1,\"2\"".to_owned(),
+                is_public: true,
+            }],
+            did: None,
+        };
+
+        let parsed = Record::from_csv(&record.to_csv().unwrap()).unwrap();
+        assert_eq!(parsed.bio, BIO);
+        assert_eq!(parsed.history[0].new_value, record.history[0].new_value);
+        assert_eq!(parsed.attributes[0].value, record.attributes[0].value);
+        assert!(parsed.is_banned && parsed.did.is_none());
+    }
+
+    #[tokio::test]
+    async fn csv_export_import() {
+        let from = memory_db().await;
+        let mut user = User::new("alice".to_owned(), PeerId([1; 32]), BIO.to_owned(), vec![]);
+        user.insert(&from, "test").await.unwrap();
+
+        let path = std::env::temp_dir().join(format!("domain-users-{}.csv", rand::random::<u32>()));
+        assert_eq!(export(&from, &path, Format::Csv).await.unwrap().exported, 1);
+
+        let to = memory_db().await;
+        let report = import(&to, &path, Format::Csv).await.unwrap();
+        assert_eq!(report.imported, 1);
+        assert!(report.conflicts.is_empty() && report.failures.is_empty());
+        assert_eq!(User::get_by_name(&to, "alice").await.unwrap().bio, BIO);
+
+        let _ = fs::remove_file(&path).await;
+    }
+}
@@ -1,12 +1,198 @@
+use domain_types::DOMAIN_ID;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tdn::types::{
-    primitives::HandleResult,
-    rpc::{json, RpcHandler},
+    message::NetworkType,
+    primitives::{HandleResult, Peer, PeerId},
+    rpc::{json, rpc_response, RpcError, RpcHandler, RpcParam},
 };
 use tokio::sync::RwLock;
 
+use crate::avatar::THUMBNAIL_SIZES;
+use crate::backup::{backup, BACKUP_DIR};
+use crate::cache;
+use crate::config::{data_file, save_custom, toml_addrs, toml_str, RegisterPolicy, MAX_BOOTSTRAP};
+use crate::crypto;
+use crate::dispatch;
 use crate::layer::Layer;
-use crate::models::User;
+use crate::models::{Attribute, Audit, AuditFilter, Did, Report, User, Version};
+use crate::network;
+use crate::profile;
+use crate::protocol::{add_ext_layer, ExtServerEvent};
+use crate::registry::{export, import, Format, TRANSFER_DIR};
+use crate::storage::{purge, read_thumbnail, reconcile, spawn_reencrypt};
+
+/// Live event: domain stopping.
+#[inline]
+pub(crate) fn event_shutdown() -> RpcParam {
+    rpc_response(0, "event-shutdown", json!([]), DOMAIN_ID)
+}
+
+/// Live event: user registered.
+#[inline]
+pub(crate) fn event_register(name: &str, pid: &PeerId, is_ok: bool) -> RpcParam {
+    rpc_response(
+        0,
+        "event-register",
+        json!([name, pid.to_hex(), is_ok]),
+        DOMAIN_ID,
+    )
+}
+
+/// Live event: user updated the profile.
+#[inline]
+pub(crate) fn event_update(name: &str, pid: &PeerId) -> RpcParam {
+    rpc_response(0, "event-update", json!([name, pid.to_hex()]), DOMAIN_ID)
+}
+
+/// Live event: user suspended or actived.
+#[inline]
+pub(crate) fn event_active(name: &str, pid: &PeerId, active: bool) -> RpcParam {
+    rpc_response(
+        0,
+        "event-active",
+        json!([name, pid.to_hex(), active]),
+        DOMAIN_ID,
+    )
+}
+
+/// Live event: user deleted.
+#[inline]
+pub(crate) fn event_delete(name: &str, pid: &PeerId) -> RpcParam {
+    rpc_response(0, "event-delete", json!([name, pid.to_hex()]), DOMAIN_ID)
+}
+
+/// Live event: user reported by peer.
+#[inline]
+pub(crate) fn event_report(name: &str, reporter: &PeerId, id: i64) -> RpcParam {
+    rpc_response(
+        0,
+        "event-report",
+        json!([name, reporter.to_hex(), id]),
+        DOMAIN_ID,
+    )
+}
+
+/// Live event: user banned or unbanned by admin.
+#[inline]
+pub(crate) fn event_ban(name: &str, ban: bool) -> RpcParam {
+    rpc_response(0, "event-ban", json!([name, ban]), DOMAIN_ID)
+}
+
+/// Live event: message from other domain services.
+#[inline]
+pub(crate) fn event_federation(pid: &PeerId, action: &str) -> RpcParam {
+    rpc_response(
+        0,
+        "event-federation",
+        json!([pid.to_hex(), action]),
+        DOMAIN_ID,
+    )
+}
+
+/// Live event: handle error.
+#[inline]
+pub(crate) fn event_error(error: &str) -> RpcParam {
+    rpc_response(0, "event-error", json!([error]), DOMAIN_ID)
+}
+
+/// `subscribe` & `unsubscribe` need the websocket connection uid,
+/// so they are handled before the RpcHandler. `subscribe` needs the
+/// admin token, and is renewed in `SUBSCRIBE_TTL`.
+pub(crate) async fn handle_subscription(
+    layer: &Arc<RwLock<Layer>>,
+    uid: u64,
+    is_ws: bool,
+    params: &RpcParam,
+) -> Option<HandleResult> {
+    let method = params["method"].as_str()?;
+    let is_ok = match method {
+        "subscribe" => {
+            let token = params["params"][0].as_str().unwrap_or("");
+            let layer = layer.read().await;
+            is_ws && token_eq(token, &layer.admin_token) && layer.subscribe(uid)
+        }
+        "unsubscribe" => layer.read().await.unsubscribe(uid),
+        _ => return None,
+    };
+    let id = params["id"].as_u64().unwrap_or(0);
+
+    let mut results = HandleResult::new();
+    results
+        .rpcs
+        .push(rpc_response(id, method, json!(is_ok), DOMAIN_ID));
+    Some(results)
+}
+
+/// methods changing the domain or writing files, the request needs the
+/// admin token in `token` (beside `method` & `params`).
+const ADMIN_METHODS: [&'static str; 13] = [
+    "set-config",
+    "add-bootstrap",
+    "remove-bootstrap",
+    "rotate-key",
+    "reconcile-avatars",
+    "purge-users",
+    "backup",
+    "export-users",
+    "import-users",
+    "triage-report",
+    "resolve-report",
+    "ban-user",
+    "export-audits",
+];
+
+/// reject the admin method without the admin token, handled before the
+/// RpcHandler, none if the request is allowed.
+pub(crate) async fn check_admin(
+    layer: &Arc<RwLock<Layer>>,
+    params: &RpcParam,
+) -> Option<HandleResult> {
+    let method = params["method"].as_str()?;
+    if !ADMIN_METHODS.contains(&method) {
+        return None;
+    }
+    let token = params["token"].as_str().unwrap_or("");
+    if token_eq(token, &layer.read().await.admin_token) {
+        return None;
+    }
+    warn!("RPC {} refused, admin token required", method);
+    let id = params["id"].as_u64().unwrap_or(0);
+
+    let mut results = HandleResult::new();
+    results
+        .rpcs
+        .push(RpcError::Custom("admin token required".to_owned()).json(id));
+    Some(results)
+}
+
+/// compare the token in constant time.
+fn token_eq(token: &str, admin_token: &str) -> bool {
+    let (a, b) = (token.as_bytes(), admin_token.as_bytes());
+    !b.is_empty() && a.len() == b.len() && a.iter().zip(b).fold(0, |r, (x, y)| r | (x ^ y)) == 0
+}
+
+/// audit filter from params: [actor, operation, user_id, start, end, limit],
+/// empty string or 0 means no filter.
+fn audit_filter(params: &[RpcParam]) -> AuditFilter {
+    let text = |i: usize| {
+        params
+            .get(i)
+            .and_then(|p| p.as_str())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_owned())
+    };
+    let number = |i: usize| params.get(i).and_then(|p| p.as_i64()).unwrap_or(0);
+
+    AuditFilter {
+        actor: text(0),
+        operation: text(1),
+        user_id: Some(number(2)).filter(|id| *id > 0),
+        start: number(3),
+        end: number(4),
+        limit: number(5),
+    }
+}
 
 pub(crate) struct RpcState {
     pub layer: Arc<RwLock<Layer>>,
@@ -22,11 +208,232 @@ pub(crate) fn new_rpc_handler(layer: Arc<RwLock<Layer>>) -> RpcHandler<RpcState>
             "name": layer.name,
             "peer_id": layer.pid.to_hex(),
             "proxy": layer.proxy,
+            "status": network::status(),
+        })))
+    });
+
+    handler.add_method("get-config", |_, state: Arc<RpcState>| async move {
+        let layer = state.layer.read().await;
+
+        Ok(HandleResult::rpc(json!({
+            "name": layer.name,
+            "proxy": layer.proxy,
+            "rate_limit": layer.rate_limit,
+            "registration": layer.registration.to_str(),
+            "avatar_max_size": layer.avatar_max_size,
+            "encryption": crypto::is_enabled(),
+            "key_version": crypto::version(),
+            "purge_retention": layer.purge_retention,
+            "bootstrap": layer.bootstrap.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+            "saved_bootstrap": layer
+                .saved_bootstrap
+                .iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<_>>(),
+        })))
+    });
+
+    handler.add_method(
+        "set-config",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let key = params
+                .get(0)
+                .and_then(|p| p.as_str())
+                .ok_or(RpcError::ParseError)?;
+            let value = params.get(1).ok_or(RpcError::ParseError)?;
+
+            // saved first, the layer not changed if saving failed.
+            let (item, apply): (String, Box<dyn FnOnce(&mut Layer) + Send>) = match key {
+                "name" => {
+                    let name = value.as_str().ok_or(RpcError::ParseError)?.trim();
+                    if name.is_empty() {
+                        return Err(RpcError::ParseError);
+                    }
+                    let name = name.to_owned();
+                    (
+                        toml_str(&name),
+                        Box::new(move |l: &mut Layer| l.name = name),
+                    )
+                }
+                "proxy" => {
+                    let proxy = value.as_bool().ok_or(RpcError::ParseError)?;
+                    (
+                        proxy.to_string(),
+                        Box::new(move |l: &mut Layer| l.proxy = proxy),
+                    )
+                }
+                "rate_limit" => {
+                    let limit = value.as_u64().ok_or(RpcError::ParseError)? as u32;
+                    (
+                        limit.to_string(),
+                        Box::new(move |l: &mut Layer| l.rate_limit = limit),
+                    )
+                }
+                "registration" => {
+                    let policy = value.as_str().ok_or(RpcError::ParseError)?;
+                    let policy = RegisterPolicy::from_str(policy).ok_or(RpcError::ParseError)?;
+                    (
+                        toml_str(policy.to_str()),
+                        Box::new(move |l: &mut Layer| l.registration = policy),
+                    )
+                }
+                "avatar_max_size" => {
+                    let size = value.as_u64().ok_or(RpcError::ParseError)? as usize;
+                    (
+                        size.to_string(),
+                        Box::new(move |l: &mut Layer| l.avatar_max_size = size),
+                    )
+                }
+                _ => return Err(RpcError::ParseError),
+            };
+
+            let mut layer = state.layer.write().await;
+            save_custom(&layer.db.base, &[(key, item)]).await?;
+            apply(&mut *layer);
+
+            Ok(HandleResult::rpc(json!([key, value])))
+        },
+    );
+
+    handler.add_method("list-bootstrap", |_, state: Arc<RpcState>| async move {
+        let layer = state.layer.read().await;
+        let addrs: Vec<String> = layer.bootstrap.iter().map(|a| a.to_string()).collect();
+        Ok(HandleResult::rpc(json!(addrs)))
+    });
+
+    handler.add_method(
+        "add-bootstrap",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let addr: SocketAddr = params
+                .get(0)
+                .and_then(|p| p.as_str())
+                .ok_or(RpcError::ParseError)?
+                .parse()
+                .map_err(|_| RpcError::ParseError)?;
+
+            let mut layer = state.layer.write().await;
+            if !layer.saved_bootstrap.contains(&addr) {
+                if layer.saved_bootstrap.len() >= MAX_BOOTSTRAP {
+                    return Err(RpcError::Custom(format!(
+                        "max {} bootstrap peers",
+                        MAX_BOOTSTRAP
+                    )));
+                }
+                // saved first, the layer not changed if saving failed.
+                let mut saved = layer.saved_bootstrap.clone();
+                saved.push(addr);
+                let item = toml_addrs(&saved);
+                save_custom(&layer.db.base, &[("network_bootstrap", item)]).await?;
+                layer.saved_bootstrap = saved;
+            }
+            if !layer.bootstrap.contains(&addr) {
+                layer.bootstrap.push(addr);
+            }
+
+            // dial it now, not wait for restart.
+            let mut results = HandleResult::rpc(json!([addr.to_string(), true]));
+            results
+                .networks
+                .push(NetworkType::Connect(Peer::socket(addr)));
+            Ok(results)
+        },
+    );
+
+    handler.add_method(
+        "remove-bootstrap",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let addr: SocketAddr = params
+                .get(0)
+                .and_then(|p| p.as_str())
+                .ok_or(RpcError::ParseError)?
+                .parse()
+                .map_err(|_| RpcError::ParseError)?;
+
+            let mut layer = state.layer.write().await;
+            let is_removed =
+                layer.saved_bootstrap.contains(&addr) || layer.bootstrap.contains(&addr);
+            if layer.saved_bootstrap.contains(&addr) {
+                // saved first, the layer not changed if saving failed.
+                let mut saved = layer.saved_bootstrap.clone();
+                saved.retain(|a| *a != addr);
+                let item = toml_addrs(&saved);
+                save_custom(&layer.db.base, &[("network_bootstrap", item)]).await?;
+                layer.saved_bootstrap = saved;
+            }
+            layer.bootstrap.retain(|a| *a != addr);
+
+            // drop the connection now, not wait for restart.
+            let mut results = HandleResult::rpc(json!([addr.to_string(), is_removed]));
+            if is_removed {
+                results
+                    .networks
+                    .push(NetworkType::Disconnect(Peer::socket(addr)));
+            }
+            Ok(results)
+        },
+    );
+
+    handler.add_method("rotate-key", |_, state: Arc<RpcState>| async move {
+        let db = state.layer.read().await.db.clone();
+        let version = crypto::rotate().await?;
+        save_custom(&db.base, &[("key_version", version.to_string())]).await?;
+        spawn_reencrypt(db);
+        Ok(HandleResult::rpc(json!(version)))
+    });
+
+    handler.add_method("reconcile-avatars", |_, state: Arc<RpcState>| async move {
+        let db = state.layer.read().await.db.clone();
+        let report = reconcile(&db).await?;
+        Ok(HandleResult::rpc(report.to_rpc()))
+    });
+
+    handler.add_method(
+        "purge-users",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let (db, retention) = {
+                let layer = state.layer.read().await;
+                (layer.db.clone(), layer.purge_retention)
+            };
+            // retention days, default is the config, 0 purges all deleted users.
+            let retention = params.get(0).and_then(|v| v.as_u64()).unwrap_or(retention);
+            let report = purge(&db, retention, "admin").await?;
+            Ok(HandleResult::rpc(report.to_rpc()))
+        },
+    );
+
+    handler.add_method("cache-stats", |_, _| async move {
+        Ok(HandleResult::rpc(cache::stats()))
+    });
+
+    handler.add_method("stats", |_, _| async move {
+        Ok(HandleResult::rpc(json!({
+            "layer": dispatch::stats(),
+            "network": network::stats(),
+            "cache": cache::stats(),
         })))
     });
 
+    handler.add_method(
+        "backup",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let name = params
+                .get(0)
+                .and_then(|p| p.as_str())
+                .ok_or(RpcError::ParseError)?;
+            let compress = params.get(1).and_then(|v| v.as_bool()).unwrap_or(false);
+            // never in the params, only from the env of the domain.
+            let password = std::env::var("DOMAIN_BACKUP_PASSWORD").unwrap_or_default();
+
+            // only the files in the backup folder of db_path.
+            let db = state.layer.read().await.db.clone();
+            let path = data_file(&db.base, BACKUP_DIR, name).await?;
+            let summary = backup(&db, &path, compress, &password).await?;
+            Ok(HandleResult::rpc(summary.to_rpc()))
+        },
+    );
+
     handler.add_method("list-users", |_, state: Arc<RpcState>| async move {
-        let users = User::list(&state.layer.read().await.base).await?;
+        let users = User::list(&state.layer.read().await.db).await?;
         let mut vecs = vec![];
         for user in users {
             vecs.push(user.to_rpc());
@@ -34,5 +441,288 @@ pub(crate) fn new_rpc_handler(layer: Arc<RwLock<Layer>>) -> RpcHandler<RpcState>
         Ok(HandleResult::rpc(json!(vecs)))
     });
 
+    handler.add_method(
+        "get-avatar",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let name = params
+                .get(0)
+                .and_then(|p| p.as_str())
+                .ok_or(RpcError::ParseError)?;
+            let size = params.get(1).and_then(|p| p.as_u64()).unwrap_or(0) as u32;
+
+            let db = state.layer.read().await.db.clone();
+            let user = User::get_by_name(&db, name).await?;
+            let bytes = if THUMBNAIL_SIZES.contains(&size) {
+                read_thumbnail(&db, &user.avatar_hash, size).await?
+            } else {
+                user.avatar
+            };
+            Ok(HandleResult::rpc(json!([
+                name,
+                user.avatar_hash,
+                hex::encode(bytes)
+            ])))
+        },
+    );
+
+    handler.add_method(
+        "export-users",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let name = params
+                .get(0)
+                .and_then(|p| p.as_str())
+                .ok_or(RpcError::ParseError)?;
+            let format = params.get(1).and_then(|p| p.as_str()).unwrap_or("");
+
+            // only the files in the transfer folder of db_path.
+            let db = state.layer.read().await.db.clone();
+            let path = data_file(&db.base, TRANSFER_DIR, name).await?;
+            let format = Format::parse(format, &path)?;
+            let report = export(&db, &path, format).await?;
+            Ok(HandleResult::rpc(report.to_rpc()))
+        },
+    );
+
+    handler.add_method(
+        "import-users",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let name = params
+                .get(0)
+                .and_then(|p| p.as_str())
+                .ok_or(RpcError::ParseError)?;
+            let format = params.get(1).and_then(|p| p.as_str()).unwrap_or("");
+
+            // only the files in the transfer folder of db_path.
+            let db = state.layer.read().await.db.clone();
+            let path = data_file(&db.base, TRANSFER_DIR, name).await?;
+            let format = Format::parse(format, &path)?;
+            let report = import(&db, &path, format).await?;
+            Ok(HandleResult::rpc(report.to_rpc()))
+        },
+    );
+
+    handler.add_method(
+        "list-reports",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let status = params.get(0).and_then(|p| p.as_str()).unwrap_or("");
+            let db = state.layer.read().await.db.clone();
+            let reports = Report::list(&db, status).await?;
+            let mut vecs = vec![];
+            for report in reports {
+                vecs.push(report.to_rpc());
+            }
+            Ok(HandleResult::rpc(json!(vecs)))
+        },
+    );
+
+    handler.add_method(
+        "list-versions",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let user_id = params
+                .get(0)
+                .and_then(|p| p.as_i64())
+                .ok_or(RpcError::ParseError)?;
+            let db = state.layer.read().await.db.clone();
+            let versions = Version::list(&db, &user_id).await?;
+            let mut vecs = vec![];
+            for version in versions {
+                vecs.push(version.to_rpc());
+            }
+            Ok(HandleResult::rpc(json!(vecs)))
+        },
+    );
+
+    handler.add_method(
+        "list-attributes",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let user_id = params
+                .get(0)
+                .and_then(|p| p.as_i64())
+                .ok_or(RpcError::ParseError)?;
+            let db = state.layer.read().await.db.clone();
+            let attributes = Attribute::list(&db, &user_id).await?;
+            let mut vecs = vec![];
+            for attribute in attributes {
+                vecs.push(attribute.to_rpc());
+            }
+            Ok(HandleResult::rpc(json!(vecs)))
+        },
+    );
+
+    handler.add_method(
+        "resolve-did",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let name = params
+                .get(0)
+                .and_then(|p| p.as_str())
+                .ok_or(RpcError::ParseError)?;
+            let db = state.layer.read().await.db.clone();
+            let user = User::get_by_name(&db, name).await?;
+            let did = Did::get(&db, &user.id).await?;
+            Ok(HandleResult::rpc(
+                did.map(|d| d.to_rpc(&user)).unwrap_or_default(),
+            ))
+        },
+    );
+
+    handler.add_method("profile-schema", |_, _| async move {
+        Ok(HandleResult::rpc(profile::schema()))
+    });
+
+    handler.add_method(
+        "triage-report",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let id = params
+                .get(0)
+                .and_then(|p| p.as_i64())
+                .ok_or(RpcError::ParseError)?;
+            let db = state.layer.read().await.db.clone();
+            Report::update(&db, &id, "reviewing", "").await?;
+            Ok(HandleResult::rpc(json!(id)))
+        },
+    );
+
+    handler.add_method(
+        "resolve-report",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let id = params
+                .get(0)
+                .and_then(|p| p.as_i64())
+                .ok_or(RpcError::ParseError)?;
+            let action = params
+                .get(1)
+                .and_then(|p| p.as_str())
+                .ok_or(RpcError::ParseError)?;
+
+            let db = state.layer.read().await.db.clone();
+            let report = Report::get(&db, &id).await?;
+            let mut user = User::get(&db, &report.user_id).await?;
+
+            let mut results = HandleResult::new();
+            let status = match action {
+                "hide-bio" => {
+                    user.update(String::new(), vec![], &db, "admin", action)
+                        .await?;
+                    "resolved"
+                }
+                "clear-avatar" => {
+                    user.clear_avatar(&db, "admin").await?;
+                    "resolved"
+                }
+                "ban" => {
+                    user.ban(&db, true, "admin").await?;
+                    let event = event_ban(&user.name, true);
+                    state.layer.read().await.event(event);
+                    "resolved"
+                }
+                "dismiss" => "dismissed",
+                _ => return Err(RpcError::ParseError),
+            };
+            Report::update(&db, &id, status, action).await?;
+
+            // notify the reporter.
+            let event = ExtServerEvent::Reported(report.name, action.to_owned());
+            add_ext_layer(&mut results, report.reporter, event, DOMAIN_ID)?;
+
+            results.rpcs.push(json!([id, status, action]));
+            Ok(results)
+        },
+    );
+
+    handler.add_method(
+        "ban-user",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let name = params
+                .get(0)
+                .and_then(|p| p.as_str())
+                .ok_or(RpcError::ParseError)?;
+            let ban = params
+                .get(1)
+                .and_then(|p| p.as_bool())
+                .ok_or(RpcError::ParseError)?;
+
+            let db = state.layer.read().await.db.clone();
+            let mut user = User::get_by_name(&db, name).await?;
+            user.ban(&db, ban, "admin").await?;
+
+            state.layer.read().await.event(event_ban(name, ban));
+            Ok(HandleResult::rpc(json!([name, ban])))
+        },
+    );
+
+    handler.add_method(
+        "list-audits",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let db = state.layer.read().await.db.clone();
+            let audits = Audit::list(&db, &audit_filter(&params)).await?;
+            let mut vecs = vec![];
+            for audit in audits {
+                vecs.push(audit.to_rpc());
+            }
+            Ok(HandleResult::rpc(json!(vecs)))
+        },
+    );
+
+    handler.add_method(
+        "export-audits",
+        |params: Vec<RpcParam>, state: Arc<RpcState>| async move {
+            let format = params.get(0).and_then(|p| p.as_str()).unwrap_or("csv");
+            let db = state.layer.read().await.db.clone();
+            let filter = audit_filter(params.get(1..).unwrap_or(&[]));
+            let audits = Audit::list(&db, &filter).await?;
+            let data = if format == "json" {
+                let mut vecs = vec![];
+                for audit in audits {
+                    vecs.push(audit.to_rpc());
+                }
+                json!(vecs).to_string()
+            } else {
+                let mut lines = vec![Audit::csv_header().to_owned()];
+                for audit in audits {
+                    lines.push(audit.to_csv());
+                }
+                lines.join("\n")
+            };
+            Ok(HandleResult::rpc(json!(data)))
+        },
+    );
+
     handler
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CustomConfig;
+    use crate::storage::{self, Backend, DatabaseConfig};
+    use tdn::types::primitives::PeerId;
+
+    #[tokio::test]
+    async fn admin_token_required() {
+        let base = std::env::temp_dir().join("domain-rpc-test");
+        let db = storage::init(&base, Backend::Memory, DatabaseConfig::default())
+            .await
+            .unwrap();
+        let config: CustomConfig =
+            toml::from_str("name = \"test\"\nproxy = false\nmnemonic = \"\"").unwrap();
+        let mut layer = Layer::new(db, &config, &config.network, PeerId([0; 32]))
+            .await
+            .unwrap();
+        layer.admin_token = "secret".to_owned();
+        let layer = Arc::new(RwLock::new(layer));
+
+        let request = |method: &str, token: &str| json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": [], "token": token});
+        assert!(check_admin(&layer, &request("ban-user", ""))
+            .await
+            .is_some());
+        assert!(check_admin(&layer, &request("ban-user", "wrong!"))
+            .await
+            .is_some());
+        assert!(check_admin(&layer, &request("ban-user", "secret"))
+            .await
+            .is_none());
+        assert!(check_admin(&layer, &request("list-users", ""))
+            .await
+            .is_none());
+    }
+}
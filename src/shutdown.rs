@@ -0,0 +1,102 @@
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc::Sender, Notify};
+
+/// max seconds of waiting the in-flight works when stopping.
+pub(crate) const DRAIN_TIMEOUT: u64 = 30;
+
+/// max seconds of waiting the last messages sent to TDN.
+pub(crate) const FLUSH_TIMEOUT: u64 = 5;
+
+/// high bit set when the domain is stopping, no new work started.
+const STOPPING: usize = 1 << (usize::BITS - 1);
+
+/// stopping bit & in-flight works, in one atomic, so a work never
+/// starts after the stopping seen by `drain`.
+static STATE: AtomicUsize = AtomicUsize::new(0);
+static DRAINED: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// in-flight work, finished when dropped.
+pub(crate) struct Work;
+
+impl Drop for Work {
+    fn drop(&mut self) {
+        if STATE.fetch_sub(1, Ordering::SeqCst) & !STOPPING == 1 {
+            DRAINED.notify_waiters();
+        }
+    }
+}
+
+/// start a work, none if the domain is stopping.
+pub(crate) fn begin() -> Option<Work> {
+    let mut state = STATE.load(Ordering::SeqCst);
+    loop {
+        if state & STOPPING != 0 {
+            return None;
+        }
+        match STATE.compare_exchange_weak(state, state + 1, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => return Some(Work),
+            Err(now) => state = now,
+        }
+    }
+}
+
+#[inline]
+pub(crate) fn is_stopping() -> bool {
+    STATE.load(Ordering::SeqCst) & STOPPING != 0
+}
+
+/// mark the domain stopping.
+pub(crate) fn stop() {
+    STATE.fetch_or(STOPPING, Ordering::SeqCst);
+}
+
+/// wait SIGINT or SIGTERM.
+#[cfg(unix)]
+pub(crate) async fn signal() -> &'static str {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut term = match signal(SignalKind::terminate()) {
+        Ok(term) => term,
+        Err(e) => {
+            warn!("SIGTERM handler failure: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return "SIGINT";
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+        _ = term.recv() => "SIGTERM",
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) async fn signal() -> &'static str {
+    let _ = tokio::signal::ctrl_c().await;
+    "SIGINT"
+}
+
+/// wait all in-flight works finished, return false if timeout.
+pub(crate) async fn drain(timeout: Duration) -> bool {
+    let wait = async {
+        loop {
+            let drained = DRAINED.notified();
+            if STATE.load(Ordering::SeqCst) & !STOPPING == 0 {
+                return;
+            }
+            drained.await;
+        }
+    };
+    tokio::time::timeout(timeout, wait).await.is_ok()
+}
+
+/// wait the messages in the channel taken by TDN, return false if timeout.
+pub(crate) async fn flush<T>(sender: &Sender<T>, timeout: Duration) -> bool {
+    let wait = async {
+        while sender.capacity() < sender.max_capacity() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    };
+    tokio::time::timeout(timeout, wait).await.is_ok()
+}
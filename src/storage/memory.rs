@@ -0,0 +1,590 @@
+use async_trait::async_trait;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tdn::types::primitives::Result;
+
+use super::{
+    deleted_at, name_key, purge_audit, AvatarStore, Migration, NameConflict, Snapshot, Store,
+};
+use crate::models::{now, Attribute, Audit, AuditFilter, Did, Report, User, Version};
+
+/// names are unique by the name key, same as the database index.
+#[inline]
+fn same_name(a: &str, b: &str) -> bool {
+    name_key(a) == name_key(b)
+}
+
+#[derive(Default)]
+struct Tables {
+    users: Vec<User>,
+    banned: HashSet<i64>,
+    audits: Vec<Audit>,
+    reports: Vec<Report>,
+    versions: Vec<Version>,
+    attributes: Vec<Attribute>,
+    dids: HashMap<i64, Did>,
+    /// auto-increment ids of tables.
+    user_id: i64,
+    audit_id: i64,
+    report_id: i64,
+    version_id: i64,
+}
+
+impl Tables {
+    /// append the audit of the user, in the same lock of the change.
+    fn audit(&mut self, audit: &Audit, user_id: i64) {
+        self.audit_id += 1;
+        let mut audit = audit.clone();
+        audit.id = self.audit_id;
+        audit.user_id = user_id;
+        self.audits.push(audit);
+    }
+
+    /// save the profile of the user as a new version, in the same lock.
+    fn version(&mut self, user_id: i64, bio: &str, avatar: &str) {
+        self.version_id += 1;
+        self.versions.push(Version {
+            id: self.version_id,
+            user_id,
+            bio: bio.to_owned(),
+            avatar_hash: avatar.to_owned(),
+            datetime: now(),
+        });
+    }
+
+    /// delete the versions of user except the newest `keep`,
+    /// return their avatar hashes.
+    fn prune(&mut self, user_id: i64, keep: i64) -> Vec<String> {
+        let ids: Vec<i64> = self
+            .versions
+            .iter()
+            .rev()
+            .filter(|v| v.user_id == user_id)
+            .skip(keep as usize)
+            .map(|v| v.id)
+            .collect();
+        let mut avatars = vec![];
+        self.versions.retain(|v| {
+            if ids.contains(&v.id) {
+                avatars.push(v.avatar_hash.clone());
+                false
+            } else {
+                true
+            }
+        });
+        avatars
+    }
+}
+
+/// In-memory storage, same behaviour as the database, all lost when exit.
+#[derive(Default)]
+pub(crate) struct MemoryStore {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn user_list(&self) -> Result<Vec<User>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .users
+            .iter()
+            .filter(|u| !u.is_deleted)
+            .cloned()
+            .collect())
+    }
+
+    async fn user_list_page(&self, after: i64, limit: i64) -> Result<Vec<User>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .users
+            .iter()
+            .filter(|u| u.id > after)
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn user_search(&self, name: &str) -> Result<User> {
+        let tables = self.tables.lock().unwrap();
+        tables
+            .users
+            .iter()
+            .find(|u| u.is_actived && same_name(&u.name, name))
+            .cloned()
+            .ok_or(anyhow!("database failure."))
+    }
+
+    async fn user_get_by_name(&self, name: &str) -> Result<User> {
+        let tables = self.tables.lock().unwrap();
+        tables
+            .users
+            .iter()
+            .find(|u| !u.is_deleted && same_name(&u.name, name))
+            .cloned()
+            .ok_or(anyhow!("database failure."))
+    }
+
+    async fn user_get(&self, id: &i64) -> Result<User> {
+        let tables = self.tables.lock().unwrap();
+        tables
+            .users
+            .iter()
+            .find(|u| !u.is_deleted && u.id == *id)
+            .cloned()
+            .ok_or(anyhow!("database failure."))
+    }
+
+    async fn user_insert(&self, user: &User, audit: &Audit) -> Result<i64> {
+        let mut tables = self.tables.lock().unwrap();
+        // as the sql stores, deleted names are reserved until purged.
+        if tables.users.iter().any(|u| same_name(&u.name, &user.name)) {
+            return Err(NameConflict(user.name.clone()).into());
+        }
+
+        tables.user_id += 1;
+        let mut user = user.clone();
+        user.id = tables.user_id;
+        user.avatar = vec![];
+        user.is_deleted = false;
+        let id = user.id;
+        tables.version(id, &user.bio, &user.avatar_hash);
+        tables.users.push(user);
+        tables.audit(audit, id);
+        Ok(id)
+    }
+
+    async fn user_import(&self, user: &User, audits: &[Audit]) -> Result<i64> {
+        let mut tables = self.tables.lock().unwrap();
+        if !user.is_deleted && tables.users.iter().any(|u| same_name(&u.name, &user.name)) {
+            return Err(NameConflict(user.name.clone()).into());
+        }
+
+        tables.user_id += 1;
+        let mut user = user.clone();
+        user.id = tables.user_id;
+        user.avatar = vec![];
+        user.deleted_at = deleted_at(&user);
+        if !user.is_deleted {
+            tables.version(user.id, &user.bio, &user.avatar_hash);
+        }
+        let id = user.id;
+        tables.users.push(user);
+        for audit in audits {
+            tables.audit(audit, id);
+        }
+        Ok(id)
+    }
+
+    async fn user_profile(
+        &self,
+        id: &i64,
+        bio: &str,
+        avatar: &str,
+        keep: i64,
+        audit: &Audit,
+    ) -> Result<Vec<String>> {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(user) = tables.users.iter_mut().find(|u| u.id == *id) {
+            user.bio = bio.to_owned();
+            user.avatar_hash = avatar.to_owned();
+        }
+        tables.version(*id, bio, avatar);
+        tables.audit(audit, *id);
+        Ok(tables.prune(*id, keep))
+    }
+
+    async fn user_update(&self, id: &i64, bio: &str) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(user) = tables.users.iter_mut().find(|u| u.id == *id) {
+            user.bio = bio.to_owned();
+        }
+        Ok(())
+    }
+
+    async fn user_avatar(&self, id: &i64, avatar: &str) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(user) = tables.users.iter_mut().find(|u| u.id == *id) {
+            user.avatar_hash = avatar.to_owned();
+        }
+        Ok(())
+    }
+
+    async fn avatar_refs(&self, avatar: &str) -> Result<i64> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .users
+            .iter()
+            .filter(|u| !u.is_deleted && u.avatar_hash == avatar)
+            .count() as i64
+            + tables
+                .versions
+                .iter()
+                .filter(|v| v.avatar_hash == avatar)
+                .count() as i64)
+    }
+
+    async fn user_active(&self, id: &i64, active: bool, audit: &Audit) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if tables.banned.contains(id) {
+            return Err(anyhow!("user is banned."));
+        }
+        let user = tables
+            .users
+            .iter_mut()
+            .find(|u| u.id == *id)
+            .ok_or(anyhow!("user is banned."))?;
+        user.is_actived = active;
+        tables.audit(audit, *id);
+        Ok(())
+    }
+
+    async fn user_ban(&self, id: &i64, ban: bool, audit: &Audit) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(user) = tables.users.iter_mut().find(|u| u.id == *id) {
+            user.is_actived = !ban;
+        }
+        if ban {
+            tables.banned.insert(*id);
+        } else {
+            tables.banned.remove(id);
+        }
+        tables.audit(audit, *id);
+        Ok(())
+    }
+
+    async fn user_banned(&self, id: &i64) -> Result<bool> {
+        Ok(self.tables.lock().unwrap().banned.contains(id))
+    }
+
+    async fn user_delete(&self, id: &i64, audit: &Audit) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(user) = tables.users.iter_mut().find(|u| u.id == *id) {
+            user.is_actived = false;
+            user.is_deleted = true;
+            user.deleted_at = now();
+        }
+        tables.audit(audit, *id);
+        Ok(())
+    }
+
+    async fn user_purge(&self, before: i64, actor: &str) -> Result<(Vec<User>, Vec<String>)> {
+        let mut tables = self.tables.lock().unwrap();
+        let ids: HashSet<i64> = tables
+            .users
+            .iter()
+            .filter(|u| u.is_deleted && u.deleted_at < before)
+            .map(|u| u.id)
+            .collect();
+
+        let mut users = vec![];
+        tables.users.retain(|u| {
+            if ids.contains(&u.id) {
+                users.push(u.clone());
+                false
+            } else {
+                true
+            }
+        });
+        let mut avatars = vec![];
+        tables.versions.retain(|v| {
+            if ids.contains(&v.user_id) {
+                avatars.push(v.avatar_hash.clone());
+                false
+            } else {
+                true
+            }
+        });
+        tables.attributes.retain(|a| !ids.contains(&a.user_id));
+        tables.dids.retain(|id, _| !ids.contains(id));
+        for id in ids {
+            tables.banned.remove(&id);
+        }
+        for user in users.iter() {
+            tables.audit(&purge_audit(user, actor), user.id);
+        }
+        Ok((users, avatars))
+    }
+
+    async fn user_unkeyed(&self) -> Result<Vec<(i64, String, bool)>> {
+        // names are always compared by the key.
+        Ok(vec![])
+    }
+
+    async fn user_set_keys(&self, _keys: &[(i64, String)]) -> Result<()> {
+        Ok(())
+    }
+
+    async fn audit_list(&self, filter: &AuditFilter) -> Result<Vec<Audit>> {
+        let end = if filter.end > 0 { filter.end } else { i64::MAX };
+        let limit = if filter.limit > 0 {
+            filter.limit
+        } else {
+            i64::MAX
+        };
+
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .audits
+            .iter()
+            .rev()
+            .filter(|a| filter.actor.as_ref().map(|v| &a.actor == v).unwrap_or(true))
+            .filter(|a| {
+                filter
+                    .operation
+                    .as_ref()
+                    .map(|v| &a.operation == v)
+                    .unwrap_or(true)
+            })
+            .filter(|a| filter.user_id.map(|v| a.user_id == v).unwrap_or(true))
+            .filter(|a| a.datetime >= filter.start && a.datetime <= end)
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn report_list(&self, status: &str) -> Result<Vec<Report>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .reports
+            .iter()
+            .filter(|r| status.is_empty() || r.status == status)
+            .cloned()
+            .collect())
+    }
+
+    async fn report_get(&self, id: &i64) -> Result<Report> {
+        let tables = self.tables.lock().unwrap();
+        tables
+            .reports
+            .iter()
+            .find(|r| r.id == *id)
+            .cloned()
+            .ok_or(anyhow!("database failure."))
+    }
+
+    async fn report_insert(&self, report: &Report) -> Result<i64> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.report_id += 1;
+        let mut report = report.clone();
+        report.id = tables.report_id;
+        tables.reports.push(report);
+        Ok(tables.report_id)
+    }
+
+    async fn report_update(
+        &self,
+        id: &i64,
+        status: &str,
+        action: &str,
+        resolved_at: i64,
+    ) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(report) = tables.reports.iter_mut().find(|r| r.id == *id) {
+            report.status = status.to_owned();
+            report.action = action.to_owned();
+            report.resolved_at = resolved_at;
+        }
+        Ok(())
+    }
+
+    async fn version_list(&self, user_id: &i64) -> Result<Vec<Version>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .versions
+            .iter()
+            .rev()
+            .filter(|v| v.user_id == *user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn version_get(&self, id: &i64) -> Result<Version> {
+        let tables = self.tables.lock().unwrap();
+        tables
+            .versions
+            .iter()
+            .find(|v| v.id == *id)
+            .cloned()
+            .ok_or(anyhow!("database failure."))
+    }
+
+    async fn version_bio(&self, id: &i64, bio: &str) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(version) = tables.versions.iter_mut().find(|v| v.id == *id) {
+            version.bio = bio.to_owned();
+        }
+        Ok(())
+    }
+
+    async fn version_avatars(&self) -> Result<Vec<String>> {
+        let tables = self.tables.lock().unwrap();
+        let avatars: HashSet<String> = tables
+            .versions
+            .iter()
+            .filter(|v| !v.avatar_hash.is_empty())
+            .map(|v| v.avatar_hash.clone())
+            .collect();
+        Ok(avatars.into_iter().collect())
+    }
+
+    async fn attribute_list(&self, user_id: &i64) -> Result<Vec<Attribute>> {
+        let tables = self.tables.lock().unwrap();
+        let mut attributes: Vec<Attribute> = tables
+            .attributes
+            .iter()
+            .filter(|a| a.user_id == *user_id)
+            .cloned()
+            .collect();
+        attributes.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(attributes)
+    }
+
+    async fn attribute_set(
+        &self,
+        user_id: &i64,
+        attributes: &[Attribute],
+        audit: Option<&Audit>,
+    ) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.attributes.retain(|a| a.user_id != *user_id);
+        for attribute in attributes {
+            let mut attribute = attribute.clone();
+            attribute.user_id = *user_id;
+            tables.attributes.push(attribute);
+        }
+        if let Some(audit) = audit {
+            tables.audit(audit, *user_id);
+        }
+        Ok(())
+    }
+
+    async fn did_get(&self, user_id: &i64) -> Result<Option<Did>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.dids.get(user_id).cloned())
+    }
+
+    async fn did_set(&self, did: &Did, audit: &Audit) -> Result<bool> {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(saved) = tables.dids.get(&did.user_id) {
+            if saved.sequence >= did.sequence {
+                return Ok(false);
+            }
+        }
+        tables.dids.insert(did.user_id, did.clone());
+        tables.audit(audit, did.user_id);
+        Ok(true)
+    }
+
+    async fn snapshot(&self) -> Result<Snapshot> {
+        let tables = self.tables.lock().unwrap();
+        Ok(Snapshot {
+            users: tables
+                .users
+                .iter()
+                .map(|u| (u.clone(), tables.banned.contains(&u.id)))
+                .collect(),
+            audits: tables.audits.clone(),
+            reports: tables.reports.clone(),
+            versions: tables.versions.clone(),
+            attributes: tables.attributes.clone(),
+            dids: tables.dids.values().cloned().collect(),
+        })
+    }
+
+    async fn restore(&self, snapshot: &Snapshot) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        *tables = Tables::default();
+        for (user, banned) in snapshot.users.iter() {
+            if *banned {
+                tables.banned.insert(user.id);
+            }
+            let mut user = user.clone();
+            user.deleted_at = deleted_at(&user);
+            tables.user_id = tables.user_id.max(user.id);
+            tables.users.push(user);
+        }
+        tables.audit_id = snapshot.audits.iter().map(|a| a.id).max().unwrap_or(0);
+        tables.audits = snapshot.audits.clone();
+        tables.report_id = snapshot.reports.iter().map(|r| r.id).max().unwrap_or(0);
+        tables.reports = snapshot.reports.clone();
+        tables.version_id = snapshot.versions.iter().map(|v| v.id).max().unwrap_or(0);
+        tables.versions = snapshot.versions.clone();
+        tables.attributes = snapshot.attributes.clone();
+        for did in snapshot.dids.iter() {
+            tables.dids.insert(did.user_id, did.clone());
+        }
+        Ok(())
+    }
+
+    fn migrations(&self) -> Vec<Migration> {
+        vec![]
+    }
+
+    async fn applied_migrations(&self) -> Result<Vec<(i64, bool)>> {
+        Ok(vec![])
+    }
+
+    async fn migrate(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn close(&self) {}
+}
+
+/// In-memory avatars, keyed by hash, with saved time, all lost when exit.
+#[derive(Default)]
+pub(crate) struct MemoryAvatars {
+    avatars: Mutex<HashMap<String, (Vec<u8>, i64)>>,
+}
+
+impl MemoryAvatars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl AvatarStore for MemoryAvatars {
+    async fn read(&self, _base: &PathBuf, hash: &str) -> Result<Vec<u8>> {
+        let avatars = self.avatars.lock().unwrap();
+        Ok(avatars
+            .get(hash)
+            .map(|(bytes, _)| bytes.clone())
+            .unwrap_or(vec![]))
+    }
+
+    async fn write(&self, _base: &PathBuf, hash: &str, bytes: &[u8]) -> Result<()> {
+        let mut avatars = self.avatars.lock().unwrap();
+        if !avatars.contains_key(hash) {
+            avatars.insert(hash.to_owned(), (bytes.to_vec(), now()));
+        }
+        Ok(())
+    }
+
+    async fn replace(&self, _base: &PathBuf, hash: &str, bytes: &[u8]) -> Result<()> {
+        let mut avatars = self.avatars.lock().unwrap();
+        avatars.insert(hash.to_owned(), (bytes.to_vec(), now()));
+        Ok(())
+    }
+
+    async fn delete(&self, _base: &PathBuf, hash: &str) -> Result<()> {
+        self.avatars.lock().unwrap().remove(hash);
+        Ok(())
+    }
+
+    async fn list(&self, _base: &PathBuf) -> Result<Vec<(String, i64)>> {
+        let avatars = self.avatars.lock().unwrap();
+        Ok(avatars
+            .iter()
+            .map(|(key, (_, time))| (key.clone(), *time))
+            .collect())
+    }
+}
@@ -0,0 +1,1179 @@
+use async_trait::async_trait;
+use dotenv::dotenv;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::time::{Duration, UNIX_EPOCH};
+use tdn::types::{
+    primitives::Result,
+    rpc::{json, RpcParam},
+};
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::avatar::{thumbnail, THUMBNAIL_SIZES};
+use crate::crypto;
+use crate::models::{now, Attribute, Audit, AuditFilter, Did, Report, User, Version};
+use crate::shutdown;
+
+mod memory;
+mod postgres;
+mod sqlite;
+
+/// storage backend, chosen in config.toml.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Postgres server, url from `DATABASE_URL`.
+    Postgres,
+    /// embedded SQLite, saved in the db_path.
+    Sqlite,
+    /// in memory, users & avatars all lost when exit.
+    Memory,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Postgres
+    }
+}
+
+impl Backend {
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Backend::Postgres => "postgres",
+            Backend::Sqlite => "sqlite",
+            Backend::Memory => "memory",
+        }
+    }
+}
+
+/// Storage of all models, users avatars are not in it.
+/// Users got from the store have empty avatar.
+/// Changes of users are saved with their audit in one transaction, audits
+/// are append-only.
+#[async_trait]
+pub(crate) trait Store: Send + Sync {
+    /// all not deleted users.
+    async fn user_list(&self) -> Result<Vec<User>>;
+    /// users after the id, include deleted users.
+    async fn user_list_page(&self, after: i64, limit: i64) -> Result<Vec<User>>;
+    /// actived user by name (case-insensitive, by `name_key`).
+    async fn user_search(&self, name: &str) -> Result<User>;
+    /// not deleted user by name (case-insensitive, by `name_key`).
+    async fn user_get_by_name(&self, name: &str) -> Result<User>;
+    /// not deleted user by id.
+    async fn user_get(&self, id: &i64) -> Result<User>;
+    /// insert new user atomically, name key is unique in all users,
+    /// return the id or `NameConflict`. the profile is saved as the first
+    /// version, the audit is saved with the new id.
+    async fn user_insert(&self, user: &User, audit: &Audit) -> Result<i64>;
+    /// insert imported user, name key of not deleted user is unique in all
+    /// users, deleted user only in not deleted users, return the id or `NameConflict`. the profile of not deleted user is
+    /// saved as the first version, the audits are saved with the new id.
+    async fn user_import(&self, user: &User, audits: &[Audit]) -> Result<i64>;
+    /// set the bio & avatar hash of user, saved as a new version, versions
+    /// except the newest `keep` are deleted, return their avatar hashes.
+    async fn user_profile(
+        &self,
+        id: &i64,
+        bio: &str,
+        avatar: &str,
+        keep: i64,
+        audit: &Audit,
+    ) -> Result<Vec<String>>;
+    /// set the stored bio of user, maintenance only, not audited.
+    async fn user_update(&self, id: &i64, bio: &str) -> Result<()>;
+    /// set the avatar hash of user, maintenance only, not audited.
+    async fn user_avatar(&self, id: &i64, avatar: &str) -> Result<()>;
+    /// count of not deleted users & versions use the avatar hash.
+    async fn avatar_refs(&self, avatar: &str) -> Result<i64>;
+    /// banned user cannot be actived.
+    async fn user_active(&self, id: &i64, active: bool, audit: &Audit) -> Result<()>;
+    async fn user_ban(&self, id: &i64, ban: bool, audit: &Audit) -> Result<()>;
+    /// if the user is banned, false if not exists.
+    async fn user_banned(&self, id: &i64) -> Result<bool>;
+    /// soft delete, the deleted time is saved for purge.
+    async fn user_delete(&self, id: &i64, audit: &Audit) -> Result<()>;
+    /// hard delete the users soft deleted before the time, and their versions,
+    /// attributes & DID documents, audited as the actor,
+    /// return the purged users & avatar hashes of their versions.
+    async fn user_purge(&self, before: i64, actor: &str) -> Result<(Vec<User>, Vec<String>)>;
+    /// users (id, name, is_deleted) saved before the name keys, key is empty.
+    async fn user_unkeyed(&self) -> Result<Vec<(i64, String, bool)>>;
+    /// set the name keys (id, key) of users atomically.
+    async fn user_set_keys(&self, keys: &[(i64, String)]) -> Result<()>;
+
+    async fn audit_list(&self, filter: &AuditFilter) -> Result<Vec<Audit>>;
+
+    /// reports by status, empty status is all.
+    async fn report_list(&self, status: &str) -> Result<Vec<Report>>;
+    async fn report_get(&self, id: &i64) -> Result<Report>;
+    async fn report_insert(&self, report: &Report) -> Result<i64>;
+    async fn report_update(
+        &self,
+        id: &i64,
+        status: &str,
+        action: &str,
+        resolved_at: i64,
+    ) -> Result<()>;
+
+    /// versions of the user, newest first.
+    async fn version_list(&self, user_id: &i64) -> Result<Vec<Version>>;
+    async fn version_get(&self, id: &i64) -> Result<Version>;
+    /// set the stored bio of the version.
+    async fn version_bio(&self, id: &i64, bio: &str) -> Result<()>;
+    /// all avatar hashes used by versions.
+    async fn version_avatars(&self) -> Result<Vec<String>>;
+
+    /// profile attributes of the user, ordered by key.
+    async fn attribute_list(&self, user_id: &i64) -> Result<Vec<Attribute>>;
+    /// replace all attributes of the user atomically, audited if not maintenance.
+    async fn attribute_set(
+        &self,
+        user_id: &i64,
+        attributes: &[Attribute],
+        audit: Option<&Audit>,
+    ) -> Result<()>;
+
+    /// DID document of the user, none if not published.
+    async fn did_get(&self, user_id: &i64) -> Result<Option<Did>>;
+    /// save the document if its sequence is after the saved one, return is saved,
+    /// the audit is saved only with the document.
+    async fn did_set(&self, did: &Did, audit: &Audit) -> Result<bool>;
+
+    /// consistent snapshot of all rows, bios as stored.
+    async fn snapshot(&self) -> Result<Snapshot>;
+    /// replace all rows by the snapshot atomically, keep the ids,
+    /// the only way audits are removed.
+    async fn restore(&self, snapshot: &Snapshot) -> Result<()>;
+
+    /// schema migrations embedded for the backend.
+    fn migrations(&self) -> Vec<Migration>;
+    /// applied migrations (version, success).
+    async fn applied_migrations(&self) -> Result<Vec<(i64, bool)>>;
+    /// apply the pending migrations.
+    async fn migrate(&self) -> Result<()>;
+
+    /// close the connections, waiting the running queries.
+    async fn close(&self);
+}
+
+/// all rows of the store, for backup & restore.
+#[derive(Default)]
+pub struct Snapshot {
+    /// all users (include deleted) with the banned state.
+    pub users: Vec<(User, bool)>,
+    pub audits: Vec<Audit>,
+    pub reports: Vec<Report>,
+    pub versions: Vec<Version>,
+    pub attributes: Vec<Attribute>,
+    pub dids: Vec<Did>,
+}
+
+/// the name is already used, returned by user insert & import.
+#[derive(Debug)]
+pub struct NameConflict(pub String);
+
+impl std::fmt::Display for NameConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "name {} is already used", self.0)
+    }
+}
+
+impl std::error::Error for NameConflict {}
+
+/// audit of the purged user, saved by the store in the purge transaction.
+pub(crate) fn purge_audit(user: &User, actor: &str) -> Audit {
+    let mut user = user.clone();
+    user.bio = crypto::decrypt_text(std::mem::take(&mut user.bio)).unwrap_or_default();
+    Audit::new(
+        actor.to_owned(),
+        "purge",
+        user.id,
+        user.name.clone(),
+        user.snapshot(),
+        String::new(),
+    )
+}
+
+/// normalized name, names are unique by it (case-insensitive, unicode).
+/// computed here, not by the database `lower()` (ASCII only in SQLite, locale
+/// dependent in Postgres), so all backends, the cache & ordering agree.
+#[inline]
+pub(crate) fn name_key(name: &str) -> String {
+    name.to_lowercase()
+}
+
+/// deleted time saved with the inserted row, kept from the imported or
+/// restored deleted user, retention starts from now if it is unknown.
+#[inline]
+fn deleted_at(user: &User) -> i64 {
+    if !user.is_deleted {
+        0
+    } else if user.deleted_at > 0 {
+        user.deleted_at
+    } else {
+        now()
+    }
+}
+
+/// Content-addressed storage of users avatars, keyed by blake3 hash.
+#[async_trait]
+pub(crate) trait AvatarStore: Send + Sync {
+    /// empty if not exists.
+    async fn read(&self, base: &PathBuf, hash: &str) -> Result<Vec<u8>>;
+    /// save the avatar if not exists.
+    async fn write(&self, base: &PathBuf, hash: &str, bytes: &[u8]) -> Result<()>;
+    /// overwrite the saved avatar, readers never see it missing.
+    async fn replace(&self, base: &PathBuf, hash: &str, bytes: &[u8]) -> Result<()>;
+    async fn delete(&self, base: &PathBuf, hash: &str) -> Result<()>;
+    /// all saved keys, with the modified time.
+    async fn list(&self, base: &PathBuf) -> Result<Vec<(String, i64)>>;
+}
+
+/// database settings, saved in config.toml, and can be overridden by env
+/// (`DATABASE_URL`, `DATABASE_MAX_CONNECTIONS`, `DATABASE_CONNECT_TIMEOUT`,
+/// `DATABASE_IDLE_TIMEOUT`, `DATABASE_SSL_MODE`, `DATABASE_STATEMENT_CACHE`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    /// postgres url, not used by sqlite & memory.
+    pub database_url: String,
+    /// max connections of the pool.
+    pub database_max_connections: u32,
+    /// seconds of waiting a connection.
+    pub database_connect_timeout: u64,
+    /// seconds of idle connection closed, 0 is never.
+    pub database_idle_timeout: u64,
+    /// postgres TLS mode: disable, allow, prefer, require, verify-ca
+    /// or verify-full. empty is the mode in url (default prefer).
+    pub database_ssl_mode: String,
+    /// max prepared statements cached per connection.
+    pub database_statement_cache: usize,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            database_url: String::new(),
+            database_max_connections: 5,
+            database_connect_timeout: 30,
+            database_idle_timeout: 600,
+            database_ssl_mode: String::new(),
+            database_statement_cache: 100,
+        }
+    }
+}
+
+impl DatabaseConfig {
+    /// override the settings by env (and .env file).
+    pub fn with_env(mut self) -> Result<Self> {
+        dotenv().ok();
+        if let Ok(url) = env::var("DATABASE_URL") {
+            self.database_url = url;
+        }
+        if let Ok(v) = env::var("DATABASE_MAX_CONNECTIONS") {
+            self.database_max_connections = v
+                .parse()
+                .map_err(|_| anyhow!("invalid DATABASE_MAX_CONNECTIONS"))?;
+        }
+        if let Ok(v) = env::var("DATABASE_CONNECT_TIMEOUT") {
+            self.database_connect_timeout = v
+                .parse()
+                .map_err(|_| anyhow!("invalid DATABASE_CONNECT_TIMEOUT"))?;
+        }
+        if let Ok(v) = env::var("DATABASE_IDLE_TIMEOUT") {
+            self.database_idle_timeout = v
+                .parse()
+                .map_err(|_| anyhow!("invalid DATABASE_IDLE_TIMEOUT"))?;
+        }
+        if let Ok(v) = env::var("DATABASE_SSL_MODE") {
+            self.database_ssl_mode = v;
+        }
+        if let Ok(v) = env::var("DATABASE_STATEMENT_CACHE") {
+            self.database_statement_cache = v
+                .parse()
+                .map_err(|_| anyhow!("invalid DATABASE_STATEMENT_CACHE"))?;
+        }
+
+        if self.database_max_connections == 0 {
+            return Err(anyhow!("database_max_connections must be greater than 0"));
+        }
+        Ok(self)
+    }
+
+    #[inline]
+    fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.database_connect_timeout)
+    }
+
+    #[inline]
+    fn idle_timeout(&self) -> Option<Duration> {
+        if self.database_idle_timeout == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(self.database_idle_timeout))
+        }
+    }
+}
+
+/// store & avatars of the domain, passed to the models and handlers.
+#[derive(Clone)]
+pub(crate) struct Db {
+    /// the db_path, avatar files are in it.
+    pub base: PathBuf,
+    pub store: Arc<dyn Store>,
+    pub avatars: Arc<dyn AvatarStore>,
+}
+
+/// held when writing bios, so re-encryption never overwrites a new bio.
+pub(crate) static BIO_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// connect the store of backend, migrations are not applied.
+pub(crate) async fn connect(
+    base: &PathBuf,
+    backend: Backend,
+    database: DatabaseConfig,
+) -> Result<Box<dyn Store>> {
+    Ok(match backend {
+        Backend::Postgres => Box::new(postgres::PgStore::connect(&database.with_env()?).await?),
+        Backend::Sqlite => Box::new(sqlite::SqliteStore::open(base, &database.with_env()?).await?),
+        Backend::Memory => Box::new(memory::MemoryStore::new()),
+    })
+}
+
+/// connect the store of backend, and apply the pending migrations.
+async fn open(
+    base: &PathBuf,
+    backend: Backend,
+    database: DatabaseConfig,
+) -> Result<Box<dyn Store>> {
+    let store = connect(base, backend, database).await?;
+    let status = SchemaStatus::check(store.as_ref()).await?;
+    status.ensure_compatible()?;
+    if !status.pending.is_empty() {
+        store.migrate().await?;
+        info!("Applied {} database migrations", status.pending.len());
+    }
+    migrate_name_keys(store.as_ref()).await?;
+    Ok(store)
+}
+
+pub(crate) async fn init(base: &PathBuf, backend: Backend, database: DatabaseConfig) -> Result<Db> {
+    let store = open(base, backend, database).await?;
+
+    let avatars: Arc<dyn AvatarStore> = if backend == Backend::Memory {
+        Arc::new(memory::MemoryAvatars::new())
+    } else {
+        init_local_files(base).await?;
+        Arc::new(FileAvatars)
+    };
+
+    let db = Db {
+        base: base.clone(),
+        store: store.into(),
+        avatars,
+    };
+    if backend != Backend::Memory {
+        migrate_legacy_avatars(&db).await?;
+    }
+    Ok(db)
+}
+
+/// set the name keys of users saved before them. if the names conflict,
+/// fail and list them, nothing changed, they are renamed or deleted by hand.
+pub(crate) async fn migrate_name_keys(store: &dyn Store) -> Result<()> {
+    let users = store.user_unkeyed().await?;
+    if users.is_empty() {
+        return Ok(());
+    }
+
+    let mut names: HashMap<String, Vec<String>> = HashMap::new();
+    for (id, name, is_deleted) in users.iter() {
+        if !is_deleted {
+            names
+                .entry(name_key(name))
+                .or_default()
+                .push(format!("{} ({})", name, id));
+        }
+    }
+    let mut conflicts: Vec<String> = names
+        .into_values()
+        .filter(|names| names.len() > 1)
+        .map(|names| names.join(", "))
+        .collect();
+    if !conflicts.is_empty() {
+        conflicts.sort();
+        return Err(anyhow!(
+            "DB users names conflict (case-insensitive), rename or delete them by hand: {}",
+            conflicts.join("; ")
+        ));
+    }
+
+    let keys: Vec<(i64, String)> = users
+        .iter()
+        .map(|(id, name, _)| (*id, name_key(name)))
+        .collect();
+    store.user_set_keys(&keys).await?;
+    info!("Set name keys of {} users", keys.len());
+    Ok(())
+}
+
+/// schema migration embedded in the binary.
+pub struct Migration {
+    pub version: i64,
+    pub description: String,
+    pub sql: String,
+}
+
+/// migrations of the sqlx migrator.
+fn embedded_migrations(migrator: &sqlx::migrate::Migrator) -> Vec<Migration> {
+    migrator
+        .iter()
+        .map(|m| Migration {
+            version: m.version,
+            description: m.description.to_string(),
+            sql: m.sql.to_string(),
+        })
+        .collect()
+}
+
+/// schema state of the database, compared with the embedded migrations.
+pub struct SchemaStatus {
+    /// all embedded migrations, and if applied.
+    pub migrations: Vec<(Migration, bool)>,
+    /// embedded migrations not applied.
+    pub pending: Vec<i64>,
+    /// applied migrations unknown to this domain, the schema is newer.
+    pub unknown: Vec<i64>,
+    /// migrations failed (dirty), need be fixed by hand.
+    pub failed: Vec<i64>,
+}
+
+impl SchemaStatus {
+    pub async fn check(store: &dyn Store) -> Result<Self> {
+        let applied = store.applied_migrations().await?;
+        let migrations = store.migrations();
+
+        let mut unknown = vec![];
+        let mut failed = vec![];
+        for (version, success) in applied.iter() {
+            if !success {
+                failed.push(*version);
+            } else if !migrations.iter().any(|m| m.version == *version) {
+                unknown.push(*version);
+            }
+        }
+
+        let mut pending = vec![];
+        let migrations = migrations
+            .into_iter()
+            .map(|m| {
+                let is_applied = applied.iter().any(|(v, ok)| *ok && *v == m.version);
+                if !is_applied {
+                    pending.push(m.version);
+                }
+                (m, is_applied)
+            })
+            .collect();
+
+        Ok(Self {
+            migrations,
+            pending,
+            unknown,
+            failed,
+        })
+    }
+
+    /// refuse the newer or dirty schema.
+    pub fn ensure_compatible(&self) -> Result<()> {
+        if !self.unknown.is_empty() {
+            return Err(anyhow!(
+                "DB schema is newer than this domain (unknown migrations {:?}), upgrade the domain",
+                self.unknown
+            ));
+        }
+        if !self.failed.is_empty() {
+            return Err(anyhow!(
+                "DB migrations {:?} failed, fix the database by hand",
+                self.failed
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) const AVATAR_DIR: &'static str = "avatars";
+
+/// suffix of avatar files in writing.
+const TEMP_SUFFIX: &'static str = ".tmp";
+
+/// avatars files younger than it are not reconciled, may be in a registration.
+const RECONCILE_GRACE: i64 = 600;
+
+/// seconds between two reconciliations.
+const RECONCILE_INTERVAL: u64 = 3600;
+
+/// written in the db_path when the legacy avatars all moved.
+const LEGACY_MARKER: &'static str = "avatars.migrated";
+
+/// count of the avatar locks, hashes share them by stripe.
+const AVATAR_LOCKS: usize = 64;
+
+/// held when saving or releasing an avatar, so the files never deleted
+/// between written and referenced by the committed row.
+static AVATAR_LOCK: Lazy<Vec<Mutex<()>>> =
+    Lazy::new(|| (0..AVATAR_LOCKS).map(|_| Mutex::new(())).collect());
+
+/// online backups running, no avatar deleted while any.
+static AVATAR_PINS: AtomicUsize = AtomicUsize::new(0);
+
+/// avatars kept until dropped, the unused are deleted by next reconciliation.
+pub(crate) struct AvatarPin;
+
+impl Drop for AvatarPin {
+    fn drop(&mut self) {
+        AVATAR_PINS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// keep all avatars files, e.g. when backup.
+pub(crate) fn pin_avatars() -> AvatarPin {
+    AVATAR_PINS.fetch_add(1, Ordering::SeqCst);
+    AvatarPin
+}
+
+#[inline]
+fn is_pinned() -> bool {
+    AVATAR_PINS.load(Ordering::SeqCst) > 0
+}
+
+/// lock of the avatar hash, held until the row referencing it committed.
+pub(crate) async fn lock_avatar(hash: &str) -> MutexGuard<'static, ()> {
+    let stripe = hash
+        .bytes()
+        .fold(0usize, |h, b| h.wrapping_mul(31).wrapping_add(b as usize));
+    AVATAR_LOCK[stripe % AVATAR_LOCKS].lock().await
+}
+
+pub(crate) async fn init_local_files(base: &PathBuf) -> Result<()> {
+    let mut avatar_path = base.clone();
+    avatar_path.push(AVATAR_DIR);
+    if !avatar_path.exists() {
+        fs::create_dir_all(avatar_path).await?;
+    }
+    Ok(())
+}
+
+/// Avatars in the db_path files, file name is the hash.
+struct FileAvatars;
+
+#[async_trait]
+impl AvatarStore for FileAvatars {
+    async fn read(&self, base: &PathBuf, hash: &str) -> Result<Vec<u8>> {
+        let mut path = base.clone();
+        path.push(AVATAR_DIR);
+        path.push(hash);
+        if path.exists() {
+            Ok(fs::read(path).await?)
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    async fn write(&self, base: &PathBuf, hash: &str, bytes: &[u8]) -> Result<()> {
+        let mut path = base.clone();
+        path.push(AVATAR_DIR);
+        path.push(hash);
+        if path.exists() {
+            return Ok(());
+        }
+        write_file(base, hash, bytes).await
+    }
+
+    async fn replace(&self, base: &PathBuf, hash: &str, bytes: &[u8]) -> Result<()> {
+        write_file(base, hash, bytes).await
+    }
+
+    async fn delete(&self, base: &PathBuf, hash: &str) -> Result<()> {
+        let mut path = base.clone();
+        path.push(AVATAR_DIR);
+        path.push(hash);
+        if path.exists() {
+            Ok(fs::remove_file(path).await?)
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn list(&self, base: &PathBuf) -> Result<Vec<(String, i64)>> {
+        let mut path = base.clone();
+        path.push(AVATAR_DIR);
+        let mut files = vec![];
+        let mut entries = fs::read_dir(path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            files.push((entry.file_name().to_string_lossy().into_owned(), modified));
+        }
+        Ok(files)
+    }
+}
+
+/// write to a temp file, then rename it, a crash never leaves half file.
+async fn write_file(base: &PathBuf, hash: &str, bytes: &[u8]) -> Result<()> {
+    let mut path = base.clone();
+    path.push(AVATAR_DIR);
+    let mut tmp = path.clone();
+    path.push(hash);
+    tmp.push(format!("{}.{}{}", hash, rand::random::<u32>(), TEMP_SUFFIX));
+    if let Err(e) = write_synced(&tmp, bytes).await {
+        let _ = fs::remove_file(&tmp).await;
+        return Err(e);
+    }
+    Ok(fs::rename(tmp, path).await?)
+}
+
+/// write the file and flush it to the disk, before it renamed.
+async fn write_synced(path: &PathBuf, bytes: &[u8]) -> Result<()> {
+    let mut file = fs::File::create(path).await?;
+    file.write_all(bytes).await?;
+    file.sync_all().await?;
+    Ok(())
+}
+
+/// blake3 hash of the avatar, empty avatar is empty hash.
+#[inline]
+pub(crate) fn avatar_hash(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        String::new()
+    } else {
+        blake3::hash(bytes).to_hex().to_string()
+    }
+}
+
+#[inline]
+pub(crate) async fn read_avatar(db: &Db, hash: &str) -> Result<Vec<u8>> {
+    if hash.is_empty() {
+        return Ok(vec![]);
+    }
+    crypto::decrypt_bytes(db.avatars.read(&db.base, hash).await?)
+}
+
+/// key of the avatar thumbnail.
+#[inline]
+pub(crate) fn thumbnail_key(hash: &str, size: u32) -> String {
+    format!("{}-{}", hash, size)
+}
+
+/// read the avatar thumbnail, empty if not exists.
+#[inline]
+pub(crate) async fn read_thumbnail(db: &Db, hash: &str, size: u32) -> Result<Vec<u8>> {
+    if hash.is_empty() {
+        return Ok(vec![]);
+    }
+    let bytes = db
+        .avatars
+        .read(&db.base, &thumbnail_key(hash, size))
+        .await?;
+    crypto::decrypt_bytes(bytes)
+}
+
+/// save the avatar and thumbnails, return the hash and the lock of it,
+/// hold the lock until the row referencing the hash committed.
+pub(crate) async fn write_avatar(
+    db: &Db,
+    bytes: &[u8],
+) -> Result<(String, Option<MutexGuard<'static, ()>>)> {
+    let hash = avatar_hash(bytes);
+    let mut guard = None;
+    if !hash.is_empty() {
+        guard = Some(lock_avatar(&hash).await);
+        let (base, avatars) = (&db.base, &db.avatars);
+        avatars
+            .write(base, &hash, &crypto::encrypt_bytes(bytes)?)
+            .await?;
+        for size in THUMBNAIL_SIZES {
+            match thumbnail(bytes, size) {
+                Ok(thumb) => {
+                    let thumb = crypto::encrypt_bytes(&thumb)?;
+                    avatars
+                        .write(base, &thumbnail_key(&hash, size), &thumb)
+                        .await?
+                }
+                Err(e) => warn!("avatar {} thumbnail failure: {}", hash, e),
+            }
+        }
+    }
+    Ok((hash, guard))
+}
+
+/// delete the avatar and thumbnails when no user use it, return if deleted.
+pub(crate) async fn release_avatar(db: &Db, hash: &str) -> Result<bool> {
+    if hash.is_empty() {
+        return Ok(false);
+    }
+    let _guard = lock_avatar(hash).await;
+    delete_unused(db, hash).await
+}
+
+/// delete the avatar and thumbnails if not referenced, the lock of hash held.
+async fn delete_unused(db: &Db, hash: &str) -> Result<bool> {
+    if is_pinned() || db.store.avatar_refs(hash).await? > 0 {
+        return Ok(false);
+    }
+    let (base, avatars) = (&db.base, &db.avatars);
+    for size in THUMBNAIL_SIZES {
+        avatars.delete(base, &thumbnail_key(hash, size)).await?;
+    }
+    avatars.delete(base, hash).await?;
+    Ok(true)
+}
+
+/// move the avatars saved by user id (`avatars/{id}.png`) to content-addressed,
+/// only once, the marker is written when all moved.
+async fn migrate_legacy_avatars(db: &Db) -> Result<()> {
+    let (base, store) = (&db.base, &db.store);
+    let mut marker = base.clone();
+    marker.push(LEGACY_MARKER);
+    if marker.exists() {
+        return Ok(());
+    }
+
+    let mut after = 0;
+    loop {
+        let users = store.user_list_page(after, 100).await?;
+        if users.is_empty() {
+            break;
+        }
+
+        for user in users {
+            after = user.id;
+            let mut path = base.clone();
+            path.push(AVATAR_DIR);
+            path.push(format!("{}.png", user.id));
+            if !path.exists() {
+                continue;
+            }
+
+            if user.avatar_hash.is_empty() && !user.is_deleted {
+                let bytes = fs::read(&path).await?;
+                let (hash, _guard) = write_avatar(db, &bytes).await?;
+                store.user_avatar(&user.id, &hash).await?;
+            }
+            fs::remove_file(path).await?;
+        }
+    }
+    fs::write(marker, now().to_string()).await?;
+    Ok(())
+}
+
+/// if any bio, attribute, version or avatar file is encrypted,
+/// checked before the encryption key is loaded.
+pub(crate) async fn has_encrypted(
+    base: &PathBuf,
+    backend: Backend,
+    database: DatabaseConfig,
+) -> Result<bool> {
+    if backend == Backend::Memory {
+        return Ok(false);
+    }
+    let store = open(base, backend, database).await?;
+    let snapshot = store.snapshot().await;
+    store.close().await;
+    let snapshot = snapshot?;
+    if snapshot
+        .users
+        .iter()
+        .any(|(u, _)| crypto::text_version(&u.bio) > 0)
+        || snapshot
+            .versions
+            .iter()
+            .any(|v| crypto::text_version(&v.bio) > 0)
+        || snapshot
+            .attributes
+            .iter()
+            .any(|a| crypto::text_version(&a.value) > 0)
+    {
+        return Ok(true);
+    }
+
+    let mut path = base.clone();
+    path.push(AVATAR_DIR);
+    if !path.exists() {
+        return Ok(false);
+    }
+    for (hash, _) in FileAvatars.list(base).await? {
+        if crypto::bytes_version(&FileAvatars.read(base, &hash).await?) > 0 {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// re-encrypt the bios & avatars not encrypted by current key,
+/// used when encryption enabled or key rotated. return the count.
+pub(crate) async fn reencrypt(db: &Db) -> Result<usize> {
+    if !crypto::is_enabled() {
+        return Ok(0);
+    }
+    let version = crypto::version();
+    let (base, store, avatars) = (&db.base, &db.store, &db.avatars);
+    let mut hashes = HashSet::new();
+    let mut count = 0;
+
+    let mut after = 0;
+    loop {
+        // stopping, the rest is re-encrypted by next start.
+        if shutdown::is_stopping() {
+            return Ok(count);
+        }
+        let users = store.user_list_page(after, 100).await?;
+        if users.is_empty() {
+            break;
+        }
+
+        for user in users {
+            after = user.id;
+            if !user.bio.is_empty() && crypto::text_version(&user.bio) != version {
+                let _guard = BIO_LOCK.lock().await;
+                let bio = if user.is_deleted {
+                    user.bio
+                } else {
+                    store.user_get(&user.id).await?.bio
+                };
+                if crypto::text_version(&bio) != version {
+                    let bio = crypto::encrypt_text(&crypto::decrypt_text(bio)?)?;
+                    store.user_update(&user.id, &bio).await?;
+                    count += 1;
+                }
+            }
+
+            let attributes = store.attribute_list(&user.id).await?;
+            if attributes
+                .iter()
+                .any(|a| crypto::text_version(&a.value) != version)
+            {
+                let _guard = BIO_LOCK.lock().await;
+                let mut attributes = store.attribute_list(&user.id).await?;
+                for a in attributes.iter_mut() {
+                    if crypto::text_version(&a.value) != version {
+                        a.value = crypto::encrypt_text(&crypto::decrypt_text(std::mem::take(
+                            &mut a.value,
+                        ))?)?;
+                        count += 1;
+                    }
+                }
+                store.attribute_set(&user.id, &attributes, None).await?;
+            }
+
+            // versions never change, not need the lock.
+            for v in store.version_list(&user.id).await? {
+                if !v.bio.is_empty() && crypto::text_version(&v.bio) != version {
+                    let bio = crypto::encrypt_text(&crypto::decrypt_text(v.bio)?)?;
+                    store.version_bio(&v.id, &bio).await?;
+                    count += 1;
+                }
+            }
+
+            if !user.avatar_hash.is_empty() {
+                hashes.insert(user.avatar_hash);
+            }
+        }
+    }
+    hashes.extend(store.version_avatars().await?);
+
+    for hash in hashes {
+        if shutdown::is_stopping() {
+            break;
+        }
+        let mut keys = vec![hash.clone()];
+        for size in THUMBNAIL_SIZES {
+            keys.push(thumbnail_key(&hash, size));
+        }
+        let _guard = lock_avatar(&hash).await;
+        for key in keys {
+            let bytes = avatars.read(base, &key).await?;
+            if bytes.is_empty() || crypto::bytes_version(&bytes) == version {
+                continue;
+            }
+            let bytes = crypto::encrypt_bytes(&crypto::decrypt_bytes(bytes)?)?;
+            avatars.replace(base, &key, &bytes).await?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// close the store when the domain stopping.
+pub(crate) async fn close(db: &Db) {
+    db.store.close().await;
+}
+
+/// re-encrypt in background.
+pub(crate) fn spawn_reencrypt(db: Db) {
+    tokio::spawn(async move {
+        let _work = match shutdown::begin() {
+            Some(work) => work,
+            None => return,
+        };
+        match reencrypt(&db).await {
+            Ok(0) => {}
+            Ok(count) => info!("Re-encrypted {} bios & avatars", count),
+            Err(e) => error!("Re-encrypt failure: {}", e),
+        }
+    });
+}
+
+/// result of the avatars reconciliation.
+#[derive(Default)]
+pub struct ReconcileReport {
+    /// avatar files no user use, removed.
+    pub orphans: usize,
+    /// temp files left by crash, removed.
+    pub temps: usize,
+    /// users (name, hash) whose avatar is missing.
+    pub missing: Vec<(String, String)>,
+}
+
+impl ReconcileReport {
+    pub fn to_rpc(self) -> RpcParam {
+        json!({
+            "orphans": self.orphans,
+            "temps": self.temps,
+            "missing": self.missing,
+        })
+    }
+}
+
+/// remove the avatar files no user use, and report users whose avatar missing.
+pub(crate) async fn reconcile(db: &Db) -> Result<ReconcileReport> {
+    let (base, store, avatars) = (&db.base, &db.store, &db.avatars);
+    let mut report = ReconcileReport::default();
+
+    let mut users = vec![];
+    let mut after = 0;
+    loop {
+        let page = store.user_list_page(after, 100).await?;
+        if page.is_empty() {
+            break;
+        }
+        for user in page {
+            after = user.id;
+            if !user.is_deleted && !user.avatar_hash.is_empty() {
+                users.push((user.name, user.avatar_hash));
+            }
+        }
+    }
+    // avatars of versions can be reverted to, keep them.
+    let versions = store.version_avatars().await?;
+    let mut used: HashSet<&str> = users.iter().map(|(_, hash)| hash.as_str()).collect();
+    used.extend(versions.iter().map(|hash| hash.as_str()));
+
+    let files = avatars.list(base).await?;
+    let now = now();
+    let mut orphans: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (key, modified) in files.iter() {
+        if now - modified < RECONCILE_GRACE {
+            continue;
+        }
+        if key.ends_with(TEMP_SUFFIX) {
+            avatars.delete(base, key).await?;
+            report.temps += 1;
+            continue;
+        }
+        // key is hash, or thumbnail `hash-size`.
+        let hash = key.split('-').next().unwrap_or("");
+        if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) && !used.contains(hash) {
+            orphans.entry(hash).or_default().push(key);
+        }
+    }
+    // the hash may be referenced after listed, check again under the lock.
+    for (hash, keys) in orphans {
+        let _guard = lock_avatar(hash).await;
+        if is_pinned() || store.avatar_refs(hash).await? > 0 {
+            continue;
+        }
+        for key in keys {
+            avatars.delete(base, key).await?;
+            report.orphans += 1;
+        }
+    }
+
+    let keys: HashSet<&str> = files.iter().map(|(key, _)| key.as_str()).collect();
+    for (name, hash) in users.iter() {
+        if !keys.contains(hash.as_str()) {
+            warn!("user {} avatar {} is missing", name, hash);
+            report.missing.push((name.clone(), hash.clone()));
+        }
+    }
+
+    Ok(report)
+}
+
+/// reconcile when start, and periodically.
+pub(crate) fn spawn_reconcile(db: Db) {
+    tokio::spawn(async move {
+        while let Some(work) = shutdown::begin() {
+            match reconcile(&db).await {
+                Ok(r) => {
+                    if r.orphans + r.temps > 0 || !r.missing.is_empty() {
+                        info!(
+                            "Reconciled avatars: {} orphans, {} temps removed, {} missing",
+                            r.orphans,
+                            r.temps,
+                            r.missing.len()
+                        );
+                    }
+                }
+                Err(e) => error!("Reconcile avatars failure: {}", e),
+            }
+            drop(work);
+            tokio::time::sleep(Duration::from_secs(RECONCILE_INTERVAL)).await;
+        }
+    });
+}
+
+/// default days of deleted users kept before purge.
+pub(crate) const DEFAULT_PURGE_RETENTION: u64 = 30;
+
+/// seconds between two purges.
+const PURGE_INTERVAL: u64 = 3600;
+
+/// Purge result.
+#[derive(Default)]
+pub struct PurgeReport {
+    /// purged users (id, name).
+    pub users: Vec<(i64, String)>,
+    /// avatars deleted, used only by the purged users & versions.
+    pub avatars: usize,
+}
+
+impl PurgeReport {
+    pub fn to_rpc(self) -> RpcParam {
+        json!({
+            "users": self.users,
+            "avatars": self.avatars,
+        })
+    }
+}
+
+/// hard delete the users deleted more than retention days ago, with their
+/// versions & avatars, audit logs & reports are kept.
+pub(crate) async fn purge(db: &Db, retention: u64, actor: &str) -> Result<PurgeReport> {
+    let before = now() - retention as i64 * 86400;
+    let (users, versions) = db.store.user_purge(before, actor).await?;
+    let mut report = PurgeReport::default();
+
+    let mut hashes: HashSet<String> = versions.into_iter().collect();
+    for user in users {
+        info!("Purged deleted user {} ({})", user.name, user.id);
+        hashes.insert(user.avatar_hash);
+        report.users.push((user.id, user.name));
+    }
+
+    for hash in hashes.iter().filter(|h| !h.is_empty()) {
+        match release_avatar(db, hash).await {
+            Ok(true) => report.avatars += 1,
+            Ok(false) => {}
+            Err(e) => warn!("release avatar {} failure: {}", hash, e),
+        }
+    }
+
+    Ok(report)
+}
+
+/// purge periodically, retention 0 is disabled.
+pub(crate) fn spawn_purge(db: Db, retention: u64) {
+    if retention == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        while let Some(work) = shutdown::begin() {
+            match purge(&db, retention, "system").await {
+                Ok(r) => {
+                    if !r.users.is_empty() {
+                        info!(
+                            "Purged {} deleted users, {} avatars released",
+                            r.users.len(),
+                            r.avatars
+                        );
+                    }
+                }
+                Err(e) => error!("Purge deleted users failure: {}", e),
+            }
+            drop(work);
+            tokio::time::sleep(Duration::from_secs(PURGE_INTERVAL)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tdn::types::primitives::PeerId;
+
+    /// stores of all backends, postgres only if `DATABASE_URL` is set.
+    async fn stores(base: &PathBuf) -> Vec<Box<dyn Store>> {
+        let mut backends = vec![Backend::Memory, Backend::Sqlite];
+        if env::var("DATABASE_URL").is_ok() {
+            backends.push(Backend::Postgres);
+        }
+        let mut stores = vec![];
+        for backend in backends {
+            stores.push(
+                open(base, backend, DatabaseConfig::default())
+                    .await
+                    .unwrap(),
+            );
+        }
+        stores
+    }
+
+    #[tokio::test]
+    async fn deleted_name_reserved() {
+        let base = env::temp_dir().join(format!("domain-store-{}", rand::random::<u32>()));
+        fs::create_dir_all(&base).await.unwrap();
+        let name = format!("Alice{}", rand::random::<u32>());
+        let audit = Audit::new(
+            "test".to_owned(),
+            "register",
+            0,
+            name.clone(),
+            String::new(),
+            String::new(),
+        );
+
+        for store in stores(&base).await {
+            let user = User::new(name.clone(), PeerId([1; 32]), String::new(), vec![]);
+            let id = store.user_insert(&user, &audit).await.unwrap();
+            store.user_delete(&id, &audit).await.unwrap();
+
+            let other = User::new(name.to_lowercase(), PeerId([2; 32]), String::new(), vec![]);
+            let error = store.user_insert(&other, &audit).await.unwrap_err();
+            assert!(error.downcast_ref::<NameConflict>().is_some());
+            let error = store.user_import(&other, &[]).await.unwrap_err();
+            assert!(error.downcast_ref::<NameConflict>().is_some());
+            store.close().await;
+        }
+
+        let _ = fs::remove_dir_all(&base).await;
+    }
+}
@@ -0,0 +1,1011 @@
+use async_trait::async_trait;
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgRow, PgSslMode};
+use sqlx::{PgPool, Postgres, Row, Transaction};
+use std::str::FromStr;
+use tdn::types::primitives::{PeerId, Result};
+
+use super::{
+    deleted_at, embedded_migrations, name_key, purge_audit, DatabaseConfig, Migration,
+    NameConflict, Snapshot, Store,
+};
+use crate::models::{now, Attribute, Audit, AuditFilter, Did, Report, User, Version};
+
+/// migrations embedded in the binary.
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// Postgres server storage.
+pub(crate) struct PgStore {
+    pool: PgPool,
+}
+
+impl PgStore {
+    pub async fn connect(cfg: &DatabaseConfig) -> Result<Self> {
+        if cfg.database_url.is_empty() {
+            return Err(anyhow!(
+                "DB postgres url missing! set database_url in config.toml or DATABASE_URL"
+            ));
+        }
+        let mut options = PgConnectOptions::from_str(&cfg.database_url)
+            .map_err(|e| anyhow!("DB postgres url invalid: {}", e))?
+            .statement_cache_capacity(cfg.database_statement_cache);
+        if !cfg.database_ssl_mode.is_empty() {
+            let mode = PgSslMode::from_str(&cfg.database_ssl_mode)
+                .map_err(|_| anyhow!("DB postgres ssl mode invalid: {}", cfg.database_ssl_mode))?;
+            options = options.ssl_mode(mode);
+        }
+
+        let pool = PgPoolOptions::new()
+            .max_connections(cfg.database_max_connections)
+            .connect_timeout(cfg.connect_timeout())
+            .idle_timeout(cfg.idle_timeout())
+            .connect_with(options)
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "DB postgres connect failure! check database & user/password: {}",
+                    e
+                )
+            })?;
+
+        Ok(Self { pool })
+    }
+}
+
+fn user_from_row(row: PgRow) -> Result<User> {
+    let pid: String = row.try_get("pid")?;
+    Ok(User {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        pid: PeerId::from_hex(&pid).unwrap_or(PeerId::default()),
+        bio: row.try_get("bio")?,
+        avatar: vec![],
+        avatar_hash: row.try_get("avatar")?,
+        is_actived: row.try_get("is_actived")?,
+        is_deleted: row.try_get("is_deleted")?,
+        deleted_at: row.try_get("deleted_at")?,
+        datetime: row.try_get("datetime")?,
+    })
+}
+
+fn audit_from_row(row: PgRow) -> Result<Audit> {
+    Ok(Audit {
+        id: row.try_get("id")?,
+        actor: row.try_get("actor")?,
+        operation: row.try_get("operation")?,
+        user_id: row.try_get("user_id")?,
+        name: row.try_get("name")?,
+        old_value: row.try_get("old_value")?,
+        new_value: row.try_get("new_value")?,
+        datetime: row.try_get("datetime")?,
+    })
+}
+
+fn report_from_row(row: PgRow) -> Result<Report> {
+    let reporter: String = row.try_get("reporter")?;
+    Ok(Report {
+        id: row.try_get("id")?,
+        reporter: PeerId::from_hex(&reporter).unwrap_or(PeerId::default()),
+        user_id: row.try_get("user_id")?,
+        name: row.try_get("name")?,
+        reason: row.try_get("reason")?,
+        status: row.try_get("status")?,
+        action: row.try_get("action")?,
+        datetime: row.try_get("datetime")?,
+        resolved_at: row.try_get("resolved_at")?,
+    })
+}
+
+fn version_from_row(row: PgRow) -> Result<Version> {
+    Ok(Version {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        bio: row.try_get("bio")?,
+        avatar_hash: row.try_get("avatar")?,
+        datetime: row.try_get("datetime")?,
+    })
+}
+
+fn attribute_from_row(row: PgRow) -> Result<Attribute> {
+    Ok(Attribute {
+        user_id: row.try_get("user_id")?,
+        key: row.try_get("key")?,
+        value: row.try_get("value")?,
+        is_public: row.try_get("is_public")?,
+        datetime: row.try_get("datetime")?,
+    })
+}
+
+fn did_from_row(row: PgRow) -> Result<Did> {
+    Ok(Did {
+        user_id: row.try_get("user_id")?,
+        document: row.try_get("document")?,
+        key_id: row.try_get("key_id")?,
+        signature: row.try_get("signature")?,
+        sequence: row.try_get("sequence")?,
+        datetime: row.try_get("datetime")?,
+    })
+}
+
+/// append the audit of the user in the transaction of the change.
+async fn insert_audit(
+    tx: &mut Transaction<'_, Postgres>,
+    audit: &Audit,
+    user_id: i64,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO audits (actor, operation, user_id, name, old_value, new_value, datetime) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(&audit.actor)
+    .bind(&audit.operation)
+    .bind(user_id)
+    .bind(&audit.name)
+    .bind(&audit.old_value)
+    .bind(&audit.new_value)
+    .bind(audit.datetime)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| anyhow!("database failure."))?;
+
+    Ok(())
+}
+
+/// save the profile of the user as a new version, in the transaction of the change.
+async fn insert_version(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: i64,
+    bio: &str,
+    avatar: &str,
+) -> Result<()> {
+    sqlx::query("INSERT INTO versions (user_id, bio, avatar, datetime) VALUES ($1, $2, $3, $4)")
+        .bind(user_id)
+        .bind(bio)
+        .bind(avatar)
+        .bind(now())
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+    Ok(())
+}
+
+/// delete the versions of user except the newest `keep`, return their avatar hashes.
+async fn prune_versions(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: i64,
+    keep: i64,
+) -> Result<Vec<String>> {
+    sqlx::query_scalar(
+        "DELETE FROM versions WHERE user_id = $1 AND id NOT IN (SELECT id FROM versions WHERE user_id = $1 ORDER BY id DESC LIMIT $2) RETURNING avatar",
+    )
+    .bind(user_id)
+    .bind(keep)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| anyhow!("database failure."))
+}
+
+#[async_trait]
+impl Store for PgStore {
+    async fn user_list(&self) -> Result<Vec<User>> {
+        let rows = sqlx::query(
+            "SELECT id, name, pid, bio, avatar, is_actived, is_deleted, deleted_at, datetime FROM users WHERE is_deleted = false ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        rows.into_iter().map(user_from_row).collect()
+    }
+
+    async fn user_list_page(&self, after: i64, limit: i64) -> Result<Vec<User>> {
+        let rows = sqlx::query(
+            "SELECT id, name, pid, bio, avatar, is_actived, is_deleted, deleted_at, datetime FROM users WHERE id > $1 ORDER BY id LIMIT $2",
+        )
+        .bind(after)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        rows.into_iter().map(user_from_row).collect()
+    }
+
+    async fn user_search(&self, name: &str) -> Result<User> {
+        let row = sqlx::query(
+            "SELECT id, name, pid, bio, avatar, is_actived, is_deleted, deleted_at, datetime FROM users WHERE is_actived = true AND name_key = $1",
+        )
+        .bind(name_key(name))
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        user_from_row(row)
+    }
+
+    async fn user_get_by_name(&self, name: &str) -> Result<User> {
+        let row = sqlx::query(
+            "SELECT id, name, pid, bio, avatar, is_actived, is_deleted, deleted_at, datetime FROM users WHERE is_deleted = false AND name_key = $1",
+        )
+        .bind(name_key(name))
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        user_from_row(row)
+    }
+
+    async fn user_get(&self, id: &i64) -> Result<User> {
+        let row = sqlx::query(
+            "SELECT id, name, pid, bio, avatar, is_actived, is_deleted, deleted_at, datetime FROM users WHERE is_deleted = false AND id = $1",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        user_from_row(row)
+    }
+
+    async fn user_insert(&self, user: &User, audit: &Audit) -> Result<i64> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        // deleted names are reserved, the unique index guards concurrent inserts.
+        let id: Option<i64> = sqlx::query_scalar(
+            "INSERT INTO users (name, name_key, pid, bio, avatar, is_actived, datetime) SELECT $1::VARCHAR, $2::VARCHAR, $3::VARCHAR, $4::TEXT, $5::TEXT, $6::BOOLEAN, $7::BIGINT WHERE NOT EXISTS (SELECT 1 FROM users WHERE name_key = $2) ON CONFLICT DO NOTHING RETURNING id",
+        )
+        .bind(&user.name)
+        .bind(name_key(&user.name))
+        .bind(user.pid.to_hex())
+        .bind(&user.bio)
+        .bind(&user.avatar_hash)
+        .bind(user.is_actived)
+        .bind(user.datetime)
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        let id = id.ok_or_else(|| NameConflict(user.name.clone()))?;
+        insert_version(&mut tx, id, &user.bio, &user.avatar_hash).await?;
+        insert_audit(&mut tx, audit, id).await?;
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(id)
+    }
+
+    async fn user_import(&self, user: &User, audits: &[Audit]) -> Result<i64> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        // as insert, deleted names are reserved for the active users.
+        let id: Option<i64> = sqlx::query_scalar(
+            "INSERT INTO users (name, name_key, pid, bio, avatar, is_actived, is_deleted, deleted_at, datetime) SELECT $1::VARCHAR, $2::VARCHAR, $3::VARCHAR, $4::TEXT, $5::TEXT, $6::BOOLEAN, $7::BOOLEAN, $8::BIGINT, $9::BIGINT WHERE $7::BOOLEAN OR NOT EXISTS (SELECT 1 FROM users WHERE name_key = $2) ON CONFLICT DO NOTHING RETURNING id",
+        )
+        .bind(&user.name)
+        .bind(name_key(&user.name))
+        .bind(user.pid.to_hex())
+        .bind(&user.bio)
+        .bind(&user.avatar_hash)
+        .bind(user.is_actived)
+        .bind(user.is_deleted)
+        .bind(deleted_at(user))
+        .bind(user.datetime)
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        let id = id.ok_or_else(|| NameConflict(user.name.clone()))?;
+        if !user.is_deleted {
+            insert_version(&mut tx, id, &user.bio, &user.avatar_hash).await?;
+        }
+        for audit in audits {
+            insert_audit(&mut tx, audit, id).await?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(id)
+    }
+
+    async fn user_profile(
+        &self,
+        id: &i64,
+        bio: &str,
+        avatar: &str,
+        keep: i64,
+        audit: &Audit,
+    ) -> Result<Vec<String>> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        sqlx::query("UPDATE users SET bio = $1, avatar = $2 WHERE id = $3")
+            .bind(bio)
+            .bind(avatar)
+            .bind(id)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        insert_version(&mut tx, *id, bio, avatar).await?;
+        let avatars = prune_versions(&mut tx, *id, keep).await?;
+        insert_audit(&mut tx, audit, *id).await?;
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(avatars)
+    }
+
+    async fn user_update(&self, id: &i64, bio: &str) -> Result<()> {
+        sqlx::query("UPDATE users SET bio = $1 WHERE id = $2")
+            .bind(bio)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        Ok(())
+    }
+
+    async fn user_avatar(&self, id: &i64, avatar: &str) -> Result<()> {
+        sqlx::query("UPDATE users SET avatar = $1 WHERE id = $2")
+            .bind(avatar)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        Ok(())
+    }
+
+    async fn avatar_refs(&self, avatar: &str) -> Result<i64> {
+        let refs: i64 = sqlx::query_scalar(
+            "SELECT (SELECT COUNT(*) FROM users WHERE avatar = $1 AND is_deleted = false) + (SELECT COUNT(*) FROM versions WHERE avatar = $1)",
+        )
+        .bind(avatar)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        Ok(refs)
+    }
+
+    async fn user_active(&self, id: &i64, active: bool, audit: &Audit) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        let res =
+            sqlx::query("UPDATE users SET is_actived = $1 WHERE id = $2 AND is_banned = false")
+                .bind(active)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .map_err(|_| anyhow!("database failure."))?;
+
+        if res.rows_affected() == 0 {
+            return Err(anyhow!("user is banned."));
+        }
+        insert_audit(&mut tx, audit, *id).await?;
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(())
+    }
+
+    async fn user_ban(&self, id: &i64, ban: bool, audit: &Audit) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        sqlx::query("UPDATE users SET is_banned = $1, is_actived = NOT $1 WHERE id = $2")
+            .bind(ban)
+            .bind(id)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        insert_audit(&mut tx, audit, *id).await?;
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(())
+    }
+
+    async fn user_banned(&self, id: &i64) -> Result<bool> {
+        let banned: Option<bool> = sqlx::query_scalar("SELECT is_banned FROM users WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        Ok(banned.unwrap_or(false))
+    }
+
+    async fn user_delete(&self, id: &i64, audit: &Audit) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        sqlx::query(
+            "UPDATE users SET is_actived = false, is_deleted = true, deleted_at = $1 WHERE id = $2",
+        )
+        .bind(now())
+        .bind(id)
+        .execute(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+        insert_audit(&mut tx, audit, *id).await?;
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(())
+    }
+
+    async fn user_purge(&self, before: i64, actor: &str) -> Result<(Vec<User>, Vec<String>)> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        let rows = sqlx::query(
+            "DELETE FROM users WHERE is_deleted = true AND deleted_at < $1 RETURNING id, name, pid, bio, avatar, is_actived, is_deleted, deleted_at, datetime",
+        )
+        .bind(before)
+        .fetch_all(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+        let users = rows
+            .into_iter()
+            .map(user_from_row)
+            .collect::<Result<Vec<User>>>()?;
+
+        let ids: Vec<i64> = users.iter().map(|u| u.id).collect();
+        let avatars: Vec<String> =
+            sqlx::query_scalar("DELETE FROM versions WHERE user_id = ANY($1) RETURNING avatar")
+                .bind(&ids)
+                .fetch_all(&mut tx)
+                .await
+                .map_err(|_| anyhow!("database failure."))?;
+
+        sqlx::query("DELETE FROM attributes WHERE user_id = ANY($1)")
+            .bind(&ids)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        sqlx::query("DELETE FROM dids WHERE user_id = ANY($1)")
+            .bind(&ids)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        for user in users.iter() {
+            insert_audit(&mut tx, &purge_audit(user, actor), user.id).await?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok((users, avatars))
+    }
+
+    async fn user_unkeyed(&self) -> Result<Vec<(i64, String, bool)>> {
+        let rows = sqlx::query("SELECT id, name, is_deleted FROM users WHERE name_key = ''")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        let mut users = vec![];
+        for row in rows {
+            users.push((
+                row.try_get("id")?,
+                row.try_get("name")?,
+                row.try_get("is_deleted")?,
+            ));
+        }
+        Ok(users)
+    }
+
+    async fn user_set_keys(&self, keys: &[(i64, String)]) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        for (id, key) in keys {
+            sqlx::query("UPDATE users SET name_key = $1 WHERE id = $2")
+                .bind(key)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .map_err(|_| anyhow!("database failure."))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(())
+    }
+
+    async fn audit_list(&self, filter: &AuditFilter) -> Result<Vec<Audit>> {
+        let end = if filter.end > 0 { filter.end } else { i64::MAX };
+        let limit = if filter.limit > 0 {
+            filter.limit
+        } else {
+            i64::MAX
+        };
+
+        let rows = sqlx::query(
+            "SELECT id, actor, operation, user_id, name, old_value, new_value, datetime FROM audits WHERE ($1::TEXT IS NULL OR actor = $1) AND ($2::TEXT IS NULL OR operation = $2) AND ($3::BIGINT IS NULL OR user_id = $3) AND datetime >= $4 AND datetime <= $5 ORDER BY id DESC LIMIT $6",
+        )
+        .bind(&filter.actor)
+        .bind(&filter.operation)
+        .bind(filter.user_id)
+        .bind(filter.start)
+        .bind(end)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        rows.into_iter().map(audit_from_row).collect()
+    }
+
+    async fn report_list(&self, status: &str) -> Result<Vec<Report>> {
+        let rows = sqlx::query(
+            "SELECT id, reporter, user_id, name, reason, status, action, datetime, resolved_at FROM reports WHERE $1 = '' OR status = $1 ORDER BY id",
+        )
+        .bind(status)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        rows.into_iter().map(report_from_row).collect()
+    }
+
+    async fn report_get(&self, id: &i64) -> Result<Report> {
+        let row = sqlx::query(
+            "SELECT id, reporter, user_id, name, reason, status, action, datetime, resolved_at FROM reports WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        report_from_row(row)
+    }
+
+    async fn report_insert(&self, report: &Report) -> Result<i64> {
+        sqlx::query_scalar(
+            "INSERT INTO reports (reporter, user_id, name, reason, status, action, datetime) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id",
+        )
+        .bind(report.reporter.to_hex())
+        .bind(report.user_id)
+        .bind(&report.name)
+        .bind(&report.reason)
+        .bind(&report.status)
+        .bind(&report.action)
+        .bind(report.datetime)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))
+    }
+
+    async fn report_update(
+        &self,
+        id: &i64,
+        status: &str,
+        action: &str,
+        resolved_at: i64,
+    ) -> Result<()> {
+        sqlx::query("UPDATE reports SET status = $1, action = $2, resolved_at = $3 WHERE id = $4")
+            .bind(status)
+            .bind(action)
+            .bind(resolved_at)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        Ok(())
+    }
+
+    async fn version_list(&self, user_id: &i64) -> Result<Vec<Version>> {
+        let rows = sqlx::query(
+            "SELECT id, user_id, bio, avatar, datetime FROM versions WHERE user_id = $1 ORDER BY id DESC",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        rows.into_iter().map(version_from_row).collect()
+    }
+
+    async fn version_get(&self, id: &i64) -> Result<Version> {
+        let row =
+            sqlx::query("SELECT id, user_id, bio, avatar, datetime FROM versions WHERE id = $1")
+                .bind(id)
+                .fetch_one(&self.pool)
+                .await
+                .map_err(|_| anyhow!("database failure."))?;
+
+        version_from_row(row)
+    }
+
+    async fn version_bio(&self, id: &i64, bio: &str) -> Result<()> {
+        sqlx::query("UPDATE versions SET bio = $1 WHERE id = $2")
+            .bind(bio)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        Ok(())
+    }
+
+    async fn version_avatars(&self) -> Result<Vec<String>> {
+        sqlx::query_scalar("SELECT DISTINCT avatar FROM versions WHERE avatar != ''")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))
+    }
+
+    async fn attribute_list(&self, user_id: &i64) -> Result<Vec<Attribute>> {
+        let rows = sqlx::query(
+            "SELECT user_id, key, value, is_public, datetime FROM attributes WHERE user_id = $1 ORDER BY key",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        rows.into_iter().map(attribute_from_row).collect()
+    }
+
+    async fn attribute_set(
+        &self,
+        user_id: &i64,
+        attributes: &[Attribute],
+        audit: Option<&Audit>,
+    ) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        sqlx::query("DELETE FROM attributes WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        for attribute in attributes {
+            sqlx::query(
+                "INSERT INTO attributes (user_id, key, value, is_public, datetime) VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(user_id)
+            .bind(&attribute.key)
+            .bind(&attribute.value)
+            .bind(attribute.is_public)
+            .bind(attribute.datetime)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+        if let Some(audit) = audit {
+            insert_audit(&mut tx, audit, *user_id).await?;
+        }
+
+        tx.commit().await.map_err(|_| anyhow!("database failure."))
+    }
+
+    async fn did_get(&self, user_id: &i64) -> Result<Option<Did>> {
+        let row = sqlx::query(
+            "SELECT user_id, document, key_id, signature, sequence, datetime FROM dids WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        row.map(did_from_row).transpose()
+    }
+
+    async fn did_set(&self, did: &Did, audit: &Audit) -> Result<bool> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        let res = sqlx::query(
+            "INSERT INTO dids (user_id, document, key_id, signature, sequence, datetime) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (user_id) DO UPDATE SET document = EXCLUDED.document, key_id = EXCLUDED.key_id, signature = EXCLUDED.signature, sequence = EXCLUDED.sequence, datetime = EXCLUDED.datetime WHERE dids.sequence < EXCLUDED.sequence",
+        )
+        .bind(did.user_id)
+        .bind(&did.document)
+        .bind(&did.key_id)
+        .bind(&did.signature)
+        .bind(did.sequence)
+        .bind(did.datetime)
+        .execute(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        if res.rows_affected() == 0 {
+            return Ok(false);
+        }
+        insert_audit(&mut tx, audit, did.user_id).await?;
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(true)
+    }
+
+    async fn snapshot(&self) -> Result<Snapshot> {
+        // all queries see the same database state.
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        let rows = sqlx::query(
+            "SELECT id, name, pid, bio, avatar, is_actived, is_deleted, deleted_at, is_banned, datetime FROM users ORDER BY id",
+        )
+        .fetch_all(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+        let mut users = vec![];
+        for row in rows {
+            let is_banned: bool = row.try_get("is_banned")?;
+            users.push((user_from_row(row)?, is_banned));
+        }
+
+        let audits = sqlx::query(
+            "SELECT id, actor, operation, user_id, name, old_value, new_value, datetime FROM audits ORDER BY id",
+        )
+        .fetch_all(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?
+        .into_iter()
+        .map(audit_from_row)
+        .collect::<Result<Vec<Audit>>>()?;
+
+        let reports = sqlx::query(
+            "SELECT id, reporter, user_id, name, reason, status, action, datetime, resolved_at FROM reports ORDER BY id",
+        )
+        .fetch_all(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?
+        .into_iter()
+        .map(report_from_row)
+        .collect::<Result<Vec<Report>>>()?;
+
+        let versions =
+            sqlx::query("SELECT id, user_id, bio, avatar, datetime FROM versions ORDER BY id")
+                .fetch_all(&mut tx)
+                .await
+                .map_err(|_| anyhow!("database failure."))?
+                .into_iter()
+                .map(version_from_row)
+                .collect::<Result<Vec<Version>>>()?;
+
+        let attributes = sqlx::query(
+            "SELECT user_id, key, value, is_public, datetime FROM attributes ORDER BY user_id, key",
+        )
+        .fetch_all(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?
+        .into_iter()
+        .map(attribute_from_row)
+        .collect::<Result<Vec<Attribute>>>()?;
+
+        let dids = sqlx::query(
+            "SELECT user_id, document, key_id, signature, sequence, datetime FROM dids ORDER BY user_id",
+        )
+        .fetch_all(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?
+        .into_iter()
+        .map(did_from_row)
+        .collect::<Result<Vec<Did>>>()?;
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(Snapshot {
+            users,
+            audits,
+            reports,
+            versions,
+            attributes,
+            dids,
+        })
+    }
+
+    async fn restore(&self, snapshot: &Snapshot) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        // the only delete of audits, the triggers are back in the transaction.
+        for sql in [
+            "ALTER TABLE audits DISABLE TRIGGER USER",
+            "TRUNCATE users, audits, reports, versions, attributes, dids",
+            "ALTER TABLE audits ENABLE TRIGGER USER",
+        ] {
+            sqlx::query(sql)
+                .execute(&mut tx)
+                .await
+                .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        for (user, is_banned) in snapshot.users.iter() {
+            sqlx::query(
+                "INSERT INTO users (id, name, name_key, pid, bio, avatar, is_actived, is_deleted, deleted_at, is_banned, datetime) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+            )
+            .bind(user.id)
+            .bind(&user.name)
+            .bind(name_key(&user.name))
+            .bind(user.pid.to_hex())
+            .bind(&user.bio)
+            .bind(&user.avatar_hash)
+            .bind(user.is_actived)
+            .bind(user.is_deleted)
+            .bind(deleted_at(user))
+            .bind(is_banned)
+            .bind(user.datetime)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        for audit in snapshot.audits.iter() {
+            sqlx::query(
+                "INSERT INTO audits (id, actor, operation, user_id, name, old_value, new_value, datetime) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            )
+            .bind(audit.id)
+            .bind(&audit.actor)
+            .bind(&audit.operation)
+            .bind(audit.user_id)
+            .bind(&audit.name)
+            .bind(&audit.old_value)
+            .bind(&audit.new_value)
+            .bind(audit.datetime)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        for report in snapshot.reports.iter() {
+            sqlx::query(
+                "INSERT INTO reports (id, reporter, user_id, name, reason, status, action, datetime, resolved_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+            )
+            .bind(report.id)
+            .bind(report.reporter.to_hex())
+            .bind(report.user_id)
+            .bind(&report.name)
+            .bind(&report.reason)
+            .bind(&report.status)
+            .bind(&report.action)
+            .bind(report.datetime)
+            .bind(report.resolved_at)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        for version in snapshot.versions.iter() {
+            sqlx::query(
+                "INSERT INTO versions (id, user_id, bio, avatar, datetime) VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(version.id)
+            .bind(version.user_id)
+            .bind(&version.bio)
+            .bind(&version.avatar_hash)
+            .bind(version.datetime)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        for attribute in snapshot.attributes.iter() {
+            sqlx::query(
+                "INSERT INTO attributes (user_id, key, value, is_public, datetime) VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(attribute.user_id)
+            .bind(&attribute.key)
+            .bind(&attribute.value)
+            .bind(attribute.is_public)
+            .bind(attribute.datetime)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        for did in snapshot.dids.iter() {
+            sqlx::query(
+                "INSERT INTO dids (user_id, document, key_id, signature, sequence, datetime) VALUES ($1, $2, $3, $4, $5, $6)",
+            )
+            .bind(did.user_id)
+            .bind(&did.document)
+            .bind(&did.key_id)
+            .bind(&did.signature)
+            .bind(did.sequence)
+            .bind(did.datetime)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        // next ids continue after the restored rows.
+        for table in ["users", "audits", "reports", "versions"] {
+            sqlx::query(&format!(
+                "SELECT setval(pg_get_serial_sequence('{0}', 'id'), COALESCE((SELECT MAX(id) FROM {0}), 0) + 1, false)",
+                table
+            ))
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        tx.commit().await.map_err(|_| anyhow!("database failure."))
+    }
+
+    fn migrations(&self) -> Vec<Migration> {
+        embedded_migrations(&MIGRATOR)
+    }
+
+    async fn applied_migrations(&self) -> Result<Vec<(i64, bool)>> {
+        let exists: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        if !exists {
+            return Ok(vec![]);
+        }
+
+        sqlx::query_as("SELECT version, success FROM _sqlx_migrations ORDER BY version")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))
+    }
+
+    async fn migrate(&self) -> Result<()> {
+        MIGRATOR
+            .run(&self.pool)
+            .await
+            .map_err(|e| anyhow!("DB postgres migrate failure: {}", e))
+    }
+
+    async fn close(&self) {
+        self.pool.close().await
+    }
+}
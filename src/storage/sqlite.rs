@@ -0,0 +1,1038 @@
+use async_trait::async_trait;
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::{Row, Sqlite, Transaction};
+use std::path::PathBuf;
+use tdn::types::primitives::{PeerId, Result};
+
+use super::{
+    deleted_at, embedded_migrations, name_key, purge_audit, DatabaseConfig, Migration,
+    NameConflict, Snapshot, Store,
+};
+use crate::models::{now, Attribute, Audit, AuditFilter, Did, Report, User, Version};
+
+/// SQLite database file in the db_path.
+const SQLITE_FILE: &'static str = "domain.sqlite";
+
+/// append-only guard of audits, same as the migration.
+const AUDITS_NO_DELETE: &'static str = "CREATE TRIGGER IF NOT EXISTS audits_no_delete BEFORE DELETE ON audits BEGIN SELECT RAISE(ABORT, 'audits are append-only'); END";
+
+/// migrations embedded in the binary.
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/// Embedded SQLite storage.
+pub(crate) struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    /// open (create if missing) the database.
+    pub async fn open(base: &PathBuf, cfg: &DatabaseConfig) -> Result<Self> {
+        let mut path = base.clone();
+        path.push(SQLITE_FILE);
+
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .statement_cache_capacity(cfg.database_statement_cache);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(cfg.database_max_connections)
+            .connect_timeout(cfg.connect_timeout())
+            .idle_timeout(cfg.idle_timeout())
+            .connect_with(options)
+            .await
+            .map_err(|_| anyhow!("DB sqlite open failure!"))?;
+
+        Ok(Self { pool })
+    }
+}
+
+fn user_from_row(row: SqliteRow) -> Result<User> {
+    let pid: String = row.try_get("pid")?;
+    Ok(User {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        pid: PeerId::from_hex(&pid).unwrap_or(PeerId::default()),
+        bio: row.try_get("bio")?,
+        avatar: vec![],
+        avatar_hash: row.try_get("avatar")?,
+        is_actived: row.try_get("is_actived")?,
+        is_deleted: row.try_get("is_deleted")?,
+        deleted_at: row.try_get("deleted_at")?,
+        datetime: row.try_get("datetime")?,
+    })
+}
+
+fn audit_from_row(row: SqliteRow) -> Result<Audit> {
+    Ok(Audit {
+        id: row.try_get("id")?,
+        actor: row.try_get("actor")?,
+        operation: row.try_get("operation")?,
+        user_id: row.try_get("user_id")?,
+        name: row.try_get("name")?,
+        old_value: row.try_get("old_value")?,
+        new_value: row.try_get("new_value")?,
+        datetime: row.try_get("datetime")?,
+    })
+}
+
+fn report_from_row(row: SqliteRow) -> Result<Report> {
+    let reporter: String = row.try_get("reporter")?;
+    Ok(Report {
+        id: row.try_get("id")?,
+        reporter: PeerId::from_hex(&reporter).unwrap_or(PeerId::default()),
+        user_id: row.try_get("user_id")?,
+        name: row.try_get("name")?,
+        reason: row.try_get("reason")?,
+        status: row.try_get("status")?,
+        action: row.try_get("action")?,
+        datetime: row.try_get("datetime")?,
+        resolved_at: row.try_get("resolved_at")?,
+    })
+}
+
+fn version_from_row(row: SqliteRow) -> Result<Version> {
+    Ok(Version {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        bio: row.try_get("bio")?,
+        avatar_hash: row.try_get("avatar")?,
+        datetime: row.try_get("datetime")?,
+    })
+}
+
+fn attribute_from_row(row: SqliteRow) -> Result<Attribute> {
+    Ok(Attribute {
+        user_id: row.try_get("user_id")?,
+        key: row.try_get("key")?,
+        value: row.try_get("value")?,
+        is_public: row.try_get("is_public")?,
+        datetime: row.try_get("datetime")?,
+    })
+}
+
+fn did_from_row(row: SqliteRow) -> Result<Did> {
+    Ok(Did {
+        user_id: row.try_get("user_id")?,
+        document: row.try_get("document")?,
+        key_id: row.try_get("key_id")?,
+        signature: row.try_get("signature")?,
+        sequence: row.try_get("sequence")?,
+        datetime: row.try_get("datetime")?,
+    })
+}
+
+/// append the audit of the user in the transaction of the change.
+async fn insert_audit(tx: &mut Transaction<'_, Sqlite>, audit: &Audit, user_id: i64) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO audits (actor, operation, user_id, name, old_value, new_value, datetime) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&audit.actor)
+    .bind(&audit.operation)
+    .bind(user_id)
+    .bind(&audit.name)
+    .bind(&audit.old_value)
+    .bind(&audit.new_value)
+    .bind(audit.datetime)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| anyhow!("database failure."))?;
+
+    Ok(())
+}
+
+/// save the profile of the user as a new version, in the transaction of the change.
+async fn insert_version(
+    tx: &mut Transaction<'_, Sqlite>,
+    user_id: i64,
+    bio: &str,
+    avatar: &str,
+) -> Result<()> {
+    sqlx::query("INSERT INTO versions (user_id, bio, avatar, datetime) VALUES (?, ?, ?, ?)")
+        .bind(user_id)
+        .bind(bio)
+        .bind(avatar)
+        .bind(now())
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+    Ok(())
+}
+
+/// delete the versions of user except the newest `keep`, return their avatar hashes.
+async fn prune_versions(
+    tx: &mut Transaction<'_, Sqlite>,
+    user_id: i64,
+    keep: i64,
+) -> Result<Vec<String>> {
+    let rows = sqlx::query(
+        "SELECT id, avatar FROM versions WHERE user_id = ? ORDER BY id DESC LIMIT -1 OFFSET ?",
+    )
+    .bind(user_id)
+    .bind(keep)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| anyhow!("database failure."))?;
+
+    let mut avatars = vec![];
+    for row in rows {
+        let id: i64 = row.try_get("id")?;
+        avatars.push(row.try_get("avatar")?);
+        sqlx::query("DELETE FROM versions WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+    }
+    Ok(avatars)
+}
+
+#[async_trait]
+impl Store for SqliteStore {
+    async fn user_list(&self) -> Result<Vec<User>> {
+        let rows = sqlx::query(
+            "SELECT id, name, pid, bio, avatar, is_actived, is_deleted, deleted_at, datetime FROM users WHERE is_deleted = false ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        rows.into_iter().map(user_from_row).collect()
+    }
+
+    async fn user_list_page(&self, after: i64, limit: i64) -> Result<Vec<User>> {
+        let rows = sqlx::query(
+            "SELECT id, name, pid, bio, avatar, is_actived, is_deleted, deleted_at, datetime FROM users WHERE id > ? ORDER BY id LIMIT ?",
+        )
+        .bind(after)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        rows.into_iter().map(user_from_row).collect()
+    }
+
+    async fn user_search(&self, name: &str) -> Result<User> {
+        let row = sqlx::query(
+            "SELECT id, name, pid, bio, avatar, is_actived, is_deleted, deleted_at, datetime FROM users WHERE is_actived = true AND name_key = ?",
+        )
+        .bind(name_key(name))
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        user_from_row(row)
+    }
+
+    async fn user_get_by_name(&self, name: &str) -> Result<User> {
+        let row = sqlx::query(
+            "SELECT id, name, pid, bio, avatar, is_actived, is_deleted, deleted_at, datetime FROM users WHERE is_deleted = false AND name_key = ?",
+        )
+        .bind(name_key(name))
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        user_from_row(row)
+    }
+
+    async fn user_get(&self, id: &i64) -> Result<User> {
+        let row = sqlx::query(
+            "SELECT id, name, pid, bio, avatar, is_actived, is_deleted, deleted_at, datetime FROM users WHERE is_deleted = false AND id = ?",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        user_from_row(row)
+    }
+
+    async fn user_insert(&self, user: &User, audit: &Audit) -> Result<i64> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        // deleted names are reserved, the unique index guards concurrent inserts.
+        let res = sqlx::query(
+            "INSERT OR IGNORE INTO users (name, name_key, pid, bio, avatar, is_actived, datetime) SELECT ?, ?, ?, ?, ?, ?, ? WHERE NOT EXISTS (SELECT 1 FROM users WHERE name_key = ?)",
+        )
+        .bind(&user.name)
+        .bind(name_key(&user.name))
+        .bind(user.pid.to_hex())
+        .bind(&user.bio)
+        .bind(&user.avatar_hash)
+        .bind(user.is_actived)
+        .bind(user.datetime)
+        .bind(name_key(&user.name))
+        .execute(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        if res.rows_affected() == 0 {
+            return Err(NameConflict(user.name.clone()).into());
+        }
+        let id = res.last_insert_rowid();
+        insert_version(&mut tx, id, &user.bio, &user.avatar_hash).await?;
+        insert_audit(&mut tx, audit, id).await?;
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(id)
+    }
+
+    async fn user_import(&self, user: &User, audits: &[Audit]) -> Result<i64> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        // as insert, deleted names are reserved for the active users.
+        let res = sqlx::query(
+            "INSERT OR IGNORE INTO users (name, name_key, pid, bio, avatar, is_actived, is_deleted, deleted_at, datetime) SELECT ?, ?, ?, ?, ?, ?, ?, ?, ? WHERE ? OR NOT EXISTS (SELECT 1 FROM users WHERE name_key = ?)",
+        )
+        .bind(&user.name)
+        .bind(name_key(&user.name))
+        .bind(user.pid.to_hex())
+        .bind(&user.bio)
+        .bind(&user.avatar_hash)
+        .bind(user.is_actived)
+        .bind(user.is_deleted)
+        .bind(deleted_at(user))
+        .bind(user.datetime)
+        .bind(user.is_deleted)
+        .bind(name_key(&user.name))
+        .execute(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        if res.rows_affected() == 0 {
+            return Err(NameConflict(user.name.clone()).into());
+        }
+        let id = res.last_insert_rowid();
+        if !user.is_deleted {
+            insert_version(&mut tx, id, &user.bio, &user.avatar_hash).await?;
+        }
+        for audit in audits {
+            insert_audit(&mut tx, audit, id).await?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(id)
+    }
+
+    async fn user_profile(
+        &self,
+        id: &i64,
+        bio: &str,
+        avatar: &str,
+        keep: i64,
+        audit: &Audit,
+    ) -> Result<Vec<String>> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        sqlx::query("UPDATE users SET bio = ?, avatar = ? WHERE id = ?")
+            .bind(bio)
+            .bind(avatar)
+            .bind(id)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        insert_version(&mut tx, *id, bio, avatar).await?;
+        let avatars = prune_versions(&mut tx, *id, keep).await?;
+        insert_audit(&mut tx, audit, *id).await?;
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(avatars)
+    }
+
+    async fn user_update(&self, id: &i64, bio: &str) -> Result<()> {
+        sqlx::query("UPDATE users SET bio = ? WHERE id = ?")
+            .bind(bio)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        Ok(())
+    }
+
+    async fn user_avatar(&self, id: &i64, avatar: &str) -> Result<()> {
+        sqlx::query("UPDATE users SET avatar = ? WHERE id = ?")
+            .bind(avatar)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        Ok(())
+    }
+
+    async fn avatar_refs(&self, avatar: &str) -> Result<i64> {
+        let row = sqlx::query(
+            "SELECT (SELECT COUNT(*) FROM users WHERE avatar = ? AND is_deleted = false) + (SELECT COUNT(*) FROM versions WHERE avatar = ?) AS refs",
+        )
+        .bind(avatar)
+        .bind(avatar)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        Ok(row.try_get("refs")?)
+    }
+
+    async fn user_active(&self, id: &i64, active: bool, audit: &Audit) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        let res = sqlx::query("UPDATE users SET is_actived = ? WHERE id = ? AND is_banned = false")
+            .bind(active)
+            .bind(id)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        if res.rows_affected() == 0 {
+            return Err(anyhow!("user is banned."));
+        }
+        insert_audit(&mut tx, audit, *id).await?;
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(())
+    }
+
+    async fn user_ban(&self, id: &i64, ban: bool, audit: &Audit) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        sqlx::query("UPDATE users SET is_banned = ?, is_actived = ? WHERE id = ?")
+            .bind(ban)
+            .bind(!ban)
+            .bind(id)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        insert_audit(&mut tx, audit, *id).await?;
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(())
+    }
+
+    async fn user_banned(&self, id: &i64) -> Result<bool> {
+        let row = sqlx::query("SELECT is_banned FROM users WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        match row {
+            Some(row) => Ok(row.try_get("is_banned")?),
+            None => Ok(false),
+        }
+    }
+
+    async fn user_delete(&self, id: &i64, audit: &Audit) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        sqlx::query(
+            "UPDATE users SET is_actived = false, is_deleted = true, deleted_at = ? WHERE id = ?",
+        )
+        .bind(now())
+        .bind(id)
+        .execute(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+        insert_audit(&mut tx, audit, *id).await?;
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(())
+    }
+
+    async fn user_purge(&self, before: i64, actor: &str) -> Result<(Vec<User>, Vec<String>)> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        let rows = sqlx::query(
+            "SELECT id, name, pid, bio, avatar, is_actived, is_deleted, deleted_at, datetime FROM users WHERE is_deleted = true AND deleted_at < ? ORDER BY id",
+        )
+        .bind(before)
+        .fetch_all(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+        let users = rows
+            .into_iter()
+            .map(user_from_row)
+            .collect::<Result<Vec<User>>>()?;
+
+        let mut avatars = vec![];
+        for user in users.iter() {
+            let rows = sqlx::query("SELECT avatar FROM versions WHERE user_id = ?")
+                .bind(user.id)
+                .fetch_all(&mut tx)
+                .await
+                .map_err(|_| anyhow!("database failure."))?;
+            for row in rows {
+                avatars.push(row.try_get("avatar")?);
+            }
+            for sql in [
+                "DELETE FROM versions WHERE user_id = ?",
+                "DELETE FROM attributes WHERE user_id = ?",
+                "DELETE FROM dids WHERE user_id = ?",
+                "DELETE FROM users WHERE id = ?",
+            ] {
+                sqlx::query(sql)
+                    .bind(user.id)
+                    .execute(&mut tx)
+                    .await
+                    .map_err(|_| anyhow!("database failure."))?;
+            }
+            insert_audit(&mut tx, &purge_audit(user, actor), user.id).await?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok((users, avatars))
+    }
+
+    async fn user_unkeyed(&self) -> Result<Vec<(i64, String, bool)>> {
+        let rows = sqlx::query("SELECT id, name, is_deleted FROM users WHERE name_key = ''")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        let mut users = vec![];
+        for row in rows {
+            users.push((
+                row.try_get("id")?,
+                row.try_get("name")?,
+                row.try_get("is_deleted")?,
+            ));
+        }
+        Ok(users)
+    }
+
+    async fn user_set_keys(&self, keys: &[(i64, String)]) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        for (id, key) in keys {
+            sqlx::query("UPDATE users SET name_key = ? WHERE id = ?")
+                .bind(key)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .map_err(|_| anyhow!("database failure."))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(())
+    }
+
+    async fn audit_list(&self, filter: &AuditFilter) -> Result<Vec<Audit>> {
+        let end = if filter.end > 0 { filter.end } else { i64::MAX };
+        let limit = if filter.limit > 0 {
+            filter.limit
+        } else {
+            i64::MAX
+        };
+
+        let rows = sqlx::query(
+            "SELECT id, actor, operation, user_id, name, old_value, new_value, datetime FROM audits WHERE (? IS NULL OR actor = ?) AND (? IS NULL OR operation = ?) AND (? IS NULL OR user_id = ?) AND datetime >= ? AND datetime <= ? ORDER BY id DESC LIMIT ?",
+        )
+        .bind(&filter.actor)
+        .bind(&filter.actor)
+        .bind(&filter.operation)
+        .bind(&filter.operation)
+        .bind(filter.user_id)
+        .bind(filter.user_id)
+        .bind(filter.start)
+        .bind(end)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        rows.into_iter().map(audit_from_row).collect()
+    }
+
+    async fn report_list(&self, status: &str) -> Result<Vec<Report>> {
+        let rows = sqlx::query(
+            "SELECT id, reporter, user_id, name, reason, status, action, datetime, resolved_at FROM reports WHERE ? = '' OR status = ? ORDER BY id",
+        )
+        .bind(status)
+        .bind(status)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        rows.into_iter().map(report_from_row).collect()
+    }
+
+    async fn report_get(&self, id: &i64) -> Result<Report> {
+        let row = sqlx::query(
+            "SELECT id, reporter, user_id, name, reason, status, action, datetime, resolved_at FROM reports WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        report_from_row(row)
+    }
+
+    async fn report_insert(&self, report: &Report) -> Result<i64> {
+        let res = sqlx::query(
+            "INSERT INTO reports (reporter, user_id, name, reason, status, action, datetime) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(report.reporter.to_hex())
+        .bind(report.user_id)
+        .bind(&report.name)
+        .bind(&report.reason)
+        .bind(&report.status)
+        .bind(&report.action)
+        .bind(report.datetime)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        Ok(res.last_insert_rowid())
+    }
+
+    async fn report_update(
+        &self,
+        id: &i64,
+        status: &str,
+        action: &str,
+        resolved_at: i64,
+    ) -> Result<()> {
+        sqlx::query("UPDATE reports SET status = ?, action = ?, resolved_at = ? WHERE id = ?")
+            .bind(status)
+            .bind(action)
+            .bind(resolved_at)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        Ok(())
+    }
+
+    async fn version_list(&self, user_id: &i64) -> Result<Vec<Version>> {
+        let rows = sqlx::query(
+            "SELECT id, user_id, bio, avatar, datetime FROM versions WHERE user_id = ? ORDER BY id DESC",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        rows.into_iter().map(version_from_row).collect()
+    }
+
+    async fn version_get(&self, id: &i64) -> Result<Version> {
+        let row =
+            sqlx::query("SELECT id, user_id, bio, avatar, datetime FROM versions WHERE id = ?")
+                .bind(id)
+                .fetch_one(&self.pool)
+                .await
+                .map_err(|_| anyhow!("database failure."))?;
+
+        version_from_row(row)
+    }
+
+    async fn version_bio(&self, id: &i64, bio: &str) -> Result<()> {
+        sqlx::query("UPDATE versions SET bio = ? WHERE id = ?")
+            .bind(bio)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        Ok(())
+    }
+
+    async fn version_avatars(&self) -> Result<Vec<String>> {
+        let rows = sqlx::query("SELECT DISTINCT avatar FROM versions WHERE avatar != ''")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        rows.into_iter()
+            .map(|row| Ok(row.try_get("avatar")?))
+            .collect()
+    }
+
+    async fn attribute_list(&self, user_id: &i64) -> Result<Vec<Attribute>> {
+        let rows = sqlx::query(
+            "SELECT user_id, key, value, is_public, datetime FROM attributes WHERE user_id = ? ORDER BY key",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        rows.into_iter().map(attribute_from_row).collect()
+    }
+
+    async fn attribute_set(
+        &self,
+        user_id: &i64,
+        attributes: &[Attribute],
+        audit: Option<&Audit>,
+    ) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        sqlx::query("DELETE FROM attributes WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        for attribute in attributes {
+            sqlx::query(
+                "INSERT INTO attributes (user_id, key, value, is_public, datetime) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(user_id)
+            .bind(&attribute.key)
+            .bind(&attribute.value)
+            .bind(attribute.is_public)
+            .bind(attribute.datetime)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+        if let Some(audit) = audit {
+            insert_audit(&mut tx, audit, *user_id).await?;
+        }
+
+        tx.commit().await.map_err(|_| anyhow!("database failure."))
+    }
+
+    async fn did_get(&self, user_id: &i64) -> Result<Option<Did>> {
+        let row = sqlx::query(
+            "SELECT user_id, document, key_id, signature, sequence, datetime FROM dids WHERE user_id = ?",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        row.map(did_from_row).transpose()
+    }
+
+    async fn did_set(&self, did: &Did, audit: &Audit) -> Result<bool> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        let res = sqlx::query(
+            "INSERT INTO dids (user_id, document, key_id, signature, sequence, datetime) VALUES (?, ?, ?, ?, ?, ?) ON CONFLICT (user_id) DO UPDATE SET document = excluded.document, key_id = excluded.key_id, signature = excluded.signature, sequence = excluded.sequence, datetime = excluded.datetime WHERE dids.sequence < excluded.sequence",
+        )
+        .bind(did.user_id)
+        .bind(&did.document)
+        .bind(&did.key_id)
+        .bind(&did.signature)
+        .bind(did.sequence)
+        .bind(did.datetime)
+        .execute(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+
+        if res.rows_affected() == 0 {
+            return Ok(false);
+        }
+        insert_audit(&mut tx, audit, did.user_id).await?;
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(true)
+    }
+
+    async fn snapshot(&self) -> Result<Snapshot> {
+        // a read transaction sees one consistent database.
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        let rows = sqlx::query(
+            "SELECT id, name, pid, bio, avatar, is_actived, is_deleted, deleted_at, is_banned, datetime FROM users ORDER BY id",
+        )
+        .fetch_all(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+        let mut users = vec![];
+        for row in rows {
+            let is_banned: bool = row.try_get("is_banned")?;
+            users.push((user_from_row(row)?, is_banned));
+        }
+
+        let rows = sqlx::query(
+            "SELECT id, actor, operation, user_id, name, old_value, new_value, datetime FROM audits ORDER BY id",
+        )
+        .fetch_all(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+        let audits = rows
+            .into_iter()
+            .map(audit_from_row)
+            .collect::<Result<Vec<_>>>()?;
+
+        let rows = sqlx::query(
+            "SELECT id, reporter, user_id, name, reason, status, action, datetime, resolved_at FROM reports ORDER BY id",
+        )
+        .fetch_all(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+        let reports = rows
+            .into_iter()
+            .map(report_from_row)
+            .collect::<Result<Vec<_>>>()?;
+
+        let rows =
+            sqlx::query("SELECT id, user_id, bio, avatar, datetime FROM versions ORDER BY id")
+                .fetch_all(&mut tx)
+                .await
+                .map_err(|_| anyhow!("database failure."))?;
+        let versions = rows
+            .into_iter()
+            .map(version_from_row)
+            .collect::<Result<Vec<_>>>()?;
+
+        let rows = sqlx::query(
+            "SELECT user_id, key, value, is_public, datetime FROM attributes ORDER BY user_id, key",
+        )
+        .fetch_all(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+        let attributes = rows
+            .into_iter()
+            .map(attribute_from_row)
+            .collect::<Result<Vec<_>>>()?;
+
+        let rows = sqlx::query(
+            "SELECT user_id, document, key_id, signature, sequence, datetime FROM dids ORDER BY user_id",
+        )
+        .fetch_all(&mut tx)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+        let dids = rows
+            .into_iter()
+            .map(did_from_row)
+            .collect::<Result<Vec<_>>>()?;
+
+        tx.commit()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        Ok(Snapshot {
+            users,
+            audits,
+            reports,
+            versions,
+            attributes,
+            dids,
+        })
+    }
+
+    async fn restore(&self, snapshot: &Snapshot) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+
+        // the only delete of audits, the trigger is back in the transaction.
+        for sql in [
+            "DROP TRIGGER IF EXISTS audits_no_delete",
+            "DELETE FROM users",
+            "DELETE FROM audits",
+            "DELETE FROM reports",
+            "DELETE FROM versions",
+            "DELETE FROM attributes",
+            "DELETE FROM dids",
+            "DELETE FROM sqlite_sequence WHERE name IN ('users', 'audits', 'reports', 'versions')",
+            AUDITS_NO_DELETE,
+        ] {
+            sqlx::query(sql)
+                .execute(&mut tx)
+                .await
+                .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        for (user, is_banned) in snapshot.users.iter() {
+            sqlx::query(
+                "INSERT INTO users (id, name, name_key, pid, bio, avatar, is_actived, is_deleted, deleted_at, is_banned, datetime) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(user.id)
+            .bind(&user.name)
+            .bind(name_key(&user.name))
+            .bind(user.pid.to_hex())
+            .bind(&user.bio)
+            .bind(&user.avatar_hash)
+            .bind(user.is_actived)
+            .bind(user.is_deleted)
+            .bind(deleted_at(user))
+            .bind(is_banned)
+            .bind(user.datetime)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        for audit in snapshot.audits.iter() {
+            sqlx::query(
+                "INSERT INTO audits (id, actor, operation, user_id, name, old_value, new_value, datetime) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(audit.id)
+            .bind(&audit.actor)
+            .bind(&audit.operation)
+            .bind(audit.user_id)
+            .bind(&audit.name)
+            .bind(&audit.old_value)
+            .bind(&audit.new_value)
+            .bind(audit.datetime)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        for report in snapshot.reports.iter() {
+            sqlx::query(
+                "INSERT INTO reports (id, reporter, user_id, name, reason, status, action, datetime, resolved_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(report.id)
+            .bind(report.reporter.to_hex())
+            .bind(report.user_id)
+            .bind(&report.name)
+            .bind(&report.reason)
+            .bind(&report.status)
+            .bind(&report.action)
+            .bind(report.datetime)
+            .bind(report.resolved_at)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        for version in snapshot.versions.iter() {
+            sqlx::query(
+                "INSERT INTO versions (id, user_id, bio, avatar, datetime) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(version.id)
+            .bind(version.user_id)
+            .bind(&version.bio)
+            .bind(&version.avatar_hash)
+            .bind(version.datetime)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        for attribute in snapshot.attributes.iter() {
+            sqlx::query(
+                "INSERT INTO attributes (user_id, key, value, is_public, datetime) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(attribute.user_id)
+            .bind(&attribute.key)
+            .bind(&attribute.value)
+            .bind(attribute.is_public)
+            .bind(attribute.datetime)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        for did in snapshot.dids.iter() {
+            sqlx::query(
+                "INSERT INTO dids (user_id, document, key_id, signature, sequence, datetime) VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(did.user_id)
+            .bind(&did.document)
+            .bind(&did.key_id)
+            .bind(&did.signature)
+            .bind(did.sequence)
+            .bind(did.datetime)
+            .execute(&mut tx)
+            .await
+            .map_err(|_| anyhow!("database failure."))?;
+        }
+
+        tx.commit().await.map_err(|_| anyhow!("database failure."))
+    }
+
+    fn migrations(&self) -> Vec<Migration> {
+        embedded_migrations(&MIGRATOR)
+    }
+
+    async fn applied_migrations(&self) -> Result<Vec<(i64, bool)>> {
+        let exists: Option<String> = sqlx::query_scalar(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'",
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| anyhow!("database failure."))?;
+        if exists.is_none() {
+            return Ok(vec![]);
+        }
+
+        sqlx::query_as("SELECT version, success FROM _sqlx_migrations ORDER BY version")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|_| anyhow!("database failure."))
+    }
+
+    async fn migrate(&self) -> Result<()> {
+        MIGRATOR
+            .run(&self.pool)
+            .await
+            .map_err(|e| anyhow!("DB sqlite migrate failure: {}", e))
+    }
+
+    async fn close(&self) {
+        self.pool.close().await
+    }
+}
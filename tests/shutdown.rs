@@ -0,0 +1,79 @@
+//! the domain stops gracefully on SIGTERM.
+#![cfg(unix)]
+
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn sigterm_stops_gracefully() {
+    let dir = std::env::temp_dir().join(format!("domain-shutdown-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_domain"))
+        .args([
+            "run",
+            "--p2p-addr",
+            "127.0.0.1:0",
+            "--rpc-addr",
+            "127.0.0.1:0",
+        ])
+        .args(["--bootstrap", ""])
+        .arg("--data-dir")
+        .arg(&dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("spawn domain");
+
+    let (tx, rx) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().flatten() {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    // logs are in the terminal of debug build, or the log file of release.
+    let log_file = dir.join("domain.log.txt");
+    let mut output = String::new();
+    let mut wait_line = |needle: &str, timeout: Duration| {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            while let Ok(line) = rx.try_recv() {
+                output.push_str(&line);
+                output.push('\n');
+            }
+            let logs = std::fs::read_to_string(&log_file).unwrap_or_default();
+            if output.contains(needle) || logs.contains(needle) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        false
+    };
+
+    let started = wait_line("Network Peer id", Duration::from_secs(30));
+    if !started {
+        let _ = child.kill();
+    }
+    assert!(started, "domain not started");
+
+    let killed = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .expect("send SIGTERM");
+    assert!(killed.success());
+
+    assert!(
+        wait_line("Domain stopped", Duration::from_secs(40)),
+        "domain not stopped"
+    );
+    let status = child.wait().expect("wait domain");
+    assert!(status.success(), "exit with {:?}", status);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}